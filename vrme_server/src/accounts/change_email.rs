@@ -0,0 +1,240 @@
+//! Email address changes.
+//!
+//! A logged-in user asks to change their email address at `POST /account/email` by providing
+//! their current password and the new email address. A single-use confirmation token is emailed to
+//! the new address, and the email address is only swapped once that token is submitted at
+//! `POST /account/email/confirm`, which proves that the user owns the new address. A notice is then
+//! sent to the old address.
+
+use crate::accounts::password::get_password_hash;
+use crate::accounts::register::validate_email;
+use crate::auth::auth_payload::AuthPayload;
+use crate::auth::one_time_tokens::{consume_token, issue_token, TokenPurpose};
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::mail::{Email, MailSender};
+use crate::service_errors::ServiceError;
+use crate::settings::Settings;
+use crate::types::client_hashed_password::ClientHashedPassword;
+use actix_web::{web, Error, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::Duration;
+use deadpool_postgres::Client;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Validity duration of email change confirmation tokens in hours.
+pub const EMAIL_CHANGE_VALIDITY: i64 = 24;
+
+/// Required payload to change the email address.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangeEmailRequest {
+	/// base64-encoded client-side-hashed current password.
+	pub current_hashed_password: String,
+	pub new_email: String,
+}
+
+/// Handler for requesting an email address change at `POST /account/email`. A confirmation token
+/// valid for `EMAIL_CHANGE_VALIDITY` hours is emailed to the new address; confirmation tokens sent
+/// before are revoked.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the new email address is invalid or is the current one.
+/// - `403 Forbidden`: the current password is incorrect.
+/// - `409 Conflict`: an account with the new email address already exists.
+pub async fn handle_change_email(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	mail_sender: web::Data<MailSender>,
+	request: web::Json<ChangeEmailRequest>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
+
+	validate_email(&request.new_email)?;
+	let current_hash = ClientHashedPassword::new(&request.current_hashed_password)?
+		.decode()
+		.await?;
+
+	let client = pool.get().await?;
+
+	let previous_hash = get_password_hash(&client, &auth_payload.uuid)
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such account found".to_string()))?;
+
+	if !previous_hash.verify(&current_hash).await? {
+		return Err(
+			ServiceError::Forbidden("The current password is incorrect".to_string()).into(),
+		);
+	}
+
+	if let Some(user_id) = find_account(&client, &request.new_email).await? {
+		return Err(if user_id == auth_payload.uuid {
+			ServiceError::BadRequest("`new_email` is the current email address".to_string())
+		} else {
+			email_taken(&request.new_email)
+		}
+		.into());
+	}
+
+	let token = issue_token(
+		&client,
+		&settings.auth,
+		&auth_payload.uuid,
+		TokenPurpose::EmailChange,
+		Some(&request.new_email),
+		Duration::hours(EMAIL_CHANGE_VALIDITY),
+	)
+	.await?;
+
+	mail_sender
+		.send(Email {
+			to: request.new_email.clone(),
+			subject: "Confirm your new VRME email address".to_string(),
+			body: format!(
+				"To use this email address for your VRME account, enter the following code in the \
+				 VRME app:\n\n\
+				 {}\n\n\
+				 The code is valid for {} hours. If you did not ask to change the email address \
+				 of your VRME account, you can ignore this email.\n",
+				token, EMAIL_CHANGE_VALIDITY
+			),
+		})
+		.await?;
+
+	info!(
+		"Sent email change confirmation email to user `{}`",
+		&auth_payload.uuid
+	);
+
+	Ok(HttpResponse::Accepted().finish())
+}
+
+/// Required payload to confirm an email address change.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConfirmEmailChangeRequest {
+	/// Confirmation token from the email.
+	pub token: String,
+}
+
+/// Handler for confirming an email address change at `POST /account/email/confirm`. The new email
+/// address is verified by the same token.
+///
+/// ## Success Response
+///
+/// ```json
+/// {
+///     "message": "Email address successfully changed",
+///     "data": {
+///         "user_id": "123e4567-e89b-12d3-a456-426655440000",
+///         "email": "new@example.com"
+///     }
+/// }
+/// ```
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the token is invalid, was already used or has expired.
+/// - `409 Conflict`: an account with the new email address was created in the meantime. The token
+///   is used up regardless.
+pub async fn handle_confirm_email_change(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	mail_sender: web::Data<MailSender>,
+	request: web::Json<ConfirmEmailChangeRequest>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	let invalid_token =
+		|| ServiceError::BadRequest("Invalid or expired confirmation token".to_string());
+
+	let consumed = consume_token(
+		&client,
+		&settings.auth,
+		&request.token,
+		TokenPurpose::EmailChange,
+	)
+	.await?
+	.ok_or_else(invalid_token)?;
+	let new_email = consumed.payload.ok_or_else(invalid_token)?;
+
+	let old_email = set_email(&client, &consumed.user_id, &new_email).await?;
+
+	info!("Changed email address of user `{}`", &consumed.user_id);
+
+	if let Err(e) = mail_sender
+		.send(Email {
+			to: old_email,
+			subject: "Your VRME email address was changed".to_string(),
+			body: format!(
+				"The email address of your VRME account was changed to {}. You will no longer \
+				 receive emails about your account at this address.\n\n\
+				 If you did not change it, reset your password right away.\n",
+				new_email
+			),
+		})
+		.await
+	{
+		error!("Failed to send email change notice: {}", e);
+	}
+
+	Ok(HttpResponse::Ok().json(json!({
+		"message": "Email address successfully changed",
+		"data": {
+			"user_id": consumed.user_id,
+			"email": new_email
+		}
+	})))
+}
+
+fn email_taken(email: &str) -> ServiceError {
+	ServiceError::Conflict(format!(
+		"An account with the given email address {} already exists",
+		email
+	))
+}
+
+const FIND_ACCOUNT_QUERY: &str = r#"
+    SELECT user_id
+    FROM accounts
+    WHERE
+        email = $1::VARCHAR(355)
+    ;
+"#;
+
+async fn find_account(client: &Client, email: &str) -> Result<Option<Uuid>, ServiceError> {
+	let statement = client.prepare(FIND_ACCOUNT_QUERY).await?;
+	let row = client.query_opt(&statement, &[&email]).await?;
+	Ok(row.map(|row| row.get(0)))
+}
+
+// The CTE reads the accounts table as it was before the update, i.e. the old email address.
+const SET_EMAIL_QUERY: &str = r#"
+    WITH previous AS (
+        SELECT email
+        FROM accounts
+        WHERE
+            user_id = $1::UUID
+    )
+    UPDATE accounts
+    SET
+        email = $2::VARCHAR(355),
+        email_verified = TRUE
+    WHERE
+        user_id = $1::UUID AND
+        NOT EXISTS (SELECT 1 FROM accounts WHERE email = $2::VARCHAR(355))
+    RETURNING (SELECT email FROM previous)
+    ;
+"#;
+
+/// Replace the email address of the user, returning the old one.
+async fn set_email(client: &Client, user_id: &Uuid, email: &str) -> Result<String, ServiceError> {
+	let statement = client.prepare(SET_EMAIL_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[user_id, &email])
+		.await?
+		.ok_or_else(|| email_taken(email))?;
+	Ok(row.get(0))
+}
@@ -1,54 +1,461 @@
-//! Deletes user account.
+//! Account deletion.
+//!
+//! Purging an account removes everything the server holds about the user in a single transaction:
+//! the account, its auth sessions, one-time tokens and data export, the meeting sessions the user
+//! presents together with their presentations, and the user's place in other meeting sessions.
+//! Blobs, i.e. the avatar, the data export archive and presentation files, are removed afterwards
+//! through deletion jobs (see `crate::storage::deletion_jobs`).
+//!
+//! If `accounts.deletion_grace_period` is set, `DELETE /account` only schedules the deletion: the
+//! user is logged out of every device, the live meeting sessions they present are ended and they
+//! leave their other meeting sessions, and the account is hidden and cannot be logged into. Their
+//! meeting sessions and files are kept, so that until the grace period is over and
+//! `purge_deleted_accounts` purges the account, the user can restore it at `POST /account/restore`.
 
-use crate::auth::auth_payload::AuthPayload;
+use crate::accounts::export::lock_export_key;
+use crate::auth::roles::Identity;
+use crate::avatars::avatar_key;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::leave::publish;
+use crate::presentations::pages::presentation_keys;
 use crate::service_errors::ServiceError;
+use crate::settings::{AccountsSettings, Settings};
+use crate::storage::deletion_jobs::enqueue_blob_deletions;
+use crate::types::client_hashed_password::ClientHashedPassword;
+use crate::types::hashed_password::HashedPassword;
+use actix::Addr;
 use actix_web::web;
 use actix_web::{Error, HttpResponse};
-use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::{Duration, NaiveDateTime, Utc};
 use deadpool_postgres::Client;
-use futures::future;
-use tokio_postgres::{Error as TPError, Statement};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::Transaction;
 use uuid::Uuid;
 
-/// Handler for deleting a user account. This is a destructive operation and the user account cannot
-/// be recovered.
+/// How many accounts `purge_deleted_accounts` purges at once.
+pub const PURGE_BATCH_SIZE: i64 = 100;
+
+/// Handler for deleting the account of the user at `DELETE /account`. Without a grace period, this
+/// is a destructive operation and the user account cannot be recovered.
 pub async fn handle_delete_account(
 	pool: web::Data<PersistentConnectionPool>,
-	auth: BearerAuth,
+	settings: web::Data<Settings>,
+	hub: web::Data<Addr<MeetingHub>>,
+	identity: Identity,
 ) -> Result<HttpResponse, Error> {
-	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
-	let client = pool.get().await?;
-	delete_user_account(&client, &auth_payload.uuid).await?;
+	let mut client = pool.get().await?;
+	let user_id = identity.user_id;
+
+	let changes = match settings.accounts.deletion_grace_period {
+		Some(_) => schedule_account_deletion(&mut client, &user_id).await?,
+		None => purge_account(&mut client, &user_id, None)
+			.await?
+			.ok_or_else(|| ServiceError::NotFound("No such account found".to_string()))?,
+	};
+	changes.publish(&hub);
+
+	info!("Deleted account of user `{}`", &user_id);
+
 	Ok(HttpResponse::NoContent().finish())
 }
 
+/// Required payload to restore an account scheduled for deletion.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RestoreAccountRequest {
+	pub email: String,
+	/// base64-encoded client-side-hashed password.
+	pub hashed_password: String,
+}
+
+/// Handler for restoring an account scheduled for deletion at `POST /account/restore`. The user
+/// has to login again afterwards.
+///
+/// ## Success Response
+///
+/// ```json
+/// {
+///     "message": "Account successfully restored",
+///     "data": {
+///         "user_id": "123e4567-e89b-12d3-a456-426655440000"
+///     }
+/// }
+/// ```
+///
+/// ## Errors
+///
+/// - `401 Unauthorized`: no account scheduled for deletion matches the email and password.
+/// - `404 Not Found`: the account was purged or restored in the meantime.
+pub async fn handle_restore_account(
+	pool: web::Data<PersistentConnectionPool>,
+	request: web::Json<RestoreAccountRequest>,
+) -> Result<HttpResponse, Error> {
+	let client_hash = ClientHashedPassword::new(&request.hashed_password)?
+		.decode()
+		.await?;
+
+	let client = pool.get().await?;
+
+	let invalid_credentials = || {
+		ServiceError::Unauthorized(
+			"No account scheduled for deletion matches the email and password".to_string(),
+		)
+	};
+
+	let (user_id, password_hash) = find_deleted_account(&client, &request.email)
+		.await?
+		.ok_or_else(invalid_credentials)?;
+
+	if !password_hash.verify(&client_hash).await? {
+		return Err(invalid_credentials().into());
+	}
+
+	if !restore_account(&client, &user_id).await? {
+		return Err(ServiceError::NotFound(
+			"The account is no longer scheduled for deletion".to_string(),
+		)
+		.into());
+	}
+
+	info!("Restored account of user `{}`", &user_id);
+
+	Ok(HttpResponse::Ok().json(json!({
+		"message": "Account successfully restored",
+		"data": {
+			"user_id": user_id
+		}
+	})))
+}
+
+/// Purge the accounts whose deletion grace period is over. Returns how many accounts were purged.
+pub async fn purge_deleted_accounts(
+	pool: &PersistentConnectionPool,
+	accounts_settings: &AccountsSettings,
+	hub: &Addr<MeetingHub>,
+) -> Result<usize, ServiceError> {
+	let mut client = pool.get().await?;
+
+	// Accounts scheduled for deletion before the grace period was disabled are purged right away.
+	let grace_period = accounts_settings.deletion_grace_period.unwrap_or(0);
+	let deleted_before = Utc::now().naive_utc() - Duration::hours(grace_period as i64);
+
+	let statement = client.prepare(FIND_PURGEABLE_ACCOUNTS_QUERY).await?;
+	let rows = client
+		.query(&statement, &[&deleted_before, &PURGE_BATCH_SIZE])
+		.await?;
+
+	let mut purged = 0;
+
+	for row in &rows {
+		let user_id: Uuid = row.get(0);
+
+		// The user may have restored the account since it was found.
+		if let Some(changes) = purge_account(&mut client, &user_id, Some(&deleted_before)).await? {
+			changes.publish(hub);
+			purged += 1;
+			info!("Purged account of user `{}`", &user_id);
+		}
+	}
+
+	Ok(purged)
+}
+
+/// Meeting sessions affected by removing a user from their meeting sessions.
+#[derive(Debug)]
+struct MeetingChanges {
+	user_id: Uuid,
+	/// Meeting sessions the user presented, which were ended or deleted.
+	ended: Vec<Uuid>,
+	/// Meeting sessions the user listened to, which they left.
+	left: Vec<Uuid>,
+}
+
+impl MeetingChanges {
+	/// Notify the clients of the affected meeting sessions.
+	fn publish(&self, hub: &Addr<MeetingHub>) {
+		for meeting_id in &self.ended {
+			publish(hub, meeting_id, MeetingEvent::SessionEnded);
+		}
+		for meeting_id in &self.left {
+			publish(
+				hub,
+				meeting_id,
+				MeetingEvent::ParticipantLeft {
+					user_id: self.user_id,
+				},
+			);
+		}
+	}
+}
+
+/// Purge the account. With `deleted_before`, only an account scheduled for deletion by then is
+/// purged. Returns `None`, leaving everything untouched, if no such account exists.
+async fn purge_account(
+	client: &mut Client,
+	user_id: &Uuid,
+	deleted_before: Option<&NaiveDateTime>,
+) -> Result<Option<MeetingChanges>, ServiceError> {
+	let transaction = client.transaction().await?;
+
+	let changes = remove_from_meetings(&transaction, user_id).await?;
+	let mut keys = vec![avatar_key(user_id)];
+	keys.extend(lock_export_key(&transaction, user_id).await?);
+	enqueue_blob_deletions(&transaction, &keys).await?;
+
+	// Auth sessions, one-time tokens and the data export are deleted by foreign key cascades.
+	let deleted = match deleted_before {
+		Some(deleted_before) => {
+			let statement = transaction.prepare(DELETE_SCHEDULED_ACCOUNT_QUERY).await?;
+			transaction
+				.execute(&statement, &[user_id, deleted_before])
+				.await?
+		}
+		None => {
+			let statement = transaction.prepare(DELETE_ACCOUNT_QUERY).await?;
+			transaction.execute(&statement, &[user_id]).await?
+		}
+	};
+
+	// Dropping the transaction rolls it back.
+	if deleted == 0 {
+		return Ok(None);
+	}
+
+	transaction.commit().await?;
+	Ok(Some(changes))
+}
+
+async fn schedule_account_deletion(
+	client: &mut Client,
+	user_id: &Uuid,
+) -> Result<MeetingChanges, ServiceError> {
+	let now = Utc::now().naive_utc();
+	let transaction = client.transaction().await?;
+
+	let changes = end_live_meetings(&transaction, user_id).await?;
+
+	let statement = transaction.prepare(SCHEDULE_DELETION_QUERY).await?;
+	transaction.execute(&statement, &[user_id, &now]).await?;
+
+	let statement = transaction.prepare(DELETE_AUTH_SESSIONS_QUERY).await?;
+	transaction.execute(&statement, &[user_id]).await?;
+
+	transaction.commit().await?;
+	Ok(changes)
+}
+
+const GET_PRESENTED_MEETINGS_QUERY: &str = r#"
+    SELECT
+        meeting_sessions.meeting_id,
+        presentations.upload_id,
+        presentations.page_count
+    FROM meeting_sessions
+    LEFT JOIN presentations
+        ON presentations.meeting_id = meeting_sessions.meeting_id
+    WHERE
+        meeting_sessions.presenter = $1::UUID
+    ;
+"#;
+
+// Presentations are deleted by the foreign key cascade.
+const DELETE_PRESENTED_MEETINGS_QUERY: &str = r#"
+    DELETE FROM meeting_sessions
+    WHERE
+        presenter = $1::UUID
+    ;
+"#;
+
+const LEAVE_MEETINGS_QUERY: &str = r#"
+    UPDATE meeting_participants
+    SET
+        left_at = $2::TIMESTAMP
+    WHERE
+        user_id = $1::UUID AND
+        left_at IS NULL
+    RETURNING
+        meeting_id
+    ;
+"#;
+
+/// Delete the meeting sessions the user presents, removing their presentation files, and remove
+/// the user from the meeting sessions they listen to.
+async fn remove_from_meetings(
+	transaction: &Transaction<'_>,
+	user_id: &Uuid,
+) -> Result<MeetingChanges, ServiceError> {
+	let statement = transaction.prepare(GET_PRESENTED_MEETINGS_QUERY).await?;
+	let presented = transaction.query(&statement, &[user_id]).await?;
+
+	let mut ended = Vec::new();
+	let mut keys = Vec::new();
+
+	for row in &presented {
+		let meeting_id: Uuid = row.get(0);
+		let upload_id: Option<Uuid> = row.get(1);
+
+		if let Some(page_count) = row.get::<_, Option<i32>>(2) {
+			keys.extend(presentation_keys(&meeting_id, upload_id, page_count));
+		}
+		ended.push(meeting_id);
+	}
+
+	enqueue_blob_deletions(transaction, &keys).await?;
+
+	let statement = transaction.prepare(DELETE_PRESENTED_MEETINGS_QUERY).await?;
+	transaction.execute(&statement, &[user_id]).await?;
+
+	Ok(MeetingChanges {
+		user_id: *user_id,
+		ended,
+		left: leave_meetings(transaction, user_id).await?,
+	})
+}
+
+const END_LIVE_MEETINGS_QUERY: &str = r#"
+    WITH ended AS (
+        UPDATE meeting_sessions
+        SET
+            state = 'ended',
+            ended_at = $2::TIMESTAMP
+        WHERE
+            presenter = $1::UUID AND
+            state = 'live'
+        RETURNING
+            meeting_id
+    ),
+    departed AS (
+        UPDATE meeting_participants
+        SET
+            left_at = $2::TIMESTAMP
+        FROM ended
+        WHERE
+            meeting_participants.meeting_id = ended.meeting_id AND
+            meeting_participants.left_at IS NULL
+    )
+    SELECT
+        meeting_id
+    FROM ended
+    ;
+"#;
+
+/// End the live meeting sessions the user presents, and remove the user from the meeting sessions
+/// they listen to. Unlike `remove_from_meetings`, meeting sessions and presentations are kept so
+/// that the account can be restored.
+async fn end_live_meetings(
+	transaction: &Transaction<'_>,
+	user_id: &Uuid,
+) -> Result<MeetingChanges, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = transaction.prepare(END_LIVE_MEETINGS_QUERY).await?;
+	let ended = transaction
+		.query(&statement, &[user_id, &now])
+		.await?
+		.iter()
+		.map(|row| row.get(0))
+		.collect();
+
+	Ok(MeetingChanges {
+		user_id: *user_id,
+		ended,
+		left: leave_meetings(transaction, user_id).await?,
+	})
+}
+
+/// Record that the user left the meeting sessions they still take part in. Returns the meeting
+/// sessions they left.
+async fn leave_meetings(
+	transaction: &Transaction<'_>,
+	user_id: &Uuid,
+) -> Result<Vec<Uuid>, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = transaction.prepare(LEAVE_MEETINGS_QUERY).await?;
+	Ok(transaction
+		.query(&statement, &[user_id, &now])
+		.await?
+		.iter()
+		.map(|row| row.get(0))
+		.collect())
+}
+
 const DELETE_ACCOUNT_QUERY: &str = r#"
     DELETE FROM accounts
     WHERE user_id = $1::UUID;
 "#;
 
-const DELETE_AUTH_SESSION_QUERY: &str = r#"
+const DELETE_SCHEDULED_ACCOUNT_QUERY: &str = r#"
+    DELETE FROM accounts
+    WHERE
+        user_id = $1::UUID AND
+        deleted_at IS NOT NULL AND
+        deleted_at <= $2::TIMESTAMP
+    ;
+"#;
+
+const DELETE_AUTH_SESSIONS_QUERY: &str = r#"
     DELETE FROM auth_sessions
     WHERE user_id = $1::UUID;
 "#;
 
-async fn delete_user_account(client: &Client, uuid: &Uuid) -> Result<(), ServiceError> {
-	let (acc_statement, auth_statement) = pipelined_prepare(client).await?;
+const SCHEDULE_DELETION_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        deleted_at = $2::TIMESTAMP
+    WHERE
+        user_id = $1::UUID AND
+        deleted_at IS NULL
+    ;
+"#;
 
-	future::try_join(
-		client.query(&acc_statement, &[&uuid]),
-		client.query(&auth_statement, &[&uuid]),
-	)
-	.await?;
+const FIND_PURGEABLE_ACCOUNTS_QUERY: &str = r#"
+    SELECT user_id
+    FROM accounts
+    WHERE
+        deleted_at <= $1::TIMESTAMP
+    ORDER BY
+        deleted_at
+    LIMIT $2::BIGINT
+    ;
+"#;
 
-	Ok(())
+const FIND_DELETED_ACCOUNT_QUERY: &str = r#"
+    SELECT
+        user_id,
+        password_hash
+    FROM accounts
+    WHERE
+        email = $1::VARCHAR(355) AND
+        deleted_at IS NOT NULL
+    ;
+"#;
+
+async fn find_deleted_account(
+	client: &Client,
+	email: &str,
+) -> Result<Option<(Uuid, HashedPassword)>, ServiceError> {
+	let statement = client.prepare(FIND_DELETED_ACCOUNT_QUERY).await?;
+	let row = client.query_opt(&statement, &[&email]).await?;
+	Ok(row.map(|row| (row.get(0), HashedPassword::from_phc_string(row.get(1)))))
 }
 
-async fn pipelined_prepare(client: &Client) -> Result<(Statement, Statement), TPError> {
-	future::try_join(
-		client.prepare(DELETE_ACCOUNT_QUERY),
-		client.prepare(DELETE_AUTH_SESSION_QUERY),
-	)
-	.await
+const RESTORE_ACCOUNT_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        deleted_at = NULL
+    WHERE
+        user_id = $1::UUID AND
+        deleted_at IS NOT NULL
+    ;
+"#;
+
+/// Cancel the scheduled deletion of the account. Returns `false` if it is not scheduled for
+/// deletion (anymore).
+async fn restore_account(client: &Client, user_id: &Uuid) -> Result<bool, ServiceError> {
+	let statement = client.prepare(RESTORE_ACCOUNT_QUERY).await?;
+	let restored = client.execute(&statement, &[user_id]).await?;
+	Ok(restored == 1)
 }
@@ -0,0 +1,717 @@
+//! Personal data exports.
+//!
+//! A user requests an export of everything the server holds about them at
+//! `POST /account/export`. The export is generated in the background into a ZIP archive containing:
+//!
+//! - `account.json`: the account, without the password hash.
+//! - `sessions.json`: the auth sessions, without their `auth_token` hashes.
+//! - `meetings.json`: the meeting sessions the user took part in, with their attendance, the
+//!   scheduled meeting sessions the user presents or is invited to, the lobbies the user asked to
+//!   join, the meeting sessions the user is banned from, and whether the user has a calendar
+//!   token. The token itself is only stored hashed and thus not exported.
+//! - `avatar.png`: the avatar, if the user uploaded one.
+//! - `presentations/{meeting_id}/...`: the presentations the user uploaded, as the original PDF
+//!   document if there is one, or else as the pages.
+//!
+//! The client polls `GET /account/export` until the export is `ready`, and then downloads the
+//! archive at `GET /account/export/download`. Each user only has their latest export; requesting
+//! a new one replaces it.
+
+use crate::auth::auth_payload::AuthPayload;
+use crate::avatars::avatar_key;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::participants::ParticipantRole;
+use crate::meetings::schedule::{list_scheduled_meetings, ScheduledMeeting};
+use crate::presentations::pages::{get_uploaded_presentations, original_key, page_key};
+use crate::service_errors::ServiceError;
+use crate::storage::deletion_jobs::enqueue_blob_deletions;
+use crate::storage::BlobStorage;
+use actix_web::http::header;
+use actix_web::{web, Error, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use bytes::Bytes;
+use chrono::{Duration, NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+use std::str::FromStr;
+use tokio_postgres::{Row, Transaction};
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// How long in minutes a pending export may take before another one can be requested, e.g. if the
+/// server restarted while generating it.
+pub const EXPORT_TIMEOUT: i64 = 60;
+
+/// State of a data export.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+	/// The archive is being generated.
+	Pending,
+	/// The archive can be downloaded.
+	Ready,
+	/// Generating the archive failed; another export can be requested.
+	Failed,
+}
+
+impl ExportStatus {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::Pending => "pending",
+			Self::Ready => "ready",
+			Self::Failed => "failed",
+		}
+	}
+}
+
+impl FromStr for ExportStatus {
+	type Err = ServiceError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"pending" => Ok(Self::Pending),
+			"ready" => Ok(Self::Ready),
+			"failed" => Ok(Self::Failed),
+			other => Err(ServiceError::InternalServerError(format!(
+				"Unknown export status `{}`",
+				other
+			))),
+		}
+	}
+}
+
+/// A data export as shown to its user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataExport {
+	pub export_id: Uuid,
+	pub status: ExportStatus,
+	/// When the export was requested (UTC).
+	pub requested_at: NaiveDateTime,
+	/// When the export became ready or failed (UTC).
+	pub completed_at: Option<NaiveDateTime>,
+}
+
+impl DataExport {
+	fn from_row(row: &Row) -> Result<Self, ServiceError> {
+		Ok(Self {
+			export_id: row.get(0),
+			status: row.get::<_, &str>(1).parse()?,
+			requested_at: row.get(2),
+			completed_at: row.get(3),
+		})
+	}
+}
+
+/// Handler for requesting a data export at `POST /account/export`. Responds with
+/// `202 Accepted` and the `DataExport`. If an export is already being generated, it is returned
+/// instead of starting another one.
+pub async fn handle_request_export(
+	pool: web::Data<PersistentConnectionPool>,
+	storage: web::Data<BlobStorage>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
+	let mut client = pool.get().await?;
+
+	let now = Utc::now().naive_utc();
+	let (export, started) = start_export(&mut client, &user_id, &now).await?;
+
+	if started {
+		let export_id = export.export_id;
+
+		actix_rt::spawn(async move {
+			if let Err(e) = generate_export(&pool, &storage, &user_id, &export_id).await {
+				error!("Failed to generate data export `{}`: {}", &export_id, e);
+
+				if let Ok(client) = pool.get().await {
+					let _ = complete_export(&client, &export_id, ExportStatus::Failed).await;
+				}
+			}
+		});
+	}
+
+	Ok(HttpResponse::Accepted().json(export))
+}
+
+/// Handler for getting the state of the latest data export at `GET /account/export`.
+///
+/// ## Errors
+///
+/// - `404 Not Found`: the user never requested an export.
+pub async fn handle_get_export(
+	pool: web::Data<PersistentConnectionPool>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
+	let client = pool.get().await?;
+
+	let export = get_export(&client, &user_id)
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No data export was requested".to_string()))?;
+
+	Ok(HttpResponse::Ok().json(export))
+}
+
+/// Handler for downloading the latest data export as a ZIP archive at
+/// `GET /account/export/download`.
+///
+/// ## Errors
+///
+/// - `404 Not Found`: there is no export which is `ready`.
+pub async fn handle_download_export(
+	pool: web::Data<PersistentConnectionPool>,
+	storage: web::Data<BlobStorage>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
+	let client = pool.get().await?;
+
+	let not_ready = || ServiceError::NotFound("No data export is ready".to_string());
+
+	let export = get_export(&client, &user_id)
+		.await?
+		.filter(|export| export.status == ExportStatus::Ready)
+		.ok_or_else(not_ready)?;
+
+	let archive = storage
+		.get(&export_key(&export.export_id))
+		.await
+		.map_err(ServiceError::from)?
+		.ok_or_else(not_ready)?;
+
+	Ok(HttpResponse::Ok()
+		.content_type("application/zip")
+		.header(
+			header::CONTENT_DISPOSITION,
+			format!(
+				"attachment; filename=\"vrme-export-{}.zip\"",
+				export.requested_at.format("%Y-%m-%d")
+			),
+		)
+		.body(archive))
+}
+
+/// Blob key of the archive of the data export `export_id`.
+pub fn export_key(export_id: &Uuid) -> String {
+	format!("exports/{}.zip", export_id)
+}
+
+async fn generate_export(
+	pool: &PersistentConnectionPool,
+	storage: &BlobStorage,
+	user_id: &Uuid,
+	export_id: &Uuid,
+) -> Result<(), ServiceError> {
+	let mut client = pool.get().await?;
+
+	let mut files: Vec<(String, Bytes)> = Vec::new();
+
+	let account = get_account(&client, user_id).await?;
+	files.push(("account.json".to_string(), to_json(&account)?));
+
+	let sessions = get_sessions(&client, user_id).await?;
+	files.push(("sessions.json".to_string(), to_json(&sessions)?));
+
+	let meetings = ExportedMeetings {
+		attended: get_meetings(&client, user_id).await?,
+		scheduled: list_scheduled_meetings(&client, user_id).await?,
+		lobby: get_lobby_entries(&client, user_id).await?,
+		bans: get_bans(&client, user_id).await?,
+		calendar_token_issued: has_calendar_token(&client, user_id).await?,
+	};
+	files.push(("meetings.json".to_string(), to_json(&meetings)?));
+
+	if let Some(avatar) = storage.get(&avatar_key(user_id)).await? {
+		files.push(("avatar.png".to_string(), avatar));
+	}
+
+	for (meeting_id, state) in get_uploaded_presentations(&client, user_id).await? {
+		let prefix = format!("presentations/{}", meeting_id);
+
+		if let Some(original) = storage
+			.get(&original_key(&meeting_id, state.upload_id))
+			.await?
+		{
+			files.push((format!("{}/original.pdf", prefix), original));
+			continue;
+		}
+
+		for index in 0..state.page_count {
+			if let Some(page) = storage
+				.get(&page_key(&meeting_id, state.upload_id, index))
+				.await?
+			{
+				files.push((format!("{}/{}.png", prefix, index), page));
+			}
+		}
+	}
+
+	let archive = web::block(move || write_archive(files)).await?;
+	storage
+		.put(
+			&export_key(export_id),
+			Bytes::from(archive),
+			"application/zip",
+		)
+		.await?;
+
+	if !complete_export(&client, export_id, ExportStatus::Ready).await? {
+		// The export was replaced or the account purged while generating it, which queued the
+		// deletion of the archive before it was stored.
+		let transaction = client.transaction().await?;
+		enqueue_blob_deletions(&transaction, &[export_key(export_id)]).await?;
+		transaction.commit().await?;
+
+		info!("Discarded replaced data export `{}`", export_id);
+		return Ok(());
+	}
+
+	info!("Generated data export of user `{}`", user_id);
+	Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Bytes, ServiceError> {
+	serde_json::to_vec_pretty(value)
+		.map(Bytes::from)
+		.map_err(|e| ServiceError::InternalServerError(e.to_string()))
+}
+
+/// Write `files` into a ZIP archive. JSON files are compressed; images and documents are already
+/// compressed and stored as is.
+fn write_archive(files: Vec<(String, Bytes)>) -> Result<Vec<u8>, ServiceError> {
+	let zip_error = |e: std::io::Error| ServiceError::InternalServerError(e.to_string());
+
+	let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+	for (name, data) in files {
+		let compression = if name.ends_with(".json") {
+			CompressionMethod::Deflated
+		} else {
+			CompressionMethod::Stored
+		};
+
+		zip.start_file(name, FileOptions::default().compression_method(compression))
+			.map_err(|e| zip_error(e.into()))?;
+		zip.write_all(&data).map_err(zip_error)?;
+	}
+
+	let cursor = zip.finish().map_err(|e| zip_error(e.into()))?;
+	Ok(cursor.into_inner())
+}
+
+// Replaces the user's previous export unless one is pending and has not timed out yet.
+const START_EXPORT_QUERY: &str = r#"
+    INSERT INTO data_exports
+        (user_id, export_id, status, requested_at, completed_at)
+    VALUES
+        ($1::UUID, $2::UUID, 'pending', $3::TIMESTAMP, NULL)
+    ON CONFLICT
+        (user_id)
+    DO UPDATE SET
+        export_id = EXCLUDED.export_id,
+        status = EXCLUDED.status,
+        requested_at = EXCLUDED.requested_at,
+        completed_at = NULL
+    WHERE
+        data_exports.status <> 'pending' OR
+        data_exports.requested_at < $4::TIMESTAMP
+    RETURNING
+        export_id,
+        status,
+        requested_at,
+        completed_at
+    ;
+"#;
+
+/// Start a new export of the user's data unless one is pending. Returns the export, and whether it
+/// was started by this call. The archive of the replaced export is queued for deletion.
+async fn start_export(
+	client: &mut Client,
+	user_id: &Uuid,
+	now: &NaiveDateTime,
+) -> Result<(DataExport, bool), ServiceError> {
+	let timed_out_before = *now - Duration::minutes(EXPORT_TIMEOUT);
+
+	let transaction = client.transaction().await?;
+	let previous_key = lock_export_key(&transaction, user_id).await?;
+
+	let statement = transaction.prepare(START_EXPORT_QUERY).await?;
+	let row = transaction
+		.query_opt(
+			&statement,
+			&[user_id, &Uuid::new_v4(), now, &timed_out_before],
+		)
+		.await?;
+
+	let export = match row {
+		Some(row) => DataExport::from_row(&row)?,
+		None => {
+			transaction.commit().await?;
+
+			let pending = get_export(client, user_id).await?.ok_or_else(|| {
+				ServiceError::InternalServerError("Data export vanished".to_string())
+			})?;
+			return Ok((pending, false));
+		}
+	};
+
+	if let Some(previous_key) = previous_key {
+		enqueue_blob_deletions(&transaction, &[previous_key]).await?;
+	}
+
+	transaction.commit().await?;
+	Ok((export, true))
+}
+
+const GET_EXPORT_QUERY: &str = r#"
+    SELECT
+        export_id,
+        status,
+        requested_at,
+        completed_at
+    FROM data_exports
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn get_export(client: &Client, user_id: &Uuid) -> Result<Option<DataExport>, ServiceError> {
+	let statement = client.prepare(GET_EXPORT_QUERY).await?;
+	let row = client.query_opt(&statement, &[user_id]).await?;
+	row.as_ref().map(DataExport::from_row).transpose()
+}
+
+// Only completes the export if it was not replaced by a newer one in the meantime.
+const COMPLETE_EXPORT_QUERY: &str = r#"
+    UPDATE data_exports
+    SET
+        status = $2::VARCHAR(16),
+        completed_at = $3::TIMESTAMP
+    WHERE
+        export_id = $1::UUID
+    ;
+"#;
+
+/// Complete the export. Returns `false` if it no longer exists.
+async fn complete_export(
+	client: &Client,
+	export_id: &Uuid,
+	status: ExportStatus,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(COMPLETE_EXPORT_QUERY).await?;
+	let completed = client
+		.execute(&statement, &[export_id, &status.as_str(), &now])
+		.await?;
+	Ok(completed == 1)
+}
+
+const LOCK_EXPORT_QUERY: &str = r#"
+    SELECT
+        export_id
+    FROM data_exports
+    WHERE
+        user_id = $1::UUID
+    FOR UPDATE
+    ;
+"#;
+
+/// Blob key of the archive of the user's latest data export, if any, as part of `transaction`. The
+/// export is locked so that it cannot be replaced before the transaction ends.
+pub async fn lock_export_key(
+	transaction: &Transaction<'_>,
+	user_id: &Uuid,
+) -> Result<Option<String>, ServiceError> {
+	let statement = transaction.prepare(LOCK_EXPORT_QUERY).await?;
+	let row = transaction.query_opt(&statement, &[user_id]).await?;
+	Ok(row.map(|row| export_key(&row.get(0))))
+}
+
+/// The account of the user, as exported.
+#[derive(Debug, Serialize)]
+struct ExportedAccount {
+	user_id: Uuid,
+	email: String,
+	email_verified: bool,
+	first_name: String,
+	last_name: String,
+	display_name: Option<String>,
+	pronouns: Option<String>,
+	organisation: Option<String>,
+	locale: Option<String>,
+	profile_visibility: String,
+	role: String,
+	banned_at: Option<NaiveDateTime>,
+	ban_reason: Option<String>,
+	created_at: chrono::NaiveDate,
+}
+
+const GET_ACCOUNT_QUERY: &str = r#"
+    SELECT
+        user_id,
+        email,
+        email_verified,
+        first_name,
+        last_name,
+        display_name,
+        pronouns,
+        organisation,
+        locale,
+        profile_visibility,
+        role,
+        banned_at,
+        ban_reason,
+        created_at
+    FROM accounts
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn get_account(client: &Client, user_id: &Uuid) -> Result<ExportedAccount, ServiceError> {
+	let statement = client.prepare(GET_ACCOUNT_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[user_id])
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such account found".to_string()))?;
+
+	Ok(ExportedAccount {
+		user_id: row.get(0),
+		email: row.get(1),
+		email_verified: row.get(2),
+		first_name: row.get(3),
+		last_name: row.get(4),
+		display_name: row.get(5),
+		pronouns: row.get(6),
+		organisation: row.get(7),
+		locale: row.get(8),
+		profile_visibility: row.get(9),
+		role: row.get(10),
+		banned_at: row.get(11),
+		ban_reason: row.get(12),
+		created_at: row.get(13),
+	})
+}
+
+/// An auth session of the user, as exported.
+#[derive(Debug, Serialize)]
+struct ExportedSession {
+	session_id: Uuid,
+	device_label: Option<String>,
+	user_agent: Option<String>,
+	created_at: NaiveDateTime,
+	last_used: NaiveDateTime,
+}
+
+const GET_SESSIONS_QUERY: &str = r#"
+    SELECT
+        session_id,
+        device_label,
+        user_agent,
+        created_at,
+        last_used
+    FROM auth_sessions
+    WHERE
+        user_id = $1::UUID
+    ORDER BY
+        created_at
+    ;
+"#;
+
+async fn get_sessions(
+	client: &Client,
+	user_id: &Uuid,
+) -> Result<Vec<ExportedSession>, ServiceError> {
+	let statement = client.prepare(GET_SESSIONS_QUERY).await?;
+	let rows = client.query(&statement, &[user_id]).await?;
+
+	Ok(rows
+		.iter()
+		.map(|row| ExportedSession {
+			session_id: row.get(0),
+			device_label: row.get(1),
+			user_agent: row.get(2),
+			created_at: row.get(3),
+			last_used: row.get(4),
+		})
+		.collect())
+}
+
+/// The meeting sessions of the user, as exported.
+#[derive(Debug, Serialize)]
+struct ExportedMeetings {
+	attended: Vec<ExportedMeeting>,
+	scheduled: Vec<ScheduledMeeting>,
+	lobby: Vec<ExportedLobbyEntry>,
+	bans: Vec<ExportedBan>,
+	calendar_token_issued: bool,
+}
+
+/// A meeting session the user took part in, with their attendance, as exported.
+#[derive(Debug, Serialize)]
+struct ExportedMeeting {
+	meeting_id: Uuid,
+	presenter: Uuid,
+	started_at: NaiveDateTime,
+	role: ParticipantRole,
+	joined_at: NaiveDateTime,
+	left_at: Option<NaiveDateTime>,
+	/// When the presenter removed the user, if they did.
+	removed_at: Option<NaiveDateTime>,
+}
+
+const GET_MEETINGS_QUERY: &str = r#"
+    SELECT
+        meeting_sessions.meeting_id,
+        meeting_sessions.presenter,
+        meeting_sessions.started_at,
+        meeting_participants.role,
+        meeting_participants.joined_at,
+        meeting_participants.left_at,
+        meeting_participants.removed_at
+    FROM meeting_participants
+    INNER JOIN meeting_sessions
+        ON meeting_sessions.meeting_id = meeting_participants.meeting_id
+    WHERE
+        meeting_participants.user_id = $1::UUID
+    ORDER BY
+        meeting_sessions.started_at
+    ;
+"#;
+
+async fn get_meetings(
+	client: &Client,
+	user_id: &Uuid,
+) -> Result<Vec<ExportedMeeting>, ServiceError> {
+	let statement = client.prepare(GET_MEETINGS_QUERY).await?;
+	let rows = client.query(&statement, &[user_id]).await?;
+
+	rows.iter()
+		.map(|row| {
+			Ok(ExportedMeeting {
+				meeting_id: row.get(0),
+				presenter: row.get(1),
+				started_at: row.get(2),
+				role: row.get::<_, &str>(3).parse()?,
+				joined_at: row.get(4),
+				left_at: row.get(5),
+				removed_at: row.get(6),
+			})
+		})
+		.collect()
+}
+
+/// A request of the user to join a meeting session from its lobby, as exported.
+#[derive(Debug, Serialize)]
+struct ExportedLobbyEntry {
+	meeting_id: Uuid,
+	requested_at: NaiveDateTime,
+	rejected_at: Option<NaiveDateTime>,
+}
+
+const GET_LOBBY_ENTRIES_QUERY: &str = r#"
+    SELECT
+        meeting_id,
+        requested_at,
+        rejected_at
+    FROM meeting_lobby
+    WHERE
+        user_id = $1::UUID
+    ORDER BY
+        requested_at
+    ;
+"#;
+
+async fn get_lobby_entries(
+	client: &Client,
+	user_id: &Uuid,
+) -> Result<Vec<ExportedLobbyEntry>, ServiceError> {
+	let statement = client.prepare(GET_LOBBY_ENTRIES_QUERY).await?;
+	let rows = client.query(&statement, &[user_id]).await?;
+
+	Ok(rows
+		.iter()
+		.map(|row| ExportedLobbyEntry {
+			meeting_id: row.get(0),
+			requested_at: row.get(1),
+			rejected_at: row.get(2),
+		})
+		.collect())
+}
+
+/// A ban of the user from a meeting session, as exported.
+#[derive(Debug, Serialize)]
+struct ExportedBan {
+	meeting_id: Uuid,
+	banned_at: NaiveDateTime,
+}
+
+const GET_BANS_QUERY: &str = r#"
+    SELECT
+        meeting_id,
+        banned_at
+    FROM meeting_bans
+    WHERE
+        user_id = $1::UUID
+    ORDER BY
+        banned_at
+    ;
+"#;
+
+async fn get_bans(client: &Client, user_id: &Uuid) -> Result<Vec<ExportedBan>, ServiceError> {
+	let statement = client.prepare(GET_BANS_QUERY).await?;
+	let rows = client.query(&statement, &[user_id]).await?;
+
+	Ok(rows
+		.iter()
+		.map(|row| ExportedBan {
+			meeting_id: row.get(0),
+			banned_at: row.get(1),
+		})
+		.collect())
+}
+
+const HAS_CALENDAR_TOKEN_QUERY: &str = r#"
+    SELECT
+        calendar_token_hash IS NOT NULL
+    FROM accounts
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn has_calendar_token(client: &Client, user_id: &Uuid) -> Result<bool, ServiceError> {
+	let statement = client.prepare(HAS_CALENDAR_TOKEN_QUERY).await?;
+	let row = client.query_opt(&statement, &[user_id]).await?;
+	Ok(row.is_some_and(|row| row.get(0)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Read;
+	use zip::ZipArchive;
+
+	#[test]
+	fn test_write_archive() {
+		let files = vec![
+			("account.json".to_string(), Bytes::from_static(b"{}")),
+			("avatar.png".to_string(), Bytes::from_static(b"png")),
+		];
+
+		let archive = write_archive(files).unwrap();
+		let mut zip = ZipArchive::new(Cursor::new(archive)).unwrap();
+		assert_eq!(zip.len(), 2);
+
+		let mut avatar = String::new();
+		zip.by_name("avatar.png")
+			.unwrap()
+			.read_to_string(&mut avatar)
+			.unwrap();
+		assert_eq!(avatar, "png");
+	}
+}
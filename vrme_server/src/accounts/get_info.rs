@@ -6,16 +6,23 @@ use deadpool_postgres::Client;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::accounts::visibility::{check_profile_visible, ProfileVisibility};
+use crate::auth::roles::OptionalIdentity;
 use crate::database::postgresql::PersistentConnectionPool;
 use crate::service_errors::ServiceError;
 
-/// Handler for getting account information (publicly available).
+/// Handler for getting account information. Authentication is optional; whether the account
+/// information is shown depends on the visibility chosen by its user (see
+/// `crate::accounts::visibility`).
 pub async fn handle_get_account_info(
 	pool: web::Data<PersistentConnectionPool>,
 	target_user_id: web::Path<Uuid>,
+	identity: OptionalIdentity,
 ) -> Result<HttpResponse, Error> {
 	let client = pool.get().await?;
 
+	check_profile_visible(&client, &target_user_id, identity.0.as_ref()).await?;
+
 	let account_info = get_account_info(&client, &target_user_id).await?;
 
 	Ok(HttpResponse::Ok().json(account_info))
@@ -25,12 +32,22 @@ pub async fn handle_get_account_info(
 struct AccountInfoResponse {
 	first_name: String,
 	last_name: String,
+	display_name: Option<String>,
+	pronouns: Option<String>,
+	organisation: Option<String>,
+	locale: Option<String>,
+	profile_visibility: ProfileVisibility,
 }
 
 const GET_ACCOUNT_INFO_QUERY: &str = r#"
     SELECT
         first_name,
-        last_name
+        last_name,
+        display_name,
+        pronouns,
+        organisation,
+        locale,
+        profile_visibility
     FROM
         accounts
     WHERE
@@ -50,10 +67,15 @@ async fn get_account_info(
 			"No matching account found".to_string(),
 		))
 	} else {
-		let (first_name, last_name) = (rows[0].get(0), rows[0].get(1));
+		let row = &rows[0];
 		Ok(AccountInfoResponse {
-			first_name,
-			last_name,
+			first_name: row.get(0),
+			last_name: row.get(1),
+			display_name: row.get(2),
+			pronouns: row.get(3),
+			organisation: row.get(4),
+			locale: row.get(5),
+			profile_visibility: row.get::<_, &str>(6).parse()?,
 		})
 	}
 }
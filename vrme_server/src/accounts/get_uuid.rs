@@ -1,5 +1,7 @@
 //! Get a user's `uuid` by providing the user's email.
 
+use crate::accounts::visibility::check_profile_visible;
+use crate::auth::roles::Identity;
 use crate::database::postgresql::PersistentConnectionPool;
 use crate::service_errors::ServiceError;
 use actix_web::{web, Error, HttpResponse};
@@ -14,12 +16,23 @@ pub struct GetUuidRequest {
 	pub email: String,
 }
 
+/// Handler for looking up the `uuid` of a user by their email address at `GET /accounts/uuid`.
+///
+/// Requires authentication, and is rate limited more strictly than other endpoints (see
+/// `rate_limiting.uuid_lookup_max_requests`), so that it cannot be used to enumerate users.
+///
+/// ## Errors
+///
+/// - `404 Not Found`: no account has the email address, or the caller may not see the account
+///   (see `crate::accounts::visibility`).
 pub async fn handle_get_uuid(
 	pool: web::Data<PersistentConnectionPool>,
 	req: web::Json<GetUuidRequest>,
+	identity: Identity,
 ) -> Result<HttpResponse, Error> {
 	let client = pool.get().await?;
 	let uuid = get_uuid_given_email(&client, &req.email).await?;
+	check_profile_visible(&client, &uuid, Some(&identity)).await?;
 	Ok(HttpResponse::Ok().json(json!({ "uuid": uuid })))
 }
 
@@ -31,8 +44,11 @@ const GET_UUID_GIVEN_EMAIL_QUERY: &str = r#"
 
 /// Retreive the user's `Uuid` given their `email` address.
 async fn get_uuid_given_email(client: &Client, email: &str) -> Result<Uuid, ServiceError> {
-	let statement = client.prepare(GET_UUID_GIVEN_EMAIL_QUERY).await.unwrap();
-	let row = client.query_one(&statement, &[&email]).await?;
+	let statement = client.prepare(GET_UUID_GIVEN_EMAIL_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[&email])
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No matching account found".to_string()))?;
 	let uuid = row.get(0);
 	Ok(uuid)
 }
@@ -1,6 +1,11 @@
 //! Account creation, modification and deletion support.
+pub mod change_email;
 pub mod delete;
+pub mod export;
 pub mod get_info;
 pub mod get_uuid;
+pub mod password;
 pub mod register;
 pub mod update_info;
+pub mod verify_email;
+pub mod visibility;
@@ -0,0 +1,329 @@
+//! Password changes and recovery.
+//!
+//! A logged-in user changes their password at `PUT /account/password` by providing their current
+//! one. A user who forgot their password instead requests a reset token at
+//! `POST /password/forgot`, which is emailed to them, and then chooses a new password at
+//! `POST /password/reset` with that token.
+
+use crate::auth::auth_payload::AuthPayload;
+use crate::auth::one_time_tokens::{consume_token, issue_token, TokenPurpose};
+use crate::auth::sessions::find_session;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::mail::{Email, MailSender};
+use crate::service_errors::ServiceError;
+use crate::settings::Settings;
+use crate::types::client_hashed_password::ClientHashedPassword;
+use crate::types::hashed_password::HashedPassword;
+use actix_web::{web, Error, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::Duration;
+use deadpool_postgres::Client;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Required payload to change the password.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangePasswordRequest {
+	/// base64-encoded client-side-hashed current password.
+	pub current_hashed_password: String,
+	/// base64-encoded client-side-hashed new password. Must be exactly `44` base64 characters.
+	pub new_hashed_password: String,
+	/// Whether to revoke all auth sessions other than the one the request is authenticated with,
+	/// logging out every other device. Defaults to `false`.
+	#[serde(default)]
+	pub revoke_other_sessions: bool,
+}
+
+/// Handler for changing the password of the user at `PUT /account/password`.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: either password is malformed.
+/// - `403 Forbidden`: the current password is incorrect.
+pub async fn handle_change_password(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	request: web::Json<ChangePasswordRequest>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
+
+	let current_hash = ClientHashedPassword::new(&request.current_hashed_password)?
+		.decode()
+		.await?;
+	let new_hash = ClientHashedPassword::new(&request.new_hashed_password)?
+		.decode()
+		.await?;
+
+	let client = pool.get().await?;
+
+	let previous_hash = get_password_hash(&client, &auth_payload.uuid)
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such account found".to_string()))?;
+
+	if !previous_hash.verify(&current_hash).await? {
+		return Err(
+			ServiceError::Forbidden("The current password is incorrect".to_string()).into(),
+		);
+	}
+
+	let revoke_sessions = if request.revoke_other_sessions {
+		let found = find_session(&client, &settings.auth, &auth_payload)
+			.await?
+			.ok_or_else(|| {
+				ServiceError::Unauthorized("No matching auth session found".to_string())
+			})?;
+		RevokeSessions::AllExcept(found.session.session_id)
+	} else {
+		RevokeSessions::None
+	};
+
+	let hashed_password = HashedPassword::new(&new_hash, &settings.auth.argon2).await?;
+	set_password(
+		&client,
+		&auth_payload.uuid,
+		&hashed_password,
+		revoke_sessions,
+	)
+	.await?;
+
+	info!("Changed password of user `{}`", &auth_payload.uuid);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Validity duration of password reset tokens in minutes.
+pub const PASSWORD_RESET_VALIDITY: i64 = 30;
+
+/// Required payload to request a password reset.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ForgotPasswordRequest {
+	pub email: String,
+}
+
+/// Handler for requesting a password reset at `POST /password/forgot`. If an account with the
+/// email address exists, a single-use reset token valid for `PASSWORD_RESET_VALIDITY` minutes is
+/// emailed to it; reset tokens sent before are revoked.
+///
+/// Always responds with `202 Accepted` right away, whether or not an account with the email
+/// address exists, so as not to reveal which email addresses are registered; the email is sent in
+/// the background so that the response time does not reveal it either.
+pub async fn handle_forgot_password(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	mail_sender: web::Data<MailSender>,
+	request: web::Json<ForgotPasswordRequest>,
+) -> HttpResponse {
+	let email = request.into_inner().email;
+
+	actix_rt::spawn(async move {
+		if let Err(e) = send_password_reset_email(&pool, &settings, &mail_sender, &email).await {
+			error!("Failed to send password reset email: {}", e);
+		}
+	});
+
+	HttpResponse::Accepted().finish()
+}
+
+async fn send_password_reset_email(
+	pool: &PersistentConnectionPool,
+	settings: &Settings,
+	mail_sender: &MailSender,
+	email: &str,
+) -> Result<(), ServiceError> {
+	let client = pool.get().await?;
+
+	let user_id = match find_account(&client, email).await? {
+		Some(user_id) => user_id,
+		None => return Ok(()),
+	};
+
+	let token = issue_token(
+		&client,
+		&settings.auth,
+		&user_id,
+		TokenPurpose::PasswordReset,
+		None,
+		Duration::minutes(PASSWORD_RESET_VALIDITY),
+	)
+	.await?;
+
+	mail_sender
+		.send(Email {
+			to: email.to_string(),
+			subject: "Reset your VRME password".to_string(),
+			body: format!(
+				"To choose a new password for your VRME account, enter the following code in the \
+				 VRME app:\n\n\
+				 {}\n\n\
+				 The code is valid for {} minutes. If you did not ask to reset your password, you \
+				 can ignore this email; your password stays unchanged.\n",
+				token, PASSWORD_RESET_VALIDITY
+			),
+		})
+		.await?;
+
+	info!("Sent password reset email to user `{}`", &user_id);
+	Ok(())
+}
+
+/// Required payload to reset a password.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResetPasswordRequest {
+	/// Reset token from the email.
+	pub token: String,
+	/// base64-encoded client-side-hashed new password. Must be exactly `44` base64 characters.
+	pub hashed_password: String,
+}
+
+/// Handler for choosing a new password with a reset token at `POST /password/reset`.
+///
+/// All auth sessions of the user are revoked, so every device has to login again with the new
+/// password.
+///
+/// ## Success Response
+///
+/// ```json
+/// {
+///     "message": "Password successfully reset",
+///     "data": {
+///         "user_id": "123e4567-e89b-12d3-a456-426655440000"
+///     }
+/// }
+/// ```
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the token is invalid, was already used or has expired, or the new
+///   password is malformed.
+pub async fn handle_reset_password(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	request: web::Json<ResetPasswordRequest>,
+) -> Result<HttpResponse, Error> {
+	// Check the new password before consuming the token, so that a malformed request does not use
+	// up the token.
+	let client_hash = ClientHashedPassword::new(&request.hashed_password)?
+		.decode()
+		.await?;
+
+	let client = pool.get().await?;
+
+	let consumed = consume_token(
+		&client,
+		&settings.auth,
+		&request.token,
+		TokenPurpose::PasswordReset,
+	)
+	.await?
+	.ok_or_else(|| ServiceError::BadRequest("Invalid or expired reset token".to_string()))?;
+
+	let hashed_password = HashedPassword::new(&client_hash, &settings.auth.argon2).await?;
+	set_password(
+		&client,
+		&consumed.user_id,
+		&hashed_password,
+		RevokeSessions::All,
+	)
+	.await?;
+
+	info!("Reset password of user `{}`", &consumed.user_id);
+
+	Ok(HttpResponse::Ok().json(json!({
+		"message": "Password successfully reset",
+		"data": {
+			"user_id": consumed.user_id
+		}
+	})))
+}
+
+const FIND_ACCOUNT_QUERY: &str = r#"
+    SELECT user_id
+    FROM accounts
+    WHERE
+        email = $1::VARCHAR(355) AND
+        deleted_at IS NULL
+    ;
+"#;
+
+async fn find_account(client: &Client, email: &str) -> Result<Option<Uuid>, ServiceError> {
+	let statement = client.prepare(FIND_ACCOUNT_QUERY).await?;
+	let row = client.query_opt(&statement, &[&email]).await?;
+	Ok(row.map(|row| row.get(0)))
+}
+
+const GET_PASSWORD_HASH_QUERY: &str = r#"
+    SELECT password_hash
+    FROM accounts
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+pub(crate) async fn get_password_hash(
+	client: &Client,
+	user_id: &Uuid,
+) -> Result<Option<HashedPassword>, ServiceError> {
+	let statement = client.prepare(GET_PASSWORD_HASH_QUERY).await?;
+	let row = client.query_opt(&statement, &[user_id]).await?;
+	Ok(row.map(|row| HashedPassword::from_phc_string(row.get(0))))
+}
+
+/// Which auth sessions of the user to revoke when their password is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevokeSessions {
+	/// Keep every session.
+	None,
+	/// Revoke every session.
+	All,
+	/// Revoke every session but the given one.
+	AllExcept(Uuid),
+}
+
+// A single statement, so that the password is never changed without revoking the sessions.
+const SET_PASSWORD_QUERY: &str = r#"
+    WITH updated AS (
+        UPDATE accounts
+        SET
+            password_hash = $2::TEXT
+        WHERE
+            user_id = $1::UUID
+        RETURNING user_id
+    )
+    DELETE FROM auth_sessions
+    WHERE
+        $3::BOOLEAN AND
+        user_id IN (SELECT user_id FROM updated) AND
+        session_id IS DISTINCT FROM $4::UUID
+    ;
+"#;
+
+/// Replace the password of the user and revoke their auth sessions as requested.
+pub async fn set_password(
+	client: &Client,
+	user_id: &Uuid,
+	hashed_password: &HashedPassword,
+	revoke_sessions: RevokeSessions,
+) -> Result<(), ServiceError> {
+	let (revoke, keep_session_id) = match revoke_sessions {
+		RevokeSessions::None => (false, None),
+		RevokeSessions::All => (true, None),
+		RevokeSessions::AllExcept(session_id) => (true, Some(session_id)),
+	};
+
+	let statement = client.prepare(SET_PASSWORD_QUERY).await?;
+	client
+		.execute(
+			&statement,
+			&[
+				user_id,
+				&hashed_password.as_str(),
+				&revoke,
+				&keep_session_id,
+			],
+		)
+		.await?;
+	Ok(())
+}
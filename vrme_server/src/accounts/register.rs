@@ -8,14 +8,19 @@
 //!
 //! - [SHA-256](https://tools.ietf.org/html/rfc4634)
 
+use crate::accounts::verify_email::send_verification_email;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::mail::MailSender;
 use crate::service_errors::ServiceError;
+use crate::settings::Settings;
 use crate::types::client_hashed_password::ClientHashedPassword;
 use crate::types::hashed_password::HashedPassword;
+use actix_web::error::BlockingError;
 use actix_web::Error;
 use actix_web::{web, HttpResponse};
 use deadpool_postgres::Client;
-use log::debug;
+use lettre::Address;
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
@@ -66,6 +71,8 @@ pub struct RegistrationRequest {
 pub async fn handle_registration(
 	request_info: web::Json<RegistrationRequest>,
 	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	mail_sender: web::Data<MailSender>,
 ) -> Result<HttpResponse, Error> {
 	debug!("Request:\n {:?}", &request_info);
 	validate_request_payload(request_info.clone()).await?;
@@ -74,13 +81,22 @@ pub async fn handle_registration(
 	let client_password_hash = ClientHashedPassword::new(&request_info.hashed_password)?;
 	let client_password_hash = client_password_hash.decode().await?;
 
-	// We then need to compute the `PasswordHashInfo` to store them into the database.
-	let password_hash_info = HashedPassword::new(&client_password_hash).await?;
+	// We then need to compute the `HashedPassword` to store into the database.
+	let password_hash_info =
+		HashedPassword::new(&client_password_hash, &settings.auth.argon2).await?;
 
 	let client = pool.get().await?;
 	let (user_id, email) =
 		create_account_if_not_exists(&client, &request_info, &password_hash_info).await?;
 
+	// The account is created regardless; the user can request another verification email at
+	// `POST /register/verify/resend`.
+	if let Err(e) =
+		send_verification_email(&client, &settings, &mail_sender, &user_id, &email).await
+	{
+		error!("Failed to send verification email: {}", e);
+	}
+
 	Ok(make_success_response(&user_id, &email))
 }
 
@@ -92,10 +108,15 @@ async fn validate_request_payload(payload: RegistrationRequest) -> Result<(), Se
 		Ok::<(), ServiceError>(())
 	};
 
-	web::block(task).await.map_err(|e| e.into())
+	web::block(task).await.map_err(|e| match e {
+		BlockingError::Error(e) => e,
+		BlockingError::Canceled => {
+			ServiceError::InternalServerError("Unexpectedly cancelled".to_string())
+		}
+	})
 }
 
-fn validate_name_length(name: &str, name_kind: &str) -> Result<(), ServiceError> {
+pub(crate) fn validate_name_length(name: &str, name_kind: &str) -> Result<(), ServiceError> {
 	if name.is_empty() {
 		Err(ServiceError::BadRequest(format!(
 			"`{}` canot be empty",
@@ -111,13 +132,14 @@ fn validate_name_length(name: &str, name_kind: &str) -> Result<(), ServiceError>
 	}
 }
 
-// Only checks that the email has a minimum length of 3 characters and must include `@` character.
-fn validate_email(email: &str) -> Result<(), ServiceError> {
+// Only checks that the email is syntactically valid; whether it exists is checked by emailing a
+// verification token to it.
+pub(crate) fn validate_email(email: &str) -> Result<(), ServiceError> {
 	if email.len() < 3 {
 		Err(ServiceError::BadRequest(
 			"Invalid email address: too short".to_string(),
 		))
-	} else if !email.contains('@') {
+	} else if email.parse::<Address>().is_err() {
 		Err(ServiceError::BadRequest(
 			"Invalid email address".to_string(),
 		))
@@ -133,8 +155,6 @@ const CREATE_USER_QUERY: &str = r#"
         email,
         first_name,
         last_name,
-        iteration_count,
-        salt,
         password_hash,
         created_at
     )
@@ -144,10 +164,8 @@ const CREATE_USER_QUERY: &str = r#"
         $2::VARCHAR(355),   --email
         $3::VARCHAR(100),   --first_name
         $4::VARCHAR(100),   --last_name
-        $5::INT,            --iteration_count
-        $6::BYTEA,          --salt,
-        $7::BYTEA,          --password_hash,
-        $8::DATE            --created_at
+        $5::TEXT,           --password_hash
+        $6::DATE            --created_at
     )
     ON CONFLICT DO NOTHING
     RETURNING user_id, email;
@@ -161,7 +179,6 @@ async fn create_account_if_not_exists(
 	let statement = client.prepare(CREATE_USER_QUERY).await.unwrap();
 	let uuid = Uuid::new_v4();
 	let date = chrono::Utc::today().naive_utc();
-	let iteration_count = hashed_password.iteration_count as i32;
 
 	let rows = client
 		.query(
@@ -171,9 +188,7 @@ async fn create_account_if_not_exists(
 				&request_info.email,
 				&request_info.first_name,
 				&request_info.last_name,
-				&iteration_count,
-				&hashed_password.salt,
-				&hashed_password.hash,
+				&hashed_password.as_str(),
 				&date,
 			],
 		)
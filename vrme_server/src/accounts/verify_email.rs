@@ -0,0 +1,171 @@
+//! Email address verification.
+//!
+//! Upon registration, a single-use verification token is emailed to the user, which proves that
+//! they own the email address once submitted at `POST /register/verify`. Until then, the account
+//! is *unverified*, and cannot be logged into if `auth.require_email_verification` is enabled.
+
+use crate::auth::one_time_tokens::{consume_token, issue_token, TokenPurpose};
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::mail::{Email, MailSender};
+use crate::service_errors::ServiceError;
+use crate::settings::Settings;
+use actix_web::{web, Error, HttpResponse};
+use chrono::Duration;
+use deadpool_postgres::Client;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Validity duration of email verification tokens in hours.
+pub const EMAIL_VERIFICATION_VALIDITY: i64 = 48;
+
+/// Required payload to verify an email address.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VerifyEmailRequest {
+	/// Verification token from the email.
+	pub token: String,
+}
+
+/// Handler for verifying the email address of an account at `POST /register/verify`.
+///
+/// ## Success Response
+///
+/// ```json
+/// {
+///     "message": "Email address successfully verified",
+///     "data": {
+///         "user_id": "123e4567-e89b-12d3-a456-426655440000"
+///     }
+/// }
+/// ```
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the token is invalid, was already used or has expired.
+pub async fn handle_verify_email(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	request: web::Json<VerifyEmailRequest>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	let consumed = consume_token(
+		&client,
+		&settings.auth,
+		&request.token,
+		TokenPurpose::EmailVerification,
+	)
+	.await?
+	.ok_or_else(|| ServiceError::BadRequest("Invalid or expired verification token".to_string()))?;
+
+	mark_email_verified(&client, &consumed.user_id).await?;
+
+	Ok(HttpResponse::Ok().json(json!({
+		"message": "Email address successfully verified",
+		"data": {
+			"user_id": consumed.user_id
+		}
+	})))
+}
+
+/// Required payload to request another verification email.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResendVerificationRequest {
+	pub email: String,
+}
+
+/// Handler for sending another verification email at `POST /register/verify/resend`, e.g. if the
+/// previous one got lost or expired. Verification tokens sent before are revoked.
+///
+/// Always responds with `202 Accepted`, whether or not an unverified account with the email
+/// address exists, so as not to reveal which email addresses are registered.
+pub async fn handle_resend_verification_email(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	mail_sender: web::Data<MailSender>,
+	request: web::Json<ResendVerificationRequest>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	if let Some(user_id) = find_unverified_account(&client, &request.email).await? {
+		if let Err(e) =
+			send_verification_email(&client, &settings, &mail_sender, &user_id, &request.email)
+				.await
+		{
+			error!("Failed to send verification email: {}", e);
+		}
+	}
+
+	Ok(HttpResponse::Accepted().finish())
+}
+
+/// Issue a verification token to the user and email it to `email`.
+pub async fn send_verification_email(
+	client: &Client,
+	settings: &Settings,
+	mail_sender: &MailSender,
+	user_id: &Uuid,
+	email: &str,
+) -> Result<(), ServiceError> {
+	let token = issue_token(
+		client,
+		&settings.auth,
+		user_id,
+		TokenPurpose::EmailVerification,
+		None,
+		Duration::hours(EMAIL_VERIFICATION_VALIDITY),
+	)
+	.await?;
+
+	mail_sender
+		.send(Email {
+			to: email.to_string(),
+			subject: "Verify your VRME email address".to_string(),
+			body: format!(
+				"Welcome to VRME!\n\n\
+				 To verify your email address, enter the following code in the VRME app:\n\n\
+				 {}\n\n\
+				 The code is valid for {} hours. If you did not create a VRME account, you can \
+				 ignore this email.\n",
+				token, EMAIL_VERIFICATION_VALIDITY
+			),
+		})
+		.await?;
+
+	info!("Sent verification email to user `{}`", user_id);
+	Ok(())
+}
+
+const FIND_UNVERIFIED_ACCOUNT_QUERY: &str = r#"
+    SELECT user_id
+    FROM accounts
+    WHERE
+        email = $1::VARCHAR(355) AND
+        NOT email_verified
+    ;
+"#;
+
+async fn find_unverified_account(
+	client: &Client,
+	email: &str,
+) -> Result<Option<Uuid>, ServiceError> {
+	let statement = client.prepare(FIND_UNVERIFIED_ACCOUNT_QUERY).await?;
+	let row = client.query_opt(&statement, &[&email]).await?;
+	Ok(row.map(|row| row.get(0)))
+}
+
+const MARK_EMAIL_VERIFIED_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        email_verified = TRUE
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn mark_email_verified(client: &Client, user_id: &Uuid) -> Result<(), ServiceError> {
+	let statement = client.prepare(MARK_EMAIL_VERIFIED_QUERY).await?;
+	client.execute(&statement, &[user_id]).await?;
+	Ok(())
+}
@@ -0,0 +1,143 @@
+//! Who may look up an account: its uuid by email address, its information and its avatar.
+//!
+//! Each user chooses a `ProfileVisibility` for their account. The user themselves, moderators and
+//! admins can always see the account. To everyone else, an account they may not see is
+//! indistinguishable from one which does not exist. Accounts scheduled for deletion are hidden from
+//! everyone.
+
+use crate::auth::roles::{Identity, Role};
+use crate::service_errors::ServiceError;
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Who may see an account.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileVisibility {
+	/// Anyone, including clients which are not logged in.
+	Public,
+	/// Logged-in users.
+	Authenticated,
+	/// Users who are in a meeting session with the user.
+	Participants,
+}
+
+impl ProfileVisibility {
+	/// Name of the visibility as stored in `accounts.profile_visibility`.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Public => "public",
+			Self::Authenticated => "authenticated",
+			Self::Participants => "participants",
+		}
+	}
+}
+
+impl FromStr for ProfileVisibility {
+	type Err = ServiceError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"public" => Ok(Self::Public),
+			"authenticated" => Ok(Self::Authenticated),
+			"participants" => Ok(Self::Participants),
+			other => Err(ServiceError::InternalServerError(format!(
+				"Unknown profile visibility `{}`",
+				other
+			))),
+		}
+	}
+}
+
+const GET_VISIBILITY_QUERY: &str = r#"
+    SELECT
+        profile_visibility,
+        EXISTS (
+            SELECT 1
+            FROM meeting_participants AS own
+            INNER JOIN meeting_participants AS viewer
+                ON viewer.meeting_id = own.meeting_id
+            WHERE
+                own.user_id = $1::UUID AND
+                own.left_at IS NULL AND
+                viewer.user_id = $2::UUID AND
+                viewer.left_at IS NULL
+        )
+    FROM accounts
+    WHERE
+        user_id = $1::UUID AND
+        deleted_at IS NULL
+    ;
+"#;
+
+/// Check that the account of `user_id` exists and may be seen by `viewer` (`None` if the client
+/// is not logged in), or fail with `404 Not Found`.
+pub async fn check_profile_visible(
+	client: &Client,
+	user_id: &Uuid,
+	viewer: Option<&Identity>,
+) -> Result<(), ServiceError> {
+	let not_found = || ServiceError::NotFound("No matching account found".to_string());
+
+	let statement = client.prepare(GET_VISIBILITY_QUERY).await?;
+	let viewer_id = viewer.map(|viewer| viewer.user_id);
+	let row = client
+		.query_opt(&statement, &[user_id, &viewer_id])
+		.await?
+		.ok_or_else(not_found)?;
+
+	let visibility: &str = row.get(0);
+	let co_participant: bool = row.get(1);
+
+	if is_visible(visibility.parse()?, user_id, viewer, co_participant) {
+		Ok(())
+	} else {
+		Err(not_found())
+	}
+}
+
+fn is_visible(
+	visibility: ProfileVisibility,
+	user_id: &Uuid,
+	viewer: Option<&Identity>,
+	co_participant: bool,
+) -> bool {
+	match viewer {
+		Some(viewer) if &viewer.user_id == user_id || viewer.role >= Role::Moderator => true,
+		Some(_) => visibility != ProfileVisibility::Participants || co_participant,
+		None => visibility == ProfileVisibility::Public,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn identity(user_id: Uuid, role: Role) -> Identity {
+		Identity {
+			user_id,
+			session_id: Uuid::new_v4(),
+			role,
+		}
+	}
+
+	#[test]
+	fn test_is_visible() {
+		let user_id = Uuid::new_v4();
+		let owner = identity(user_id, Role::User);
+		let other = identity(Uuid::new_v4(), Role::User);
+		let moderator = identity(Uuid::new_v4(), Role::Moderator);
+
+		use ProfileVisibility::*;
+
+		assert!(is_visible(Public, &user_id, None, false));
+		assert!(!is_visible(Authenticated, &user_id, None, false));
+		assert!(is_visible(Authenticated, &user_id, Some(&other), false));
+		assert!(!is_visible(Participants, &user_id, Some(&other), false));
+		assert!(is_visible(Participants, &user_id, Some(&other), true));
+		assert!(is_visible(Participants, &user_id, Some(&owner), false));
+		assert!(is_visible(Participants, &user_id, Some(&moderator), false));
+	}
+}
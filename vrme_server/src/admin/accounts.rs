@@ -0,0 +1,385 @@
+//! Account moderation: searching accounts, banning users, logging them out and changing roles.
+
+use crate::auth::roles::{Admin, Authorized, Moderator, Role};
+use crate::auth::sessions::delete_all_sessions;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::service_errors::ServiceError;
+use crate::settings::AuthSettings;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// Default number of accounts listed per page.
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Upper limit on the number of accounts listed per page.
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Upper limit on the length of a ban reason in characters.
+pub const BAN_REASON_MAX_LEN: usize = 500;
+
+/// An account as listed to moderators.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountSummary {
+	pub user_id: Uuid,
+	pub email: String,
+	pub first_name: String,
+	pub last_name: String,
+	pub role: Role,
+	pub email_verified: bool,
+	/// When the user was banned, if they are.
+	pub banned_at: Option<NaiveDateTime>,
+	pub ban_reason: Option<String>,
+	pub created_at: chrono::NaiveDate,
+}
+
+impl AccountSummary {
+	fn from_row(row: &Row) -> Result<Self, ServiceError> {
+		let role: &str = row.get(4);
+
+		Ok(Self {
+			user_id: row.get(0),
+			email: row.get(1),
+			first_name: row.get(2),
+			last_name: row.get(3),
+			role: role.parse()?,
+			email_verified: row.get(5),
+			banned_at: row.get(6),
+			ban_reason: row.get(7),
+			created_at: row.get(8),
+		})
+	}
+}
+
+/// Query parameters for listing accounts.
+#[derive(Debug, Deserialize)]
+pub struct ListAccountsQuery {
+	/// Only list accounts whose email address or name contains this text (case-insensitive).
+	pub search: Option<String>,
+	/// Only list banned (`true`) or not banned (`false`) accounts.
+	pub banned: Option<bool>,
+	#[serde(default)]
+	pub offset: i64,
+	pub limit: Option<i64>,
+}
+
+/// Handler for listing and searching accounts at `GET /admin/accounts`, newest first.
+///
+/// ## Query Parameters
+///
+/// - `search`: text contained in the email address, first name or last name.
+/// - `banned`: `true` or `false` to only list banned or not banned accounts.
+/// - `offset` and `limit`: pagination, by default the first `DEFAULT_PAGE_SIZE` accounts.
+pub async fn handle_list_accounts(
+	pool: web::Data<PersistentConnectionPool>,
+	query: web::Query<ListAccountsQuery>,
+	_auth: Authorized<Moderator>,
+) -> Result<HttpResponse, Error> {
+	let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+	if !(1..=MAX_PAGE_SIZE).contains(&limit) || query.offset < 0 {
+		return Err(ServiceError::BadRequest(format!(
+			"`limit` must be between 1 and {} and `offset` must not be negative",
+			MAX_PAGE_SIZE
+		))
+		.into());
+	}
+
+	let client = pool.get().await?;
+	let accounts = list_accounts(
+		&client,
+		query.search.as_deref(),
+		query.banned,
+		query.offset,
+		limit,
+	)
+	.await?;
+
+	Ok(HttpResponse::Ok().json(accounts))
+}
+
+/// Required payload to ban a user.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BanRequest {
+	/// Reason shown to the user when they try to use their account. At most `500` characters.
+	pub reason: Option<String>,
+}
+
+/// Handler for banning a user at `POST /admin/accounts/{uuid}/ban`. Every request the user makes
+/// with their existing auth sessions, and every login attempt, is rejected with `403 Forbidden`
+/// until they are unbanned.
+pub async fn handle_ban_user(
+	pool: web::Data<PersistentConnectionPool>,
+	user_id: web::Path<Uuid>,
+	request: web::Json<BanRequest>,
+	auth: Authorized<Moderator>,
+) -> Result<HttpResponse, Error> {
+	let reason = request
+		.reason
+		.as_deref()
+		.map(str::trim)
+		.filter(|reason| !reason.is_empty());
+
+	if reason.map_or(0, |reason| reason.chars().count()) > BAN_REASON_MAX_LEN {
+		return Err(ServiceError::BadRequest("`reason` is too long".to_string()).into());
+	}
+
+	let client = pool.get().await?;
+	check_can_moderate(&client, auth.identity.role, &user_id).await?;
+
+	let now = chrono::Utc::now().naive_utc();
+	set_ban(&client, &user_id, Some(&now), reason).await?;
+
+	info!(
+		"User `{}` banned user `{}`",
+		&auth.identity.user_id, &user_id
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler for unbanning a user at `DELETE /admin/accounts/{uuid}/ban`.
+pub async fn handle_unban_user(
+	pool: web::Data<PersistentConnectionPool>,
+	user_id: web::Path<Uuid>,
+	auth: Authorized<Moderator>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	check_can_moderate(&client, auth.identity.role, &user_id).await?;
+
+	set_ban(&client, &user_id, None, None).await?;
+
+	info!(
+		"User `{}` unbanned user `{}`",
+		&auth.identity.user_id, &user_id
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler for logging a user out of every device at `DELETE /admin/accounts/{uuid}/sessions`.
+pub async fn handle_revoke_user_sessions(
+	pool: web::Data<PersistentConnectionPool>,
+	user_id: web::Path<Uuid>,
+	auth: Authorized<Moderator>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	check_can_moderate(&client, auth.identity.role, &user_id).await?;
+
+	let revoked = delete_all_sessions(&client, &user_id).await?;
+
+	info!(
+		"User `{}` revoked {} auth sessions of user `{}`",
+		&auth.identity.user_id, revoked, &user_id
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Required payload to change the role of a user.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetRoleRequest {
+	pub role: Role,
+}
+
+/// Handler for changing the role of a user at `PUT /admin/accounts/{uuid}/role`. Only admins may
+/// change roles, and cannot make other users admins.
+pub async fn handle_set_role(
+	pool: web::Data<PersistentConnectionPool>,
+	user_id: web::Path<Uuid>,
+	request: web::Json<SetRoleRequest>,
+	auth: Authorized<Admin>,
+) -> Result<HttpResponse, Error> {
+	if request.role >= auth.identity.role {
+		return Err(
+			ServiceError::Forbidden("Cannot grant a role as high as your own".to_string()).into(),
+		);
+	}
+
+	let client = pool.get().await?;
+	check_can_moderate(&client, auth.identity.role, &user_id).await?;
+
+	set_role(&client, &user_id, request.role).await?;
+
+	info!(
+		"User `{}` changed the role of user `{}` to `{}`",
+		&auth.identity.user_id,
+		&user_id,
+		request.role.as_str()
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+const GET_ROLE_QUERY: &str = r#"
+    SELECT role
+    FROM accounts
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+/// Check that the target user exists and has a lower role than the acting user.
+async fn check_can_moderate(
+	client: &Client,
+	actor_role: Role,
+	user_id: &Uuid,
+) -> Result<(), ServiceError> {
+	let statement = client.prepare(GET_ROLE_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[user_id])
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such account found".to_string()))?;
+
+	let role: &str = row.get(0);
+
+	if role.parse::<Role>()? < actor_role {
+		Ok(())
+	} else {
+		Err(ServiceError::Forbidden(
+			"Cannot act upon a user whose role is not lower than your own".to_string(),
+		))
+	}
+}
+
+const LIST_ACCOUNTS_QUERY: &str = r#"
+    SELECT
+        user_id,
+        email,
+        first_name,
+        last_name,
+        role,
+        email_verified,
+        banned_at,
+        ban_reason,
+        created_at
+    FROM accounts
+    WHERE
+        (
+            $1::TEXT IS NULL OR
+            strpos(lower(email), lower($1::TEXT)) > 0 OR
+            strpos(lower(first_name), lower($1::TEXT)) > 0 OR
+            strpos(lower(last_name), lower($1::TEXT)) > 0
+        ) AND
+        ($2::BOOLEAN IS NULL OR (banned_at IS NOT NULL) = $2::BOOLEAN)
+    ORDER BY
+        created_at DESC,
+        user_id
+    OFFSET $3::BIGINT
+    LIMIT $4::BIGINT
+    ;
+"#;
+
+async fn list_accounts(
+	client: &Client,
+	search: Option<&str>,
+	banned: Option<bool>,
+	offset: i64,
+	limit: i64,
+) -> Result<Vec<AccountSummary>, ServiceError> {
+	let statement = client.prepare(LIST_ACCOUNTS_QUERY).await?;
+	let rows = client
+		.query(&statement, &[&search, &banned, &offset, &limit])
+		.await?;
+
+	rows.iter().map(AccountSummary::from_row).collect()
+}
+
+const SET_BAN_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        banned_at = $2::TIMESTAMP,
+        ban_reason = $3::VARCHAR(500)
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn set_ban(
+	client: &Client,
+	user_id: &Uuid,
+	banned_at: Option<&NaiveDateTime>,
+	reason: Option<&str>,
+) -> Result<(), ServiceError> {
+	let statement = client.prepare(SET_BAN_QUERY).await?;
+	client
+		.execute(&statement, &[user_id, &banned_at, &reason])
+		.await?;
+	Ok(())
+}
+
+const SET_ROLE_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        role = $2::VARCHAR(16)
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn set_role(client: &Client, user_id: &Uuid, role: Role) -> Result<(), ServiceError> {
+	let statement = client.prepare(SET_ROLE_QUERY).await?;
+	client
+		.execute(&statement, &[user_id, &role.as_str()])
+		.await?;
+	Ok(())
+}
+
+const BOOTSTRAP_ADMIN_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        role = 'admin'
+    WHERE
+        email = $1::VARCHAR(355) AND
+        email_verified = TRUE AND
+        deleted_at IS NULL AND
+        NOT EXISTS (SELECT 1 FROM accounts WHERE role = 'admin')
+    RETURNING user_id
+    ;
+"#;
+
+const ADMIN_EXISTS_QUERY: &str = r#"
+    SELECT EXISTS (SELECT 1 FROM accounts WHERE role = 'admin');
+"#;
+
+/// Make the account with `auth.initial_admin_email` an admin, unless an admin already exists.
+/// Admins cannot be appointed at `PUT /admin/accounts/{uuid}/role`, so without this no one could
+/// use the admin endpoints.
+pub async fn bootstrap_admin(
+	pool: &PersistentConnectionPool,
+	settings: &AuthSettings,
+) -> Result<(), ServiceError> {
+	let email = match &settings.initial_admin_email {
+		Some(email) => email,
+		None => return Ok(()),
+	};
+
+	let client = pool.get().await?;
+	let statement = client.prepare(BOOTSTRAP_ADMIN_QUERY).await?;
+
+	match client.query_opt(&statement, &[email]).await? {
+		Some(row) => {
+			let user_id: Uuid = row.get(0);
+			info!("Made user `{}` the first admin", &user_id);
+		}
+		None => {
+			let statement = client.prepare(ADMIN_EXISTS_QUERY).await?;
+			let admin_exists: bool = client.query_one(&statement, &[]).await?.get(0);
+
+			if !admin_exists {
+				warn!(
+					"No admin exists, and no account with the verified email address `{}` was found \
+					 to make the first admin",
+					email
+				);
+			}
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,39 @@
+//! Meeting session moderation.
+
+use actix::Addr;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use log::info;
+use uuid::Uuid;
+
+use crate::auth::roles::{Authorized, Moderator};
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::leave::publish;
+use crate::meetings::lifecycle::end_meeting_session;
+use crate::service_errors::ServiceError;
+
+/// Handler for forcibly ending a meeting session at `DELETE /admin/meetings/{meeting_id}`.
+/// Connected participants are notified that the meeting session ended.
+pub async fn handle_end_meeting_session(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	auth: Authorized<Moderator>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	if !end_meeting_session(&client, &meeting_id).await? {
+		return Err(ServiceError::NotFound("No such meeting session found".to_string()).into());
+	}
+
+	publish(&hub, &meeting_id, MeetingEvent::SessionEnded);
+
+	info!(
+		"User `{}` ended meeting session `{}`",
+		&auth.identity.user_id, &meeting_id
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
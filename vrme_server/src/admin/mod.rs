@@ -0,0 +1,7 @@
+//! Moderation endpoints under `/admin`, restricted to moderators and admins.
+//!
+//! Moderators and admins may only act upon users with a lower role than their own, e.g. a
+//! moderator cannot ban another moderator.
+
+pub mod accounts;
+pub mod meetings;
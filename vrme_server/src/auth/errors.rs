@@ -35,7 +35,13 @@ pub enum AuthError {
 impl From<AuthError> for HttpResponse {
 	/// Transforms from a `AuthError` to a `HttpResonse`.
 	fn from(e: AuthError) -> Self {
-		match e {
+		e.error_response()
+	}
+}
+
+impl ResponseError for AuthError {
+	fn error_response(&self) -> HttpResponse {
+		match self {
 			AuthError::MissingCredentials(ref s) => {
 				HttpResponse::Unauthorized().json(make_error_message("missing-credentials", s))
 			}
@@ -70,5 +76,3 @@ impl From<DatabaseError> for AuthError {
 		AuthError::InternalServerError(e.to_string())
 	}
 }
-
-impl ResponseError for AuthError {}
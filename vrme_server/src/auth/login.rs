@@ -1,18 +1,19 @@
 //! Handles user login and `auth_token` issuing.
 
-use crate::auth::auth_token::AuthToken;
+use crate::auth::errors::AuthError;
+use crate::auth::sessions::{create_session, validate_device_label};
 use crate::database::postgresql::PersistentConnectionPool;
 use crate::service_errors::ServiceError;
+use crate::settings::{Argon2Settings, AuthSettings, Settings};
 use crate::types::client_hashed_password::ClientHashedPassword;
-use crate::types::hashed_password::{PBKDF2_ALGORITHM, PBKDF2_ITERATIONS};
-use actix_web::error::BlockingError;
+use crate::types::hashed_password::{HashedPassword, HASHED_PASSWORD_LEN};
+use actix_web::http::header;
 use actix_web::web;
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{Error, HttpRequest, HttpResponse, ResponseError};
 use deadpool_postgres::Client;
-use ring::pbkdf2;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::num::NonZeroU32;
 use uuid::Uuid;
 
 /// Required login payload – the user needs to login with their `email` and `hashed_password`.
@@ -22,6 +23,10 @@ pub struct LoginInfo {
 	pub email: String,
 	/// base64-encoded client-side-hashed password. Must be exactly `44` base64 characters.
 	pub hashed_password: String,
+	/// Optional label of the device being logged in on, e.g. `"VR headset"`, shown when listing
+	/// sessions at `GET /account/sessions`. At most `100` characters.
+	#[serde(default)]
+	pub device_label: Option<String>,
 }
 
 /// User login handler.
@@ -32,135 +37,190 @@ pub struct LoginInfo {
 ///
 /// ```json
 /// {
-///     "uuid": "xxxx-xxxxxxx-xxxxxx",
-///     "auth_token": "xxxxxxxxxxxxxxxxxx"
+///     "user_id": "xxxx-xxxxxxx-xxxxxx",
+///     "auth_token": "xxxxxxxxxxxxxxxxxx",
+///     "session_id": "xxxx-xxxxxxx-xxxxxx"
 /// }
 /// ```
 ///
-/// This is the definition of `crate::auth::auth_payload::AuthPayload`.
+/// `user_id` and `auth_token` make up `crate::auth::auth_payload::AuthPayload`.
 ///
 /// ## Important Side Effect
 ///
-/// A successful login will cause the `auth_token` used for the login to be refreshed in
-/// terms of its `last_used` datetime.
+/// Every successful login creates a new auth session with its own `auth_token`. Sessions on other
+/// devices stay logged in; they can be listed at `GET /account/sessions` and revoked at
+/// `DELETE /account/sessions/{session_id}`.
 pub async fn handle_login(
+	req: HttpRequest,
 	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
 	login_info: web::Json<LoginInfo>,
 ) -> HttpResponse {
+	let device_label = match validate_device_label(login_info.device_label.as_deref()) {
+		Ok(device_label) => device_label,
+		Err(e) => return e.error_response(),
+	};
+
+	let user_agent = req
+		.headers()
+		.get(header::USER_AGENT)
+		.and_then(|value| value.to_str().ok());
+
 	let client = match pool.get().await {
 		Ok(client) => client,
 		Err(e) => return e.error_response(),
 	};
 
-	let uuid =
-		match check_registration(&client, &login_info.email, &login_info.hashed_password).await {
-			Ok(uuid) => uuid,
+	let uuid = match check_registration(
+		&client,
+		&settings.auth,
+		&login_info.email,
+		&login_info.hashed_password,
+	)
+	.await
+	{
+		Ok(uuid) => uuid,
+		Err(e) => return HttpResponse::from_error(e),
+	};
+
+	let (session_id, auth_token) =
+		match create_session(&client, &settings.auth, &uuid, device_label, user_agent).await {
+			Ok(session) => session,
 			Err(e) => return e.error_response(),
 		};
 
-	let auth_token = match upsert_auth_session(&client, &uuid).await {
-		Ok(auth_token) => auth_token,
-		Err(e) => return e.error_response(),
-	};
-
-	make_success_response(&uuid, &auth_token)
+	make_success_response(&uuid, &auth_token, &session_id)
 }
 
 const GET_PREVIOUS_HASH_QUERY: &str = r#"
     SELECT
         user_id,
         password_hash,
-        salt
+        email_verified,
+        banned_at IS NOT NULL,
+        ban_reason,
+        deleted_at IS NOT NULL
     FROM accounts
     WHERE
         email = $1::VARCHAR(355)
     ;
 "#;
 
+/// Check the `email` + password combination. The stored password hash is verified with the
+/// algorithm and parameters it was computed with, and is then transparently recomputed if those
+/// are not the currently configured ones.
+///
+/// Banned users, and if `auth.require_email_verification` is enabled, users who have not verified
+/// their email address yet are refused with `403 Forbidden`.
 async fn check_registration(
 	client: &Client,
+	auth_settings: &AuthSettings,
 	email: &str,
 	client_hash: &str,
-) -> Result<Uuid, ServiceError> {
+) -> Result<Uuid, Error> {
+	let argon2_settings = &auth_settings.argon2;
 	let client_hash = ClientHashedPassword::new(client_hash)?.decode().await?;
 
-	let statement = client.prepare(GET_PREVIOUS_HASH_QUERY).await.unwrap();
-
-	let row = match client.query_one(&statement, &[&email]).await {
-		Ok(row) => row,
-		Err(_) => {
-			return Err(ServiceError::Unauthorized(
-				"The email and password combination is invalid".to_string(),
-			));
-		}
+	let statement = client
+		.prepare(GET_PREVIOUS_HASH_QUERY)
+		.await
+		.map_err(ServiceError::from)?;
+
+	let invalid_credentials =
+		|| ServiceError::Unauthorized("The email and password combination is invalid".to_string());
+
+	let row = match client
+		.query_opt(&statement, &[&email])
+		.await
+		.map_err(ServiceError::from)?
+	{
+		Some(row) => row,
+		None => return Err(invalid_credentials().into()),
 	};
 
 	let uuid: Uuid = row.get(0);
-	let previously_derived: Vec<u8> = row.get(1);
-	let salt: Vec<u8> = row.get(2);
-
-	web::block(move || {
-		pbkdf2::verify(
-			PBKDF2_ALGORITHM,
-			NonZeroU32::new(PBKDF2_ITERATIONS as u32).unwrap(),
-			&salt,
-			&client_hash,
-			&previously_derived,
+	let previous_hash = HashedPassword::from_phc_string(row.get(1));
+	let email_verified: bool = row.get(2);
+	let banned: bool = row.get(3);
+	let deleted: bool = row.get(5);
+
+	if !previous_hash.verify(&client_hash).await? {
+		return Err(invalid_credentials().into());
+	}
+
+	if banned {
+		let ban_reason: Option<String> = row.get(4);
+		return Err(AuthError::Banned(
+			ban_reason.unwrap_or_else(|| "this account has been banned".to_string()),
 		)
-	})
-	.await
-	.map_err(|e| match e {
-		BlockingError::Error(e) => e.into(),
-		BlockingError::Canceled => {
-			ServiceError::InternalServerError("Unexpectedly cancelled".to_string())
+		.into());
+	}
+
+	if deleted {
+		return Err(ServiceError::Forbidden(
+			"The account is scheduled for deletion; restore it at `POST /account/restore`"
+				.to_string(),
+		)
+		.into());
+	}
+
+	if auth_settings.require_email_verification && !email_verified {
+		return Err(ServiceError::Forbidden(
+			"The email address has not been verified yet".to_string(),
+		)
+		.into());
+	}
+
+	if previous_hash.needs_rehash(argon2_settings) {
+		// Failing to upgrade the hash must not fail the login; it is retried on the next login.
+		if let Err(e) =
+			rehash_password(client, argon2_settings, &uuid, &previous_hash, &client_hash).await
+		{
+			warn!("Failed to rehash password of user `{}`: {}", &uuid, e);
 		}
-	})?;
+	}
 
 	Ok(uuid)
 }
 
-const UPSERT_AUTH_SESSION_QUERY: &str = r#"
-    INSERT INTO auth_sessions
-        (user_id, auth_token, last_used)
-    VALUES
-        ($1::UUID, $2::VARCHAR(44), $3::TIMESTAMP)
-    ON CONFLICT
-        (user_id)
-    DO UPDATE SET
-        auth_token = EXCLUDED.auth_token,
-        last_used = EXCLUDED.last_used
-    RETURNING
-        user_id,
-        auth_token,
-        last_used
+const REHASH_PASSWORD_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        password_hash = $3::TEXT
+    WHERE
+        user_id = $1::UUID AND
+        password_hash = $2::TEXT
     ;
 "#;
 
-/// Either creates a new `auth_session`, or refreshes an existing session with a new `auth_token`.
-async fn upsert_auth_session(client: &Client, user_id: &Uuid) -> Result<String, ServiceError> {
-	let auth_token = AuthToken::new().await?.token();
-	let auth_token = base64::encode(&auth_token);
-	let last_used = chrono::Utc::now().naive_utc();
-
-	let statement = client.prepare(UPSERT_AUTH_SESSION_QUERY).await.unwrap();
-
-	let rows = client
-		.query(&statement, &[user_id, &auth_token, &last_used])
+/// Replace the password hash of the user with one computed with the current parameters, unless
+/// the password was changed in the meantime.
+async fn rehash_password(
+	client: &Client,
+	argon2_settings: &Argon2Settings,
+	user_id: &Uuid,
+	previous_hash: &HashedPassword,
+	client_hash: &[u8; HASHED_PASSWORD_LEN],
+) -> Result<(), ServiceError> {
+	let new_hash = HashedPassword::new(client_hash, argon2_settings).await?;
+
+	let statement = client.prepare(REHASH_PASSWORD_QUERY).await?;
+	client
+		.execute(
+			&statement,
+			&[user_id, &previous_hash.as_str(), &new_hash.as_str()],
+		)
 		.await?;
 
-	if rows.is_empty() {
-		Err(ServiceError::InternalServerError(
-			"Failed to create new auth session".to_string(),
-		))
-	} else {
-		Ok(auth_token)
-	}
+	debug!("Rehashed password of user `{}`", user_id);
+	Ok(())
 }
 
-fn make_success_response(user_id: &Uuid, auth_token: &str) -> HttpResponse {
+fn make_success_response(user_id: &Uuid, auth_token: &str, session_id: &Uuid) -> HttpResponse {
 	let message = json!({
 		"user_id": user_id,
-		"auth_token": auth_token
+		"auth_token": auth_token,
+		"session_id": session_id
 	});
 
 	HttpResponse::Created().json(message)
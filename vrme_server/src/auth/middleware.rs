@@ -2,16 +2,19 @@
 
 use crate::auth::auth_payload::AuthPayload;
 use crate::auth::errors::AuthError;
+use crate::auth::roles::Identity;
+use crate::auth::sessions::{delete_session, find_session, touch_session, AuthSession};
 use crate::database::postgresql::PersistentConnectionPool;
 use crate::service_errors::ServiceError;
-use crate::settings::Settings;
+use crate::settings::{AuthSettings, Settings};
 use actix_web::dev::ServiceRequest;
 use actix_web::Error as ActixError;
+use actix_web::HttpMessage;
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use base64::DecodeError;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use deadpool_postgres::Client;
-use log::debug;
+use log::{debug, error};
 use serde_json::Error as JsonError;
 use std::convert::From;
 
@@ -24,7 +27,14 @@ use std::convert::From;
 /// - If the `auth_token` associated with the `uuid` exists, but is outdated, the request will be
 ///   rejected as the client shall `POST /login` again.
 /// - If the `auth_token` associated with the `uuid` exists and has not expired yet, the request
-///   will be accepted AND the `auth_token`'s validity duration will be refreshed.
+///   will be accepted AND the `auth_token`'s validity duration will be refreshed. To spare the
+///   database a write on every request, `last_used` is only written if it is more than
+///   `auth.last_used_refresh_interval` seconds old.
+/// - If `auth.max_session_lifetime` is set, the `auth_token` expires that many hours after login
+///   regardless of its validity duration.
+/// - If the user has been banned, the request is rejected with `403 Forbidden`.
+/// - Accepted requests carry the `Identity` of the user in their extensions, for use by the
+///   `Identity` and `crate::auth::roles::Authorized` extractors.
 pub async fn identity_validator(
 	req: ServiceRequest,
 	credentials: BearerAuth,
@@ -37,58 +47,84 @@ pub async fn identity_validator(
 	let pool = req.app_data::<PersistentConnectionPool>().unwrap();
 	let client = pool.get().await?;
 
-	let last_used = match find_auth_session(&client, &auth_payload).await {
-		Ok(last_used) => last_used,
-		Err(_) => {
+	let identity = authenticate(&client, &settings.auth, &auth_payload).await?;
+	req.extensions_mut().insert(identity);
+
+	Ok(req)
+}
+
+/// Check the auth session identified by `auth_payload` as described for `identity_validator`, and
+/// return the identity of its user.
+pub async fn authenticate(
+	client: &Client,
+	auth_settings: &AuthSettings,
+	auth_payload: &AuthPayload,
+) -> Result<Identity, ActixError> {
+	let found = match find_session(client, auth_settings, auth_payload).await {
+		Ok(Some(found)) => found,
+		Ok(None) => {
 			return Err(ServiceError::Unauthorized(
 				"No matching auth session found with the given `uuid`".to_string(),
 			)
 			.into())
 		}
+		Err(e) => {
+			error!(
+				"Failed to look up the auth session of user {}: {}",
+				&auth_payload.uuid, &e
+			);
+			return Err(e.into());
+		}
 	};
-	let time_since = Utc::now().signed_duration_since(last_used).num_hours();
 
-	if time_since > settings.auth.auth_token_validity_duration as i64 {
-		Err(AuthError::AuthTokenExpired("`auth_token` has expired; login again".to_string()).into())
-	} else {
-		Ok(req)
+	let session = &found.session;
+
+	if found.banned {
+		return Err(AuthError::Banned(
+			found
+				.ban_reason
+				.unwrap_or_else(|| "this account has been banned".to_string()),
+		)
+		.into());
 	}
-}
 
-const FIND_AUTH_SESSION_QUERY: &str = r#"
-    SELECT
-        user_id,
-        auth_token,
-        last_used
-    FROM auth_sessions
-    WHERE
-        user_id = $1::UUID AND
-        auth_token = $2::VARCHAR(44);
-"#;
-
-async fn find_auth_session(
-	client: &Client,
-	auth_info: &AuthPayload,
-) -> Result<DateTime<Utc>, AuthError> {
-	let statement = client.prepare(FIND_AUTH_SESSION_QUERY).await.unwrap();
-
-	let rows = client
-		.query(&statement, &[&auth_info.uuid, &auth_info.auth_token])
-		.await
-		.map_err(|e| AuthError::InternalServerError(e.to_string()))?;
-
-	if rows.is_empty() {
-		// We did not find a matching auth session.
-		return Err(AuthError::InvalidAuthToken(
-			"No matching auth session found, try to login again".to_string(),
-		));
-	} else {
-		// We found a matching auth session.
-		let last_used: NaiveDateTime = rows[0].get(2);
-		// We store all datetimes in `UTC+0` timezone.
-		let last_used = DateTime::from_utc(last_used, Utc);
-		return Ok(last_used);
+	// We store all datetimes in `UTC+0` timezone.
+	let now = Utc::now().naive_utc();
+
+	if is_expired(auth_settings, session, &now) {
+		delete_session(client, &auth_payload.uuid, &session.session_id).await?;
+		return Err(AuthError::AuthTokenExpired(
+			"`auth_token` has expired; login again".to_string(),
+		)
+		.into());
+	}
+
+	if now.signed_duration_since(session.last_used)
+		>= Duration::seconds(auth_settings.last_used_refresh_interval as i64)
+	{
+		touch_session(client, &session.session_id, &now).await?;
 	}
+
+	Ok(Identity {
+		user_id: auth_payload.uuid,
+		session_id: session.session_id,
+		role: found.role,
+	})
+}
+
+/// Whether the auth session has not been used for longer than its validity duration, or has
+/// outlived the maximum session lifetime.
+fn is_expired(auth_settings: &AuthSettings, session: &AuthSession, now: &NaiveDateTime) -> bool {
+	let idle_expired = now.signed_duration_since(session.last_used)
+		> Duration::hours(auth_settings.auth_token_validity_duration as i64);
+
+	let lifetime_expired = auth_settings
+		.max_session_lifetime
+		.is_some_and(|max_lifetime| {
+			now.signed_duration_since(session.created_at) > Duration::hours(max_lifetime as i64)
+		});
+
+	idle_expired || lifetime_expired
 }
 
 impl From<DecodeError> for AuthError {
@@ -6,3 +6,7 @@ pub mod errors;
 pub mod login;
 pub mod logout;
 pub mod middleware;
+pub mod one_time_tokens;
+pub mod roles;
+pub mod sessions;
+pub mod token_hash;
@@ -0,0 +1,141 @@
+//! Single-use tokens sent to users out of band, e.g. by email to prove that they own their email
+//! address.
+//!
+//! A token is 32 random bytes, URL-safe base64-encoded. Only its keyed hash (see
+//! `crate::auth::token_hash`) is stored, together with what it may be used for and when it
+//! expires. Consuming a token deletes it, so that it can be used at most once.
+
+use crate::auth::auth_token::AuthToken;
+use crate::auth::token_hash::hash_auth_token;
+use crate::service_errors::ServiceError;
+use crate::settings::AuthSettings;
+use chrono::{Duration, NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use uuid::Uuid;
+
+/// What a one-time token may be used for. A token issued for one purpose cannot be used for
+/// another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenPurpose {
+	/// Verify the email address of an account.
+	EmailVerification,
+	/// Choose a new password without knowing the current one.
+	PasswordReset,
+	/// Confirm a new email address for an account. Issued with the new email address as payload.
+	EmailChange,
+}
+
+impl TokenPurpose {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::EmailVerification => "email-verification",
+			Self::PasswordReset => "password-reset",
+			Self::EmailChange => "email-change",
+		}
+	}
+}
+
+const DELETE_PREVIOUS_TOKENS_QUERY: &str = r#"
+    DELETE FROM one_time_tokens
+    WHERE
+        user_id = $1::UUID AND
+        purpose = $2::VARCHAR(32)
+    ;
+"#;
+
+const ISSUE_TOKEN_QUERY: &str = r#"
+    INSERT INTO one_time_tokens
+        (token_hash, user_id, purpose, payload, created_at, expires_at)
+    VALUES
+        ($1::BYTEA, $2::UUID, $3::VARCHAR(32), $4::TEXT, $5::TIMESTAMP, $6::TIMESTAMP)
+    ;
+"#;
+
+/// Issue a new token for `purpose` to the user, valid for `validity`, and return it. Tokens
+/// previously issued to the user for the same purpose are revoked. `payload` is handed back when
+/// the token is consumed.
+pub async fn issue_token(
+	client: &Client,
+	auth_settings: &AuthSettings,
+	user_id: &Uuid,
+	purpose: TokenPurpose,
+	payload: Option<&str>,
+	validity: Duration,
+) -> Result<String, ServiceError> {
+	let token = base64::encode_config(AuthToken::new().await?.token(), base64::URL_SAFE_NO_PAD);
+	let token_hash = hash_auth_token(auth_settings, &token);
+	let now = Utc::now().naive_utc();
+	let expires_at = now + validity;
+
+	let statement = client.prepare(DELETE_PREVIOUS_TOKENS_QUERY).await?;
+	client
+		.execute(&statement, &[user_id, &purpose.as_str()])
+		.await?;
+
+	let statement = client.prepare(ISSUE_TOKEN_QUERY).await?;
+	client
+		.execute(
+			&statement,
+			&[
+				&token_hash,
+				user_id,
+				&purpose.as_str(),
+				&payload,
+				&now,
+				&expires_at,
+			],
+		)
+		.await?;
+
+	Ok(token)
+}
+
+const CONSUME_TOKEN_QUERY: &str = r#"
+    DELETE FROM one_time_tokens
+    WHERE
+        token_hash = $1::BYTEA AND
+        purpose = $2::VARCHAR(32)
+    RETURNING
+        user_id,
+        payload,
+        expires_at
+    ;
+"#;
+
+/// A consumed one-time token.
+#[derive(Debug)]
+pub struct ConsumedToken {
+	/// User the token was issued to.
+	pub user_id: Uuid,
+	/// Payload the token was issued with.
+	pub payload: Option<String>,
+}
+
+/// Consume a token issued for `purpose`. Returns `None` if the token does not exist, was issued
+/// for another purpose, was already used or has expired.
+pub async fn consume_token(
+	client: &Client,
+	auth_settings: &AuthSettings,
+	token: &str,
+	purpose: TokenPurpose,
+) -> Result<Option<ConsumedToken>, ServiceError> {
+	let token_hash = hash_auth_token(auth_settings, token.trim());
+
+	let statement = client.prepare(CONSUME_TOKEN_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[&token_hash, &purpose.as_str()])
+		.await?;
+
+	Ok(row.and_then(|row| {
+		let expires_at: NaiveDateTime = row.get(2);
+
+		if expires_at < Utc::now().naive_utc() {
+			None
+		} else {
+			Some(ConsumedToken {
+				user_id: row.get(0),
+				payload: row.get(1),
+			})
+		}
+	}))
+}
@@ -0,0 +1,197 @@
+//! User roles and role-based access control.
+//!
+//! Every account has exactly one `Role`. Roles are ordered: a moderator may do everything a user
+//! may do, and an admin may do everything a moderator may do.
+//!
+//! Handlers require a minimum role by taking an `Authorized<R>` argument, e.g.
+//! `Authorized<Moderator>`, which rejects the request with `403 Forbidden` if the user's role is
+//! lower. `Authorized<R>` and `Identity` rely on the identity established by
+//! `crate::auth::middleware::identity_validator`, so handlers using them must be wrapped in the
+//! authentication middleware. Public handlers which behave differently for logged-in users take an
+//! `OptionalIdentity` instead.
+
+use crate::auth::auth_payload::AuthPayload;
+use crate::auth::middleware::authenticate;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::service_errors::ServiceError;
+use crate::settings::Settings;
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use futures::future::{ready, FutureExt, LocalBoxFuture, Ready};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Role of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+	/// Regular user.
+	User,
+	/// May search accounts, ban and unban users, log them out and end meeting sessions.
+	Moderator,
+	/// May additionally change the roles of other users.
+	Admin,
+}
+
+impl Role {
+	/// Name of the role as stored in `accounts.role`.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::User => "user",
+			Self::Moderator => "moderator",
+			Self::Admin => "admin",
+		}
+	}
+}
+
+impl FromStr for Role {
+	type Err = ServiceError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"user" => Ok(Self::User),
+			"moderator" => Ok(Self::Moderator),
+			"admin" => Ok(Self::Admin),
+			other => Err(ServiceError::InternalServerError(format!(
+				"Unknown role `{}`",
+				other
+			))),
+		}
+	}
+}
+
+/// Identity of the user a request was authenticated as, inserted into the request extensions by
+/// the authentication middleware.
+#[derive(Debug, Clone)]
+pub struct Identity {
+	pub user_id: Uuid,
+	pub session_id: Uuid,
+	pub role: Role,
+}
+
+impl FromRequest for Identity {
+	type Error = Error;
+	type Future = Ready<Result<Self, Self::Error>>;
+	type Config = ();
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		ready(req.extensions().get::<Identity>().cloned().ok_or_else(|| {
+			ServiceError::Unauthorized("Authentication is required".to_string()).into()
+		}))
+	}
+}
+
+/// Extractor for the identity of the user if the request carries an `Authorization` header, for
+/// handlers which are not wrapped in the authentication middleware. A request whose credentials are
+/// invalid is rejected, as by the middleware, rather than treated as anonymous.
+pub struct OptionalIdentity(pub Option<Identity>);
+
+impl FromRequest for OptionalIdentity {
+	type Error = Error;
+	type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+	type Config = ();
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		let req = req.clone();
+
+		async move {
+			if !req.headers().contains_key(AUTHORIZATION) {
+				return Ok(OptionalIdentity(None));
+			}
+
+			let credentials = BearerAuth::extract(&req).await?;
+			let auth_payload = AuthPayload::from_bearer_auth(&credentials)?;
+			let settings = web::Data::<Settings>::extract(&req).await?;
+			let pool = web::Data::<PersistentConnectionPool>::extract(&req).await?;
+			let client = pool.get().await?;
+
+			let identity = authenticate(&client, &settings.auth, &auth_payload).await?;
+			Ok(OptionalIdentity(Some(identity)))
+		}
+		.boxed_local()
+	}
+}
+
+/// Minimum role required by an `Authorized<R>` extractor.
+pub trait RequiredRole {
+	const ROLE: Role;
+}
+
+/// Requires the `Role::Moderator` role or higher.
+pub struct Moderator;
+
+impl RequiredRole for Moderator {
+	const ROLE: Role = Role::Moderator;
+}
+
+/// Requires the `Role::Admin` role.
+pub struct Admin;
+
+impl RequiredRole for Admin {
+	const ROLE: Role = Role::Admin;
+}
+
+/// Extractor for the authentication payload of a user whose role is at least `R::ROLE`.
+pub struct Authorized<R: RequiredRole> {
+	pub auth_payload: AuthPayload,
+	pub identity: Identity,
+	required_role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> FromRequest for Authorized<R> {
+	type Error = Error;
+	type Future = Ready<Result<Self, Self::Error>>;
+	type Config = ();
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		ready(authorize(req).map_err(Error::from))
+	}
+}
+
+fn authorize<R: RequiredRole>(req: &HttpRequest) -> Result<Authorized<R>, ServiceError> {
+	let unauthorized = || ServiceError::Unauthorized("Authentication is required".to_string());
+
+	let auth = BearerAuth::extract(req)
+		.into_inner()
+		.map_err(|_| unauthorized())?;
+	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
+
+	let identity = req
+		.extensions()
+		.get::<Identity>()
+		.cloned()
+		.filter(|identity| identity.user_id == auth_payload.uuid)
+		.ok_or_else(unauthorized)?;
+
+	if identity.role < R::ROLE {
+		return Err(ServiceError::Forbidden(format!(
+			"This action requires the `{}` role",
+			R::ROLE.as_str()
+		)));
+	}
+
+	Ok(Authorized {
+		auth_payload,
+		identity,
+		required_role: PhantomData,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_roles_are_ordered() {
+		assert!(Role::User < Role::Moderator);
+		assert!(Role::Moderator < Role::Admin);
+
+		for role in &[Role::User, Role::Moderator, Role::Admin] {
+			assert_eq!(role.as_str().parse::<Role>().unwrap(), *role);
+		}
+	}
+}
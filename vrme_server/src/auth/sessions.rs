@@ -0,0 +1,302 @@
+//! Auth sessions. A user has one auth session per device they are logged in on, each with its own
+//! `auth_token`, so that logging in on one device does not log out the others.
+
+use crate::auth::auth_payload::AuthPayload;
+use crate::auth::auth_token::AuthToken;
+use crate::auth::roles::Role;
+use crate::auth::token_hash::{hash_auth_token, verify_auth_token_hash};
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::service_errors::ServiceError;
+use crate::settings::{AuthSettings, Settings};
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// Upper limit on the length of a device label in characters.
+pub const DEVICE_LABEL_MAX_LEN: usize = 100;
+
+/// Upper limit on the length of a stored `User-Agent` in characters. Longer user agents are
+/// truncated.
+pub const USER_AGENT_MAX_LEN: usize = 512;
+
+/// An auth session as listed to its user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthSession {
+	pub session_id: Uuid,
+	/// Label chosen by the client at login, e.g. `"VR headset"`.
+	pub device_label: Option<String>,
+	/// `User-Agent` of the client at login.
+	pub user_agent: Option<String>,
+	/// When the user logged in (UTC).
+	pub created_at: NaiveDateTime,
+	/// When the session was last used to authenticate (UTC).
+	pub last_used: NaiveDateTime,
+	/// Whether this is the session the request listing the sessions was authenticated with.
+	pub current: bool,
+}
+
+impl AuthSession {
+	fn from_row(row: &Row) -> Self {
+		Self {
+			session_id: row.get(0),
+			device_label: row.get(1),
+			user_agent: row.get(2),
+			created_at: row.get(3),
+			last_used: row.get(4),
+			current: row.get(5),
+		}
+	}
+}
+
+/// Handler for listing the auth sessions of the user at `GET /account/sessions`, most recently used
+/// first.
+///
+/// ## Success Response
+///
+/// ```json
+/// [
+///     {
+///         "session_id": "123e4567-e89b-12d3-a456-426655440000",
+///         "device_label": "VR headset",
+///         "user_agent": "VRME/1.0",
+///         "created_at": "2020-04-01T12:00:00",
+///         "last_used": "2020-04-02T08:30:00",
+///         "current": true
+///     }
+/// ]
+/// ```
+pub async fn handle_list_sessions(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
+	let client = pool.get().await?;
+	let sessions = list_sessions(&client, &settings.auth, &auth_payload).await?;
+	Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Handler for revoking one of the user's auth sessions at
+/// `DELETE /account/sessions/{session_id}`, e.g. to log out a lost device. The `auth_token` of the
+/// session is invalidated immediately.
+pub async fn handle_revoke_session(
+	pool: web::Data<PersistentConnectionPool>,
+	session_id: web::Path<Uuid>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
+	let client = pool.get().await?;
+
+	if delete_session(&client, &auth_payload.uuid, &session_id).await? {
+		Ok(HttpResponse::NoContent().finish())
+	} else {
+		Err(ServiceError::NotFound("No such auth session found".to_string()).into())
+	}
+}
+
+/// Check that a device label supplied by the client is not too long. Blank labels are treated as
+/// absent.
+pub fn validate_device_label(device_label: Option<&str>) -> Result<Option<&str>, ServiceError> {
+	match device_label.map(str::trim) {
+		Some(label) if label.chars().count() > DEVICE_LABEL_MAX_LEN => Err(
+			ServiceError::BadRequest("`device_label` is too long".to_string()),
+		),
+		Some(label) if !label.is_empty() => Ok(Some(label)),
+		_ => Ok(None),
+	}
+}
+
+const CREATE_SESSION_QUERY: &str = r#"
+    INSERT INTO auth_sessions
+        (session_id, user_id, token_hash, device_label, user_agent, created_at, last_used)
+    VALUES
+        ($1::UUID, $2::UUID, $3::BYTEA, $4::VARCHAR(100), $5::VARCHAR(512), $6::TIMESTAMP,
+         $6::TIMESTAMP)
+    ;
+"#;
+
+/// Create a new auth session for the user, returning its id and the base64-encoded `auth_token`
+/// issued for it. Only the hash of the `auth_token` is stored.
+pub async fn create_session(
+	client: &Client,
+	auth_settings: &AuthSettings,
+	user_id: &Uuid,
+	device_label: Option<&str>,
+	user_agent: Option<&str>,
+) -> Result<(Uuid, String), ServiceError> {
+	let session_id = Uuid::new_v4();
+	let auth_token = base64::encode(AuthToken::new().await?.token());
+	let token_hash = hash_auth_token(auth_settings, &auth_token);
+	let user_agent = user_agent.map(|s| s.chars().take(USER_AGENT_MAX_LEN).collect::<String>());
+	let now = chrono::Utc::now().naive_utc();
+
+	let statement = client.prepare(CREATE_SESSION_QUERY).await?;
+	client
+		.query(
+			&statement,
+			&[
+				&session_id,
+				user_id,
+				&token_hash,
+				&device_label,
+				&user_agent,
+				&now,
+			],
+		)
+		.await?;
+
+	Ok((session_id, auth_token))
+}
+
+/// An auth session matching a `uuid` + `auth_token` pair, along with the standing of the user it
+/// belongs to.
+#[derive(Debug)]
+pub struct FoundSession {
+	pub session: AuthSession,
+	pub role: Role,
+	/// Whether the user is banned.
+	pub banned: bool,
+	/// Reason given by the moderator who banned the user.
+	pub ban_reason: Option<String>,
+}
+
+const FIND_SESSION_QUERY: &str = r#"
+    SELECT
+        auth_sessions.session_id,
+        auth_sessions.device_label,
+        auth_sessions.user_agent,
+        auth_sessions.created_at,
+        auth_sessions.last_used,
+        TRUE,
+        auth_sessions.user_id,
+        auth_sessions.token_hash,
+        accounts.role,
+        accounts.banned_at IS NOT NULL,
+        accounts.ban_reason
+    FROM auth_sessions
+    INNER JOIN accounts ON accounts.user_id = auth_sessions.user_id
+    WHERE
+        auth_sessions.token_hash = $1::BYTEA
+    ;
+"#;
+
+/// Find the auth session matching the `uuid` + `auth_token` pair, if any.
+pub async fn find_session(
+	client: &Client,
+	auth_settings: &AuthSettings,
+	auth_payload: &AuthPayload,
+) -> Result<Option<FoundSession>, ServiceError> {
+	let token_hash = hash_auth_token(auth_settings, &auth_payload.auth_token);
+
+	let statement = client.prepare(FIND_SESSION_QUERY).await?;
+	let rows = client.query(&statement, &[&token_hash]).await?;
+
+	let row = match rows.first().filter(|row| {
+		let user_id: Uuid = row.get(6);
+		let stored_hash: Vec<u8> = row.get(7);
+		user_id == auth_payload.uuid && verify_auth_token_hash(&stored_hash, &token_hash)
+	}) {
+		Some(row) => row,
+		None => return Ok(None),
+	};
+
+	let role: &str = row.get(8);
+
+	Ok(Some(FoundSession {
+		session: AuthSession::from_row(row),
+		role: role.parse()?,
+		banned: row.get(9),
+		ban_reason: row.get(10),
+	}))
+}
+
+const TOUCH_SESSION_QUERY: &str = r#"
+    UPDATE auth_sessions
+    SET
+        last_used = $2::TIMESTAMP
+    WHERE
+        session_id = $1::UUID AND
+        last_used < $2::TIMESTAMP
+    ;
+"#;
+
+/// Record that the auth session was just used to authenticate, restarting its validity duration.
+pub async fn touch_session(
+	client: &Client,
+	session_id: &Uuid,
+	now: &NaiveDateTime,
+) -> Result<(), ServiceError> {
+	let statement = client.prepare(TOUCH_SESSION_QUERY).await?;
+	client.execute(&statement, &[session_id, now]).await?;
+	Ok(())
+}
+
+const LIST_SESSIONS_QUERY: &str = r#"
+    SELECT
+        session_id,
+        device_label,
+        user_agent,
+        created_at,
+        last_used,
+        token_hash = $2::BYTEA
+    FROM auth_sessions
+    WHERE
+        user_id = $1::UUID
+    ORDER BY
+        last_used DESC
+    ;
+"#;
+
+async fn list_sessions(
+	client: &Client,
+	auth_settings: &AuthSettings,
+	auth_payload: &AuthPayload,
+) -> Result<Vec<AuthSession>, ServiceError> {
+	let token_hash = hash_auth_token(auth_settings, &auth_payload.auth_token);
+
+	let statement = client.prepare(LIST_SESSIONS_QUERY).await?;
+	let rows = client
+		.query(&statement, &[&auth_payload.uuid, &token_hash])
+		.await?;
+
+	Ok(rows.iter().map(AuthSession::from_row).collect())
+}
+
+const DELETE_ALL_SESSIONS_QUERY: &str = r#"
+    DELETE FROM auth_sessions
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+/// Delete all auth sessions of the user, logging them out on every device. Returns the number of
+/// sessions deleted.
+pub async fn delete_all_sessions(client: &Client, user_id: &Uuid) -> Result<u64, ServiceError> {
+	let statement = client.prepare(DELETE_ALL_SESSIONS_QUERY).await?;
+	let deleted = client.execute(&statement, &[user_id]).await?;
+	Ok(deleted)
+}
+
+const DELETE_SESSION_QUERY: &str = r#"
+    DELETE FROM auth_sessions
+    WHERE
+        user_id = $1::UUID AND
+        session_id = $2::UUID
+    ;
+"#;
+
+/// Delete one of the user's auth sessions. Returns whether the session existed.
+pub async fn delete_session(
+	client: &Client,
+	user_id: &Uuid,
+	session_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let statement = client.prepare(DELETE_SESSION_QUERY).await?;
+	let deleted = client.execute(&statement, &[user_id, session_id]).await?;
+	Ok(deleted > 0)
+}
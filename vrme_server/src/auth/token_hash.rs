@@ -0,0 +1,36 @@
+//! Keyed hashing of `auth_token`s. Only the hash of each `auth_token` is stored in `auth_sessions`,
+//! so that read access to the database or a backup is not enough to impersonate users.
+
+use crate::settings::AuthSettings;
+use ring::{constant_time, hmac};
+
+/// Hash the base64-encoded `auth_token` with HMAC-SHA-256, keyed with `auth.token_secret`.
+pub fn hash_auth_token(auth_settings: &AuthSettings, auth_token: &str) -> Vec<u8> {
+	hash_with_secret(auth_settings.token_secret.as_bytes(), auth_token)
+}
+
+fn hash_with_secret(token_secret: &[u8], auth_token: &str) -> Vec<u8> {
+	let key = hmac::Key::new(hmac::HMAC_SHA256, token_secret);
+	hmac::sign(&key, auth_token.as_bytes()).as_ref().to_vec()
+}
+
+/// Compare a stored `auth_token` hash with a freshly computed one in constant time.
+pub fn verify_auth_token_hash(stored: &[u8], computed: &[u8]) -> bool {
+	constant_time::verify_slices_are_equal(stored, computed).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_hash_depends_on_secret() {
+		let token = "yTPPzA5H9yRSgGt0Br59LYJn07XSBl2NBK1Xu0+2mhE=";
+		let a = hash_with_secret(b"first secret", token);
+		let b = hash_with_secret(b"second secret", token);
+
+		assert_eq!(a.len(), 32);
+		assert!(verify_auth_token_hash(&a, &a));
+		assert!(!verify_auth_token_hash(&a, &b));
+	}
+}
@@ -1,28 +1,32 @@
 //! Delete user's avatar.
 
+use crate::auth::auth_payload::AuthPayload;
+use crate::avatars::avatar_key;
 use crate::service_errors::ServiceError;
+use crate::storage::BlobStorage;
 use actix_web::web;
 use actix_web::{Error, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
 use uuid::Uuid;
 
 // Delete's the avatar of the user with id `{uuid}`. This is a destructive operation and the deleted
 // avatar cannot be recovered by the client.
-pub async fn handle_delete_avatar(uuid: web::Path<Uuid>) -> Result<HttpResponse, Error> {
-	delete_file(&uuid).await?;
-	Ok(HttpResponse::NoContent().finish())
-}
+pub async fn handle_delete_avatar(
+	storage: web::Data<BlobStorage>,
+	auth: BearerAuth,
+	uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+	let auth_payload = AuthPayload::from_bearer_auth(&auth)?;
 
-async fn delete_file(uuid: &Uuid) -> Result<(), ServiceError> {
-	let raw_target_avatar_path = format!("/data/avatars/{}.png", &uuid);
-	let path = std::path::PathBuf::from(&raw_target_avatar_path);
+	if auth_payload.uuid != *uuid {
+		return Err(
+			ServiceError::Forbidden("Cannot delete someone else's avatar".to_string()).into(),
+		);
+	}
 
-	web::block(move || {
-		if path.exists() {
-			std::fs::remove_file(&path)
-		} else {
-			Ok(())
-		}
-	})
-	.await
-	.map_err(|e| e.into())
+	storage
+		.delete(&avatar_key(&uuid))
+		.await
+		.map_err(ServiceError::from)?;
+	Ok(HttpResponse::NoContent().finish())
 }
@@ -1,44 +1,42 @@
 //! Retrieve avatar.
 
+use crate::accounts::visibility::check_profile_visible;
+use crate::auth::roles::OptionalIdentity;
+use crate::avatars::avatar_key;
+use crate::database::postgresql::PersistentConnectionPool;
 use crate::service_errors::ServiceError;
-use actix_files as afs;
-use actix_web::http::header::ContentType;
+use crate::storage::{blob_response, BlobStorage};
 use actix_web::web;
-use actix_web::Error;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use bytes::Bytes;
 use uuid::Uuid;
 
+/// The default avatar, served for users who did not upload an avatar. It is compiled into the
+/// server so that it does not need to be provisioned in every blob storage backend.
+static DEFAULT_AVATAR: &[u8] = include_bytes!("../../data/avatars/default.png");
+
 /// Serves the avatar of the user with `uuid`. If the user did *not* upload an avatar yet, the
-/// default avatar is served instead.
+/// default avatar is served instead. Authentication is optional; whether the avatar is served
+/// depends on the visibility chosen by the user (see `crate::accounts::visibility`).
 ///
 /// ## Path Parameter
 ///
 /// 1. `{uuid}`: the unique id of the user whose avatar the client is trying to get.
-pub async fn handle_get_avatar(uuid: web::Path<Uuid>) -> Result<afs::NamedFile, Error> {
-	let file = get_avatar_or_default(&uuid).await?;
-
-	Ok(file
-		.use_last_modified(true)
-		.set_content_type(ContentType::png().0))
-}
+pub async fn handle_get_avatar(
+	req: HttpRequest,
+	pool: web::Data<PersistentConnectionPool>,
+	storage: web::Data<BlobStorage>,
+	uuid: web::Path<Uuid>,
+	identity: OptionalIdentity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	check_profile_visible(&client, &uuid, identity.0.as_ref()).await?;
 
-async fn get_avatar_or_default(uuid: &Uuid) -> Result<afs::NamedFile, ServiceError> {
-	let raw_path = build_path(&uuid);
-	let path = std::path::PathBuf::from(&raw_path);
-
-	// We delegate the responsibility of reading a PNG file to a thread pool to avoid blocking.
-	web::block(move || -> Result<afs::NamedFile, ServiceError> {
-		let file = if path.exists() {
-			afs::NamedFile::open(&path)?
-		} else {
-			afs::NamedFile::open("data/avatars/default.png")?
-		};
-
-		Ok(file.use_last_modified(true).use_etag(true))
-	})
-	.await
-	.map_err(|e| e.into())
-}
+	let avatar = storage
+		.get(&avatar_key(&uuid))
+		.await
+		.map_err(ServiceError::from)?
+		.unwrap_or_else(|| Bytes::from_static(DEFAULT_AVATAR));
 
-fn build_path(uuid: &Uuid) -> String {
-	format!("/data/avatars/{}.png", uuid)
+	Ok(blob_response(&req, avatar, "image/png"))
 }
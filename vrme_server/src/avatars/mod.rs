@@ -3,3 +3,10 @@
 pub mod delete_avatar;
 pub mod get_avatar;
 pub mod upload;
+
+use uuid::Uuid;
+
+/// Blob key of the avatar uploaded by the user with `uuid`.
+pub fn avatar_key(uuid: &Uuid) -> String {
+	format!("avatars/{}.png", uuid)
+}
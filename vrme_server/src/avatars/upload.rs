@@ -2,16 +2,16 @@
 //! active avatar.
 
 use crate::auth::auth_payload::AuthPayload;
+use crate::avatars::avatar_key;
 use crate::service_errors::ServiceError;
+use crate::storage::BlobStorage;
 use actix_multipart::{Field, Multipart};
 use actix_web::http::header::ContentType;
 use actix_web::web;
 use actix_web::{Error, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{StreamExt, TryStreamExt};
-use std::fs::File;
-use std::io::Write;
 use uuid::Uuid;
 
 /// Handler for avatar image upload.
@@ -34,6 +34,7 @@ use uuid::Uuid;
 ///
 /// - Multipart form specified by [RFC 7578](https://tools.ietf.org/html/rfc7578)
 pub async fn handle_upload_avatar(
+	storage: web::Data<BlobStorage>,
 	auth: BearerAuth,
 	uuid: web::Path<Uuid>,
 	mut payload: Multipart,
@@ -52,12 +53,12 @@ pub async fn handle_upload_avatar(
 		check_content_type_is_png(&field)?;
 		// We don't validate if the raw binary payload is in fact valid PNG. If the client uploads
 		// an invalid PNG, then upon request the client will receive the same invalid PNG.
-		let mut file = create_avatar_file(&auth_payload.uuid).await?;
+		let data = read_avatar(&mut field).await?;
 
-		while let Some(chunk) = field.next().await {
-			let data = chunk?;
-			file = write_to_avatar_file(file, data).await?;
-		}
+		storage
+			.put(&avatar_key(&auth_payload.uuid), data, "image/png")
+			.await
+			.map_err(ServiceError::from)?;
 	}
 
 	Ok(HttpResponse::Created().finish())
@@ -75,33 +76,22 @@ fn check_content_type_is_png(field: &Field) -> Result<(), ServiceError> {
 	}
 }
 
-async fn create_avatar_file(uuid: &Uuid) -> Result<File, ServiceError> {
-	// We save the avatar to a file with the user's `uuid` as filename.
-	let file_path = format!("data/avatars/{}.png", uuid);
+async fn read_avatar(field: &mut Field) -> Result<Bytes, Error> {
+	let mut data = BytesMut::new();
 
-	// Delegate blocking fs operation to the thread pool.
-	web::block(move || File::create(&file_path))
-		.await
-		.map_err(|_| {
-			ServiceError::InternalServerError(
-				"Encountered I/O error when trying to create avatar file".to_string(),
-			)
-		})
-}
+	while let Some(chunk) = field.next().await {
+		let chunk = chunk?;
 
-async fn write_to_avatar_file(mut file: File, data: Bytes) -> Result<File, ServiceError> {
-	web::block(move || {
-		let expected_file_size = file.metadata()?.len() + data.len() as u64;
-		if expected_file_size > AVATAR_SIZE_LIMIT {
-			Err(ServiceError::UnprocessableEntity(
-				"File size too large".to_string(),
-			))
-		} else {
-			file.write_all(&data).map(|_| file).map_err(|e| e.into())
+		if (data.len() + chunk.len()) as u64 > AVATAR_SIZE_LIMIT {
+			return Err(
+				ServiceError::UnprocessableEntity("File size too large".to_string()).into(),
+			);
 		}
-	})
-	.await
-	.map_err(|e| e.into())
+
+		data.extend_from_slice(&chunk);
+	}
+
+	Ok(data.freeze())
 }
 
 /// Upper limit on avatar file size in bytes.
@@ -14,6 +14,17 @@ pub enum DatabaseError {
 	/// database.
 	#[display(fmt = "failed to create connection pool: {}", "_0")]
 	PoolCreationError(String),
+	/// Failed to apply or inspect schema migrations.
+	#[display(fmt = "failed to migrate database schema: {}", "_0")]
+	MigrationError(String),
+	/// The database has been migrated by a newer version of the server than this binary knows
+	/// about; running against it could corrupt data.
+	#[display(
+		fmt = "database schema version {} is newer than the latest known version {}",
+		database,
+		binary
+	)]
+	SchemaTooNew { database: i32, binary: i32 },
 }
 
 impl From<PostgresPoolError> for DatabaseError {
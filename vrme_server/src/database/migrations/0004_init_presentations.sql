@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS presentations (
+	meeting_id UUID PRIMARY KEY REFERENCES meeting_sessions (meeting_id) ON DELETE CASCADE,
+	page_count INT NOT NULL CHECK (page_count > 0),
+	current_page INT NOT NULL CHECK (current_page >= 0 AND current_page < page_count),
+	version BIGINT NOT NULL,
+	updated_at TIMESTAMP NOT NULL
+);
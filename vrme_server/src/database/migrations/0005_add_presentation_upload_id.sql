@@ -0,0 +1,4 @@
+-- Pages of each upload are stored under their own blob key prefix, so that a new upload never
+-- overwrites the pages of the presentation currently being served. Presentations uploaded before
+-- this migration have no `upload_id` and keep their pages directly under the meeting's prefix.
+ALTER TABLE presentations ADD COLUMN IF NOT EXISTS upload_id UUID;
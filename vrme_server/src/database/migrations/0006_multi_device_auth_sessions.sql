@@ -0,0 +1,20 @@
+ALTER TABLE auth_sessions DROP CONSTRAINT IF EXISTS auth_sessions_pkey;
+
+ALTER TABLE auth_sessions
+	ADD COLUMN session_id UUID,
+	ADD COLUMN device_label VARCHAR(100),
+	ADD COLUMN user_agent VARCHAR(512),
+	ADD COLUMN created_at TIMESTAMP;
+
+-- `gen_random_uuid()` is only built in from PostgreSQL 13 onwards.
+UPDATE auth_sessions
+SET
+	session_id = md5(user_id::TEXT || random()::TEXT || clock_timestamp()::TEXT)::UUID,
+	created_at = last_used;
+
+ALTER TABLE auth_sessions
+	ALTER COLUMN session_id SET NOT NULL,
+	ALTER COLUMN created_at SET NOT NULL,
+	ADD PRIMARY KEY (session_id);
+
+CREATE INDEX IF NOT EXISTS auth_sessions_user_id_idx ON auth_sessions (user_id);
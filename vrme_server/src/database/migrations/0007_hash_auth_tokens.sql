@@ -0,0 +1,9 @@
+-- Sessions created before tokens were hashed hold plaintext tokens; they are all revoked, so every
+-- user has to login again once.
+DELETE FROM auth_sessions;
+
+ALTER TABLE auth_sessions
+	DROP COLUMN auth_token,
+	ADD COLUMN token_hash BYTEA NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS auth_sessions_token_hash_idx ON auth_sessions (token_hash);
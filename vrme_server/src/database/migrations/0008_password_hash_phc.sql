@@ -0,0 +1,22 @@
+-- Password hashes are stored as PHC strings that record their algorithm and parameters. Existing
+-- PBKDF2-HMAC-SHA256 hashes are converted to `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>`, with
+-- the salt and hash base64-encoded without padding.
+ALTER TABLE accounts
+	ADD COLUMN password_phc TEXT;
+
+UPDATE accounts
+SET
+	password_phc = '$pbkdf2-sha256$i=' || iteration_count
+		|| '$' || rtrim(replace(encode(salt, 'base64'), E'\n', ''), '=')
+		|| '$' || rtrim(replace(encode(password_hash, 'base64'), E'\n', ''), '=');
+
+ALTER TABLE accounts
+	DROP COLUMN iteration_count,
+	DROP COLUMN salt,
+	DROP COLUMN password_hash;
+
+ALTER TABLE accounts
+	RENAME COLUMN password_phc TO password_hash;
+
+ALTER TABLE accounts
+	ALTER COLUMN password_hash SET NOT NULL;
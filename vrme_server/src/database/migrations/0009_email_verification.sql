@@ -0,0 +1,20 @@
+-- Accounts created before email verification existed are treated as verified.
+ALTER TABLE accounts
+	ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE accounts
+SET
+	email_verified = TRUE;
+
+-- Single-use tokens emailed to users, e.g. to verify their email address. Like `auth_token`s, only
+-- keyed hashes of the tokens are stored.
+CREATE TABLE IF NOT EXISTS one_time_tokens (
+	token_hash BYTEA PRIMARY KEY,
+	user_id UUID NOT NULL,
+	purpose VARCHAR(32) NOT NULL,
+	payload TEXT,
+	created_at TIMESTAMP NOT NULL,
+	expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS one_time_tokens_user_id_idx ON one_time_tokens (user_id, purpose);
@@ -0,0 +1,5 @@
+ALTER TABLE accounts
+	ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT 'user'
+		CHECK (role IN ('user', 'moderator', 'admin')),
+	ADD COLUMN banned_at TIMESTAMP,
+	ADD COLUMN ban_reason VARCHAR(500);
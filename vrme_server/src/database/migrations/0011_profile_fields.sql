@@ -0,0 +1,5 @@
+ALTER TABLE accounts
+	ADD COLUMN display_name VARCHAR(100),
+	ADD COLUMN pronouns VARCHAR(100),
+	ADD COLUMN organisation VARCHAR(100),
+	ADD COLUMN locale VARCHAR(35);
@@ -0,0 +1,3 @@
+ALTER TABLE accounts
+	ADD COLUMN profile_visibility VARCHAR(16) NOT NULL DEFAULT 'authenticated'
+		CHECK (profile_visibility IN ('public', 'authenticated', 'participants'));
@@ -0,0 +1,8 @@
+-- The latest personal data export of each user. The archive itself is stored in the blob store.
+CREATE TABLE IF NOT EXISTS data_exports (
+	user_id UUID PRIMARY KEY REFERENCES accounts (user_id) ON DELETE CASCADE,
+	export_id UUID NOT NULL,
+	status VARCHAR(16) NOT NULL CHECK (status IN ('pending', 'ready', 'failed')),
+	requested_at TIMESTAMP NOT NULL,
+	completed_at TIMESTAMP
+);
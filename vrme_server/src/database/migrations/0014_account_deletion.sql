@@ -0,0 +1,46 @@
+-- Rows left behind by accounts deleted before foreign keys were enforced.
+DELETE FROM auth_sessions
+WHERE user_id NOT IN (SELECT user_id FROM accounts);
+
+DELETE FROM one_time_tokens
+WHERE user_id NOT IN (SELECT user_id FROM accounts);
+
+DELETE FROM meeting_sessions
+WHERE presenter NOT IN (SELECT user_id FROM accounts);
+
+UPDATE meeting_sessions
+SET
+	listeners = ARRAY(
+		SELECT listener
+		FROM unnest(listeners) AS listener
+		WHERE listener IN (SELECT user_id FROM accounts)
+	);
+
+ALTER TABLE auth_sessions
+	ADD CONSTRAINT auth_sessions_user_id_fkey
+		FOREIGN KEY (user_id) REFERENCES accounts (user_id) ON DELETE CASCADE;
+
+ALTER TABLE one_time_tokens
+	ADD CONSTRAINT one_time_tokens_user_id_fkey
+		FOREIGN KEY (user_id) REFERENCES accounts (user_id) ON DELETE CASCADE;
+
+ALTER TABLE meeting_sessions
+	ADD CONSTRAINT meeting_sessions_presenter_fkey
+		FOREIGN KEY (presenter) REFERENCES accounts (user_id) ON DELETE CASCADE;
+
+-- When the user asked for their account to be deleted, if it is kept for a grace period.
+ALTER TABLE accounts
+	ADD COLUMN deleted_at TIMESTAMP;
+
+-- Blobs to remove from the blob store, retried until they are removed.
+CREATE TABLE IF NOT EXISTS blob_deletion_jobs (
+	job_id BIGSERIAL PRIMARY KEY,
+	blob_key TEXT NOT NULL,
+	attempts INT NOT NULL DEFAULT 0,
+	next_attempt_at TIMESTAMP NOT NULL,
+	last_error TEXT,
+	created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS blob_deletion_jobs_next_attempt_at_idx
+	ON blob_deletion_jobs (next_attempt_at);
@@ -0,0 +1,14 @@
+-- Invites to a meeting session, minted by its presenter. `code` is the normalized join code,
+-- i.e. without separator.
+CREATE TABLE IF NOT EXISTS meeting_invites (
+	invite_id UUID PRIMARY KEY,
+	meeting_id UUID NOT NULL REFERENCES meeting_sessions (meeting_id) ON DELETE CASCADE,
+	code VARCHAR(16) NOT NULL UNIQUE,
+	created_at TIMESTAMP NOT NULL,
+	expires_at TIMESTAMP NOT NULL,
+	max_uses INTEGER CHECK (max_uses > 0),
+	uses INTEGER NOT NULL DEFAULT 0,
+	revoked_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS meeting_invites_meeting_id_idx ON meeting_invites (meeting_id);
@@ -0,0 +1,27 @@
+-- Participants of meeting sessions, one row per user and meeting session, replacing the
+-- `meeting_sessions.listeners` array. Leaving sets `left_at` instead of removing the row, which
+-- keeps the attendance history of the meeting session.
+CREATE TABLE IF NOT EXISTS meeting_participants (
+	meeting_id UUID NOT NULL REFERENCES meeting_sessions (meeting_id) ON DELETE CASCADE,
+	user_id UUID NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+	role VARCHAR(16) NOT NULL CHECK (role IN ('presenter', 'listener')),
+	joined_at TIMESTAMP NOT NULL,
+	left_at TIMESTAMP,
+	PRIMARY KEY (meeting_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS meeting_participants_user_id_idx ON meeting_participants (user_id);
+
+INSERT INTO meeting_participants (meeting_id, user_id, role, joined_at)
+SELECT meeting_id, presenter, 'presenter', started_at
+FROM meeting_sessions
+ON CONFLICT DO NOTHING;
+
+INSERT INTO meeting_participants (meeting_id, user_id, role, joined_at)
+SELECT DISTINCT meeting_sessions.meeting_id, listener, 'listener', meeting_sessions.started_at
+FROM meeting_sessions
+CROSS JOIN LATERAL unnest(meeting_sessions.listeners) AS listener
+INNER JOIN accounts ON accounts.user_id = listener
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE meeting_sessions DROP COLUMN IF EXISTS listeners;
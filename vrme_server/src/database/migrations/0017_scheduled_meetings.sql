@@ -0,0 +1,34 @@
+-- Scheduled meeting sessions. A meeting session without `scheduled_start` is ad-hoc: presenters
+-- have at most one ad-hoc meeting session but any number of scheduled ones.
+ALTER TABLE meeting_sessions
+	DROP CONSTRAINT IF EXISTS unique_presenter,
+	ADD COLUMN IF NOT EXISTS title VARCHAR(200),
+	ADD COLUMN IF NOT EXISTS description TEXT,
+	ADD COLUMN IF NOT EXISTS scheduled_start TIMESTAMP,
+	ADD COLUMN IF NOT EXISTS scheduled_end TIMESTAMP,
+	ADD COLUMN IF NOT EXISTS timezone VARCHAR(64),
+	ADD CONSTRAINT meeting_sessions_schedule_check CHECK (
+		(scheduled_start IS NULL AND scheduled_end IS NULL) OR
+		(scheduled_start IS NOT NULL AND scheduled_end > scheduled_start)
+	);
+
+CREATE UNIQUE INDEX IF NOT EXISTS meeting_sessions_ad_hoc_presenter_idx
+	ON meeting_sessions (presenter)
+	WHERE scheduled_start IS NULL;
+
+CREATE INDEX IF NOT EXISTS meeting_sessions_presenter_idx ON meeting_sessions (presenter);
+
+-- Users invited to a scheduled meeting session.
+CREATE TABLE IF NOT EXISTS meeting_invitees (
+	meeting_id UUID NOT NULL REFERENCES meeting_sessions (meeting_id) ON DELETE CASCADE,
+	user_id UUID NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+	invited_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (meeting_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS meeting_invitees_user_id_idx ON meeting_invitees (user_id);
+
+-- Keyed hash (see `auth_sessions.token_hash`) of the token which authenticates the user's
+-- calendar feed, for calendar applications which cannot send an `Authorization` header.
+ALTER TABLE accounts
+	ADD COLUMN IF NOT EXISTS calendar_token_hash BYTEA UNIQUE;
@@ -0,0 +1,21 @@
+-- Lifecycle of meeting sessions: `scheduled` until the presenter starts it, `live` while it takes
+-- place and `ended` afterwards. Ended meeting sessions are kept as history instead of being
+-- deleted.
+ALTER TABLE meeting_sessions
+	ADD COLUMN IF NOT EXISTS state VARCHAR(16) NOT NULL DEFAULT 'live'
+		CHECK (state IN ('scheduled', 'live', 'ended')),
+	ADD COLUMN IF NOT EXISTS ended_at TIMESTAMP;
+
+UPDATE meeting_sessions
+SET state = 'scheduled'
+WHERE scheduled_start IS NOT NULL;
+
+ALTER TABLE meeting_sessions
+	ADD CONSTRAINT meeting_sessions_ended_check CHECK ((state = 'ended') = (ended_at IS NOT NULL));
+
+-- Presenters have at most one ad-hoc meeting session which has not ended.
+DROP INDEX IF EXISTS meeting_sessions_ad_hoc_presenter_idx;
+
+CREATE UNIQUE INDEX IF NOT EXISTS meeting_sessions_ad_hoc_presenter_idx
+	ON meeting_sessions (presenter)
+	WHERE scheduled_start IS NULL AND state <> 'ended';
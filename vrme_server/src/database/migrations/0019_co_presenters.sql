@@ -0,0 +1,7 @@
+-- Co-presenters share the presentation controls of the presenter. Since the presenter can hand
+-- the meeting session over, `meeting_sessions.presenter` is the current presenter rather than the
+-- user who started the meeting session.
+ALTER TABLE meeting_participants
+	DROP CONSTRAINT IF EXISTS meeting_participants_role_check,
+	ADD CONSTRAINT meeting_participants_role_check
+		CHECK (role IN ('presenter', 'co-presenter', 'listener'));
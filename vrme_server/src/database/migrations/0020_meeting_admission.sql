@@ -0,0 +1,17 @@
+-- Admission control of meeting sessions. A NULL `max_participants` falls back to the server-wide
+-- `meetings.default_max_participants`. With `lobby` enabled, users joining with an invite wait in
+-- the lobby until the presenter admits or rejects them; a `locked` meeting session refuses them.
+ALTER TABLE meeting_sessions
+	ADD COLUMN IF NOT EXISTS max_participants INTEGER CHECK (max_participants > 0),
+	ADD COLUMN IF NOT EXISTS lobby BOOLEAN NOT NULL DEFAULT FALSE,
+	ADD COLUMN IF NOT EXISTS locked BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Users waiting to be admitted to a meeting session. Rejected users keep their row, so that they
+-- cannot ask again.
+CREATE TABLE IF NOT EXISTS meeting_lobby (
+	meeting_id UUID NOT NULL REFERENCES meeting_sessions (meeting_id) ON DELETE CASCADE,
+	user_id UUID NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+	requested_at TIMESTAMP NOT NULL,
+	rejected_at TIMESTAMP,
+	PRIMARY KEY (meeting_id, user_id)
+);
@@ -0,0 +1,11 @@
+-- When the presenter removed the participant from the meeting session. Cleared when they rejoin.
+ALTER TABLE meeting_participants
+	ADD COLUMN IF NOT EXISTS removed_at TIMESTAMP;
+
+-- Users who may not join a meeting session again.
+CREATE TABLE IF NOT EXISTS meeting_bans (
+	meeting_id UUID NOT NULL REFERENCES meeting_sessions (meeting_id) ON DELETE CASCADE,
+	user_id UUID NOT NULL REFERENCES accounts (user_id) ON DELETE CASCADE,
+	banned_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (meeting_id, user_id)
+);
@@ -0,0 +1,15 @@
+-- Who uploaded the presentation, which since co-presenters and handovers is not necessarily the
+-- presenter of the meeting session.
+ALTER TABLE presentations
+	ADD COLUMN IF NOT EXISTS uploaded_by UUID REFERENCES accounts (user_id) ON DELETE SET NULL;
+
+-- Best guess for existing presentations: the current presenter.
+UPDATE presentations
+SET uploaded_by = meeting_sessions.presenter
+FROM meeting_sessions
+INNER JOIN accounts ON accounts.user_id = meeting_sessions.presenter
+WHERE
+	presentations.meeting_id = meeting_sessions.meeting_id AND
+	presentations.uploaded_by IS NULL;
+
+CREATE INDEX IF NOT EXISTS presentations_uploaded_by_idx ON presentations (uploaded_by);
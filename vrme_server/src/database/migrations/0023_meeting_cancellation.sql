@@ -0,0 +1,18 @@
+-- Whether a meeting session was ended before it started, i.e. cancelled. Calendar feeds mark such
+-- meeting sessions as cancelled rather than dropping them.
+ALTER TABLE meeting_sessions
+	ADD COLUMN IF NOT EXISTS cancelled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Best guess for existing meeting sessions: the presenter only takes part once they start it.
+UPDATE meeting_sessions
+SET cancelled = TRUE
+WHERE
+	state = 'ended' AND
+	scheduled_start IS NOT NULL AND
+	NOT EXISTS (
+		SELECT 1
+		FROM meeting_participants
+		WHERE
+			meeting_participants.meeting_id = meeting_sessions.meeting_id AND
+			meeting_participants.role = 'presenter'
+	);
@@ -0,0 +1,373 @@
+//! Embedded, versioned schema migrations.
+//!
+//! Every migration is a plain SQL file under `src/database/migrations/` named
+//! `<version>_<name>.sql`, which is embedded into the binary at compile time and registered in
+//! `MIGRATIONS`. Applied versions are recorded in the `schema_migrations` table so that each
+//! migration is only ever applied once.
+//!
+//! # Adding a Migration
+//!
+//! 1. Create the file `<next_version>_<name>.sql` in this directory.
+//! 2. Append a matching `Migration` entry to the end of `MIGRATIONS`.
+//!
+//! Migrations that have already been released must *never* be edited; write a new migration
+//! instead.
+
+use super::error::DatabaseError;
+use super::postgresql::PersistentConnectionPool;
+
+use log::{info, warn};
+
+/// A single embedded schema migration.
+#[derive(Debug)]
+pub struct Migration {
+	/// Strictly increasing version number, starting from `1`.
+	pub version: i32,
+	/// Short human-readable name of the migration.
+	pub name: &'static str,
+	/// SQL statements to execute. They are executed within a single transaction.
+	pub sql: &'static str,
+}
+
+/// All known migrations, in ascending order of `version`.
+pub const MIGRATIONS: &[Migration] = &[
+	Migration {
+		version: 1,
+		name: "init_accounts",
+		sql: include_str!("0001_init_accounts.sql"),
+	},
+	Migration {
+		version: 2,
+		name: "init_auth_sessions",
+		sql: include_str!("0002_init_auth_sessions.sql"),
+	},
+	Migration {
+		version: 3,
+		name: "init_meeting_sessions",
+		sql: include_str!("0003_init_meeting_sessions.sql"),
+	},
+	Migration {
+		version: 4,
+		name: "init_presentations",
+		sql: include_str!("0004_init_presentations.sql"),
+	},
+	Migration {
+		version: 5,
+		name: "add_presentation_upload_id",
+		sql: include_str!("0005_add_presentation_upload_id.sql"),
+	},
+	Migration {
+		version: 6,
+		name: "multi_device_auth_sessions",
+		sql: include_str!("0006_multi_device_auth_sessions.sql"),
+	},
+	Migration {
+		version: 7,
+		name: "hash_auth_tokens",
+		sql: include_str!("0007_hash_auth_tokens.sql"),
+	},
+	Migration {
+		version: 8,
+		name: "password_hash_phc",
+		sql: include_str!("0008_password_hash_phc.sql"),
+	},
+	Migration {
+		version: 9,
+		name: "email_verification",
+		sql: include_str!("0009_email_verification.sql"),
+	},
+	Migration {
+		version: 10,
+		name: "roles_and_bans",
+		sql: include_str!("0010_roles_and_bans.sql"),
+	},
+	Migration {
+		version: 11,
+		name: "profile_fields",
+		sql: include_str!("0011_profile_fields.sql"),
+	},
+	Migration {
+		version: 12,
+		name: "profile_visibility",
+		sql: include_str!("0012_profile_visibility.sql"),
+	},
+	Migration {
+		version: 13,
+		name: "data_exports",
+		sql: include_str!("0013_data_exports.sql"),
+	},
+	Migration {
+		version: 14,
+		name: "account_deletion",
+		sql: include_str!("0014_account_deletion.sql"),
+	},
+	Migration {
+		version: 15,
+		name: "meeting_invites",
+		sql: include_str!("0015_meeting_invites.sql"),
+	},
+	Migration {
+		version: 16,
+		name: "meeting_participants",
+		sql: include_str!("0016_meeting_participants.sql"),
+	},
+	Migration {
+		version: 17,
+		name: "scheduled_meetings",
+		sql: include_str!("0017_scheduled_meetings.sql"),
+	},
+	Migration {
+		version: 18,
+		name: "meeting_lifecycle",
+		sql: include_str!("0018_meeting_lifecycle.sql"),
+	},
+	Migration {
+		version: 19,
+		name: "co_presenters",
+		sql: include_str!("0019_co_presenters.sql"),
+	},
+	Migration {
+		version: 20,
+		name: "meeting_admission",
+		sql: include_str!("0020_meeting_admission.sql"),
+	},
+	Migration {
+		version: 21,
+		name: "meeting_bans",
+		sql: include_str!("0021_meeting_bans.sql"),
+	},
+	Migration {
+		version: 22,
+		name: "presentation_uploader",
+		sql: include_str!("0022_presentation_uploader.sql"),
+	},
+	Migration {
+		version: 23,
+		name: "meeting_cancellation",
+		sql: include_str!("0023_meeting_cancellation.sql"),
+	},
+];
+
+/// Arbitrary key for the PostgreSQL advisory lock held while migrating, so that multiple server
+/// instances starting at the same time do not race to apply the same migrations.
+const MIGRATION_LOCK_KEY: i64 = 0x5652_4d45_4d49_4752;
+
+const CREATE_SCHEMA_MIGRATIONS_QUERY: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version INT PRIMARY KEY,
+        name VARCHAR(255) NOT NULL,
+        applied_at TIMESTAMP NOT NULL
+    );
+"#;
+
+const SCHEMA_MIGRATIONS_EXISTS_QUERY: &str = r#"
+    SELECT to_regclass('schema_migrations') IS NOT NULL;
+"#;
+
+const GET_APPLIED_VERSIONS_QUERY: &str = r#"
+    SELECT version
+    FROM schema_migrations
+    ORDER BY version ASC;
+"#;
+
+const RECORD_MIGRATION_QUERY: &str = r#"
+    INSERT INTO schema_migrations
+        (version, name, applied_at)
+    VALUES
+        ($1::INT, $2::VARCHAR(255), $3::TIMESTAMP);
+"#;
+
+const ACQUIRE_LOCK_QUERY: &str = "SELECT pg_advisory_lock($1::BIGINT);";
+
+const RELEASE_LOCK_QUERY: &str = "SELECT pg_advisory_unlock($1::BIGINT);";
+
+/// The latest schema version known to this binary.
+pub fn latest_version() -> i32 {
+	MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Applies all pending migrations in order.
+///
+/// # Errors
+///
+/// - `DatabaseError::SchemaTooNew` if the database has migrations applied that this binary does
+///   not know about, i.e. the database was migrated by a newer server version.
+/// - `DatabaseError::MigrationError` if a migration fails to apply. The failing migration is
+///   rolled back and no further migrations are attempted.
+pub async fn run_migrations(pool: &PersistentConnectionPool) -> Result<(), DatabaseError> {
+	let mut client = pool.get().await?;
+
+	client
+		.batch_execute(CREATE_SCHEMA_MIGRATIONS_QUERY)
+		.await
+		.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+	client
+		.execute(ACQUIRE_LOCK_QUERY, &[&MIGRATION_LOCK_KEY])
+		.await
+		.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+	let result = apply_pending_migrations(&mut client).await;
+
+	client
+		.execute(RELEASE_LOCK_QUERY, &[&MIGRATION_LOCK_KEY])
+		.await
+		.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+	result
+}
+
+async fn apply_pending_migrations(
+	client: &mut deadpool_postgres::Client,
+) -> Result<(), DatabaseError> {
+	let applied = get_applied_versions(client).await?;
+	let pending = pending_migrations(&applied, MIGRATIONS)?;
+
+	if pending.is_empty() {
+		info!(
+			"Database schema is up to date at version {}",
+			latest_version()
+		);
+		return Ok(());
+	}
+
+	for migration in pending {
+		info!(
+			"Applying migration {} ({})",
+			migration.version, migration.name
+		);
+
+		let transaction = client
+			.transaction()
+			.await
+			.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+		transaction
+			.batch_execute(migration.sql)
+			.await
+			.map_err(|e| {
+				DatabaseError::MigrationError(format!(
+					"migration {} ({}) failed: {}",
+					migration.version, migration.name, e
+				))
+			})?;
+
+		let applied_at = chrono::Utc::now().naive_utc();
+		transaction
+			.execute(
+				RECORD_MIGRATION_QUERY,
+				&[&migration.version, &migration.name, &applied_at],
+			)
+			.await
+			.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+		transaction
+			.commit()
+			.await
+			.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+	}
+
+	info!("Database schema migrated to version {}", latest_version());
+	Ok(())
+}
+
+/// Checks that the database schema is not newer than this binary without applying anything. Used
+/// when automatic migrations are disabled.
+pub async fn check_schema_version(pool: &PersistentConnectionPool) -> Result<(), DatabaseError> {
+	let client = pool.get().await?;
+
+	let row = client
+		.query_one(SCHEMA_MIGRATIONS_EXISTS_QUERY, &[])
+		.await
+		.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+	let exists: bool = row.get(0);
+
+	if !exists {
+		warn!("No `schema_migrations` table found; the database schema version is unknown");
+		return Ok(());
+	}
+
+	let applied = get_applied_versions(&client).await?;
+	let pending = pending_migrations(&applied, MIGRATIONS)?;
+
+	if !pending.is_empty() {
+		warn!(
+			"Database schema has {} pending migration(s) which will not be applied",
+			pending.len()
+		);
+	}
+
+	Ok(())
+}
+
+async fn get_applied_versions(
+	client: &deadpool_postgres::Client,
+) -> Result<Vec<i32>, DatabaseError> {
+	let rows = client
+		.query(GET_APPLIED_VERSIONS_QUERY, &[])
+		.await
+		.map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+	Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Determine which of the `available` migrations still need to be applied given the `applied`
+/// versions recorded in the database.
+fn pending_migrations<'a>(
+	applied: &[i32],
+	available: &'a [Migration],
+) -> Result<Vec<&'a Migration>, DatabaseError> {
+	let latest_known = available.last().map(|m| m.version).unwrap_or(0);
+
+	if let Some(&latest_applied) = applied.iter().max() {
+		if latest_applied > latest_known {
+			return Err(DatabaseError::SchemaTooNew {
+				database: latest_applied,
+				binary: latest_known,
+			});
+		}
+	}
+
+	Ok(available
+		.iter()
+		.filter(|m| !applied.contains(&m.version))
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_versions_are_strictly_increasing_from_one() {
+		for (i, migration) in MIGRATIONS.iter().enumerate() {
+			assert_eq!(migration.version, i as i32 + 1);
+		}
+	}
+
+	#[test]
+	fn test_all_pending_on_fresh_database() {
+		let pending = pending_migrations(&[], MIGRATIONS).unwrap();
+		assert_eq!(pending.len(), MIGRATIONS.len());
+	}
+
+	#[test]
+	fn test_only_unapplied_are_pending() {
+		let pending = pending_migrations(&[1, 2], MIGRATIONS).unwrap();
+		let versions: Vec<i32> = pending.iter().map(|m| m.version).collect();
+		assert_eq!(versions, (3..=latest_version()).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_newer_database_is_rejected() {
+		let newer = latest_version() + 1;
+		let result = pending_migrations(&[1, newer], MIGRATIONS);
+		assert_eq!(
+			result.unwrap_err(),
+			DatabaseError::SchemaTooNew {
+				database: newer,
+				binary: latest_version(),
+			}
+		);
+	}
+}
@@ -1,4 +1,5 @@
 //! Persistent database and in-memory database support.
 
 pub mod error;
+pub mod migrations;
 pub mod postgresql;
@@ -15,6 +15,12 @@ pub struct PersistentConnectionPool(Pool);
 impl PersistentConnectionPool {
 	/// Initialize a PostgreSQL database pool from supplied `database_settings`.
 	pub fn from_settings(database_settings: &DatabaseSettings) -> Result<Self, DatabaseError> {
+		info!("Attempting to create a PostgreSQL connection pool");
+		debug!(
+			"Supplied database settings for initializing connection pool:\n {:?}",
+			database_settings
+		);
+
 		let database_settings = database_settings.clone();
 
 		let postgres_config = config::Config {
@@ -26,12 +32,6 @@ impl PersistentConnectionPool {
 			..config::Config::default()
 		};
 
-		info!("Attempting to create a PostgreSQL connection pool");
-		debug!(
-			"Supplied database settings for initializing connection pool:\n {:?}",
-			&postgres_config
-		);
-
 		let pool = postgres_config.create_pool(NoTls);
 
 		match pool {
@@ -1,22 +1,31 @@
 pub mod accounts;
+pub mod admin;
 pub mod auth;
 pub mod avatars;
 pub mod database;
 mod json_error_handler;
 pub mod logging;
+pub mod mail;
 pub mod meetings;
 pub mod presentations;
 pub mod service_errors;
 pub mod settings;
+pub mod storage;
 pub mod types;
 mod welcome;
 
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::mail::MailSender;
+use crate::meetings::hub::MeetingHub;
 use crate::settings::Settings;
+use crate::storage::BlobStorage;
 
+use actix::{Actor, Addr};
+use actix_ratelimit::errors::ARError;
 use actix_ratelimit::{MemoryStore, MemoryStoreActor, RateLimiter};
-use actix_web::web;
+use actix_web::dev::ServiceRequest;
 use actix_web::HttpServer;
+use actix_web::{guard, web};
 use actix_web::{middleware, App};
 use actix_web_httpauth::middleware::HttpAuthentication;
 use log::{error, info};
@@ -29,6 +38,9 @@ use std::net;
 /// Package version.
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Seconds between two runs of the background maintenance, see `spawn_maintenance`.
+const MAINTENANCE_INTERVAL: u64 = 60;
+
 /// Main entry point to the Virtual Reality Meeting Environment backend server.
 ///
 /// # Panics
@@ -36,6 +48,8 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// - If settings provided are invalid, the server instance will panic with error messages to
 ///   indicate erroneous configuration.
 /// - Panics if failed to create a database connection pool.
+/// - Panics if failed to set up the blob storage backend.
+/// - Panics if failed to set up the mailer backend.
 ///
 /// # Additional References
 ///
@@ -57,13 +71,36 @@ async fn main() -> std::io::Result<()> {
 	info!("Server listening on http://{}", &socket_address);
 
 	let persistent_connection_pool = create_persistent_connection_pool(&settings.database);
+	migrate_database_schema(&settings.database, &persistent_connection_pool).await;
+	bootstrap_admin(&settings.auth, &persistent_connection_pool).await;
+
+	let blob_storage = create_blob_storage(&settings.storage);
+	let mail_sender = create_mail_sender(&settings.mail);
+	let meeting_hub = MeetingHub::default().start();
+
+	spawn_maintenance(
+		settings.clone(),
+		persistent_connection_pool.clone(),
+		blob_storage.clone(),
+		meeting_hub.clone(),
+	);
 
-	// Curried closure: required data `settings` and `connection_pool` needs to be passed in by
-	// value (by cloning) to prevent moving values.
+	// Curried closure: required data `settings`, `connection_pool`, `blob_storage`, `mail_sender`
+	// and `meeting_hub` needs to be passed in by value (by cloning) to prevent moving values.
 	//
-	// In pseduo-Haskell type signature: `create_app :: (Settings, ConnectionPool) -> move () -> App`.
+	// In pseduo-Haskell type signature:
+	// `create_app :: (Settings, ConnectionPool, BlobStorage, MailSender, Addr MeetingHub)
+	//     -> move () -> App`.
 	let create_app =
-		|settings: Settings, persistent_connection_pool: PersistentConnectionPool| {
+		|settings: Settings,
+		 persistent_connection_pool: PersistentConnectionPool,
+		 blob_storage: BlobStorage,
+		 mail_sender: MailSender,
+		 meeting_hub: Addr<MeetingHub>| {
+			// Shared by all workers, so that lookups cannot be spread across workers to get around
+			// the limit.
+			let uuid_lookup_rate_limit_memory_store = MemoryStore::new();
+
 			move || {
 				let auth_middleware =
 					HttpAuthentication::bearer(auth::middleware::identity_validator);
@@ -82,7 +119,11 @@ async fn main() -> std::io::Result<()> {
 						))
 						.with_max_requests(settings.rate_limiting.max_requests),
 					)
-					.wrap(middleware::Logger::default())
+					.wrap(
+						// The calendar feed is authenticated with `?token=`, which must not end up
+						// in the access log.
+						middleware::Logger::default().exclude("/account/calendar.ics"),
+					)
 					.data(settings.clone())
 					.app_data(
 						web::JsonConfig::default()
@@ -90,47 +131,137 @@ async fn main() -> std::io::Result<()> {
 							.error_handler(json_error_handler::handle_json_error),
 					)
 					.data(persistent_connection_pool.clone())
+					.data(blob_storage.clone())
+					.data(mail_sender.clone())
+					.data(meeting_hub.clone())
 					.route(
 						"/register",
 						web::post().to(accounts::register::handle_registration),
 					)
+					.route(
+						"/register/verify",
+						web::post().to(accounts::verify_email::handle_verify_email),
+					)
+					.route(
+						"/register/verify/resend",
+						web::post().to(accounts::verify_email::handle_resend_verification_email),
+					)
 					.service(
 						web::resource("/account")
 							.wrap(auth_middleware.clone())
 							.route(web::delete().to(accounts::delete::handle_delete_account)),
 					)
+					.route(
+						"/account/restore",
+						web::post().to(accounts::delete::handle_restore_account),
+					)
+					.service(
+						web::resource("/account/export")
+							.wrap(auth_middleware.clone())
+							.route(web::get().to(accounts::export::handle_get_export))
+							.route(web::post().to(accounts::export::handle_request_export)),
+					)
+					.service(
+						web::resource("/account/export/download")
+							.wrap(auth_middleware.clone())
+							.route(web::get().to(accounts::export::handle_download_export)),
+					)
+					.service(
+						web::resource("/account/meetings")
+							.wrap(auth_middleware.clone())
+							.route(web::get().to(meetings::history::handle_get_meeting_history)),
+					)
+					.route(
+						"/account/calendar.ics",
+						web::get().to(meetings::calendar::handle_get_account_calendar),
+					)
+					.service(
+						web::resource("/account/calendar/token")
+							.wrap(auth_middleware.clone())
+							.route(web::post().to(meetings::calendar::handle_issue_calendar_token)),
+					)
+					.service(
+						web::resource("/account/email")
+							.wrap(auth_middleware.clone())
+							.route(web::post().to(accounts::change_email::handle_change_email)),
+					)
+					.route(
+						"/account/email/confirm",
+						web::post().to(accounts::change_email::handle_confirm_email_change),
+					)
+					.service(
+						web::resource("/account/password")
+							.wrap(auth_middleware.clone())
+							.route(web::put().to(accounts::password::handle_change_password)),
+					)
+					.service(
+						web::resource("/account/sessions")
+							.wrap(auth_middleware.clone())
+							.route(web::get().to(auth::sessions::handle_list_sessions)),
+					)
+					.service(
+						web::resource("/account/sessions/{session_id}")
+							.wrap(auth_middleware.clone())
+							.route(web::delete().to(auth::sessions::handle_revoke_session)),
+					)
 					.route("/login", web::post().to(auth::login::handle_login))
+					.route(
+						"/password/forgot",
+						web::post().to(accounts::password::handle_forgot_password),
+					)
+					.route(
+						"/password/reset",
+						web::post().to(accounts::password::handle_reset_password),
+					)
 					.service(
 						web::resource("/logout")
 							.wrap(auth_middleware.clone())
 							.route(web::post().to(auth::logout::handle_logout)),
 					)
-					.route(
-						"/accounts/uuid",
-						web::get().to(accounts::get_uuid::handle_get_uuid),
+					.service(
+						web::resource("/accounts/uuid")
+							.wrap(auth_middleware.clone())
+							.wrap(
+								// Stricter rate limiting against enumerating users
+								RateLimiter::new(
+									MemoryStoreActor::from(
+										uuid_lookup_rate_limit_memory_store.clone(),
+									)
+									.start(),
+								)
+								.with_interval(std::time::Duration::from_secs(
+									settings.rate_limiting.cooldown_duration,
+								))
+								.with_max_requests(settings.rate_limiting.uuid_lookup_max_requests)
+								.with_identifier({
+									let trusted_proxies =
+										settings.rate_limiting.trusted_proxies.clone();
+									move |req| client_ip(req, &trusted_proxies)
+								}),
+							)
+							.route(web::get().to(accounts::get_uuid::handle_get_uuid)),
 					)
 					.service(
+						// Resources sharing a path are told apart by method guards: the router only
+						// falls through to the next resource of the same path if the guards fail.
 						web::scope("/accounts/{uuid}")
 							.service(
-								web::resource("").route(
+								web::resource("").guard(guard::Get()).route(
 									web::get().to(accounts::get_info::handle_get_account_info),
 								),
 							)
 							.service(web::resource("").wrap(auth_middleware.clone()).route(
-								web::put().to(accounts::update_info::handle_update_user_account),
+								web::patch().to(accounts::update_info::handle_update_user_account),
 							))
 							.service(
 								web::resource("/avatar")
+									.guard(guard::Get())
 									.route(web::get().to(avatars::get_avatar::handle_get_avatar)),
 							)
 							.service(
 								web::resource("/avatar")
 									.wrap(auth_middleware.clone())
-									.route(web::post().to(avatars::upload::handle_upload_avatar)),
-							)
-							.service(
-								web::resource("/avatar")
-									.wrap(auth_middleware.clone())
+									.route(web::post().to(avatars::upload::handle_upload_avatar))
 									.route(
 										web::delete()
 											.to(avatars::delete_avatar::handle_delete_avatar),
@@ -138,11 +269,21 @@ async fn main() -> std::io::Result<()> {
 							),
 					)
 					.service(
-						web::scope("/meetings").service(
-							web::resource("")
-								.wrap(auth_middleware.clone())
-								.route(web::post().to(meetings::init_session::handle_init_session)),
-						),
+						// A `web::resource` rather than a `web::scope`: a `/meetings` scope would
+						// swallow every `/meetings/{meeting_id}/...` request below.
+						web::resource("/meetings")
+							.wrap(auth_middleware.clone())
+							.route(web::post().to(meetings::init_session::handle_init_session)),
+					)
+					.service(
+						web::resource("/meetings/schedule")
+							.wrap(auth_middleware.clone())
+							.route(web::post().to(meetings::schedule::handle_schedule_meeting)),
+					)
+					.service(
+						web::resource("/meetings/join")
+							.wrap(auth_middleware.clone())
+							.route(web::post().to(meetings::invites::handle_join_meeting)),
 					)
 					.service(
 						web::scope("/meetings/{meeting_id}")
@@ -152,14 +293,86 @@ async fn main() -> std::io::Result<()> {
 									meetings::get_session_info::handle_get_meeting_session_info,
 								),
 							))
+							.service(
+								web::resource("/participants").route(
+									web::get().to(
+										meetings::get_session_info::handle_get_meeting_attendance,
+									),
+								),
+							)
+							.service(web::resource("/calendar.ics").route(
+								web::get().to(meetings::calendar::handle_get_meeting_calendar),
+							))
+							.service(
+								web::resource("/settings")
+									.route(
+										web::get()
+											.to(meetings::admission::handle_get_admission_settings),
+									)
+									.route(
+										web::patch().to(
+											meetings::admission::handle_update_admission_settings,
+										),
+									),
+							)
+							.service(
+								web::resource("/lobby")
+									.route(web::get().to(meetings::admission::handle_list_lobby)),
+							)
+							.service(web::resource("/lobby/{user_id}/admit").route(
+								web::post().to(meetings::admission::handle_admit_from_lobby),
+							))
+							.service(web::resource("/lobby/{user_id}/reject").route(
+								web::post().to(meetings::admission::handle_reject_from_lobby),
+							))
+							.service(web::resource("/participants/{user_id}").route(
+								web::delete().to(meetings::moderation::handle_remove_participant),
+							))
+							.service(
+								web::resource("/bans")
+									.route(web::get().to(meetings::moderation::handle_list_bans)),
+							)
+							.service(
+								web::resource("/bans/{user_id}")
+									.route(web::delete().to(meetings::moderation::handle_lift_ban)),
+							)
+							.service(web::resource("/participants/{user_id}/role").route(
+								web::put().to(meetings::presenters::handle_set_participant_role),
+							))
+							.service(web::resource("/presenter").route(
+								web::post().to(meetings::presenters::handle_transfer_presenter),
+							))
 							.service(
 								web::resource("/listener").route(
 									web::post().to(meetings::add_listener::handle_add_listener),
 								),
 							)
+							.service(
+								web::resource("/invites")
+									.route(web::get().to(meetings::invites::handle_list_invites))
+									.route(web::post().to(meetings::invites::handle_create_invite)),
+							)
+							.service(
+								web::resource("/invites/{invite_id}").route(
+									web::delete().to(meetings::invites::handle_revoke_invite),
+								),
+							)
+							.service(web::resource("/join").route(
+								web::post().to(meetings::invites::handle_join_scheduled_meeting),
+							))
+							.service(web::resource("/start").route(
+								web::post().to(meetings::lifecycle::handle_start_meeting_session),
+							))
+							.service(web::resource("/end").route(
+								web::post().to(meetings::lifecycle::handle_end_meeting_session),
+							))
 							.service(web::resource("/leave").route(
 								web::post().to(meetings::leave::handle_leave_meeting_session),
 							))
+							.service(
+								web::resource("/ws")
+									.route(web::get().to(meetings::ws::handle_meeting_ws)),
+							)
 							.service(
 								web::resource("/presentation")
 									.route(web::post().to(
@@ -172,7 +385,48 @@ async fn main() -> std::io::Result<()> {
 										web::delete()
 											.to(presentations::delete::handle_delete_presentation),
 									),
-							),
+							)
+							.service(
+								web::resource("/presentation/current")
+									.route(
+										web::get().to(
+											presentations::current_page::handle_get_current_page,
+										),
+									)
+									.route(
+										web::put().to(
+											presentations::current_page::handle_set_current_page,
+										),
+									),
+							)
+							.service(web::resource("/presentation/pages/{index}").route(
+								web::get().to(
+									presentations::get_presentation::handle_get_presentation_page,
+								),
+							)),
+					)
+					.service(
+						web::scope("/admin")
+							.wrap(auth_middleware.clone())
+							.service(
+								web::resource("/accounts")
+									.route(web::get().to(admin::accounts::handle_list_accounts)),
+							)
+							.service(
+								web::resource("/accounts/{uuid}/ban")
+									.route(web::post().to(admin::accounts::handle_ban_user))
+									.route(web::delete().to(admin::accounts::handle_unban_user)),
+							)
+							.service(
+								web::resource("/accounts/{uuid}/role")
+									.route(web::put().to(admin::accounts::handle_set_role)),
+							)
+							.service(web::resource("/accounts/{uuid}/sessions").route(
+								web::delete().to(admin::accounts::handle_revoke_user_sessions),
+							))
+							.service(web::resource("/meetings/{meeting_id}").route(
+								web::delete().to(admin::meetings::handle_end_meeting_session),
+							)),
 					)
 			}
 		};
@@ -180,6 +434,9 @@ async fn main() -> std::io::Result<()> {
 	let server = HttpServer::new(create_app(
 		settings.clone(),
 		persistent_connection_pool.clone(),
+		blob_storage,
+		mail_sender,
+		meeting_hub,
 	))
 	.bind(socket_address)?;
 
@@ -239,3 +496,117 @@ fn create_persistent_connection_pool(
 		}
 	}
 }
+
+#[inline]
+fn create_blob_storage(settings: &settings::StorageSettings) -> BlobStorage {
+	match BlobStorage::from_settings(settings) {
+		Ok(storage) => storage,
+		Err(e) => {
+			error!("Failed to set up blob storage: {:?}", &e);
+			panic!("Failed to set up blob storage: {:?}", &e);
+		}
+	}
+}
+
+#[inline]
+fn create_mail_sender(settings: &settings::MailSettings) -> MailSender {
+	match MailSender::from_settings(settings) {
+		Ok(mail_sender) => mail_sender,
+		Err(e) => {
+			error!("Failed to set up mailer: {:?}", &e);
+			panic!("Failed to set up mailer: {:?}", &e);
+		}
+	}
+}
+
+/// Periodically purge accounts whose deletion grace period is over and remove the blobs left
+/// behind by deleted rows, in the background.
+fn spawn_maintenance(
+	settings: Settings,
+	pool: PersistentConnectionPool,
+	blob_storage: BlobStorage,
+	meeting_hub: Addr<MeetingHub>,
+) {
+	actix_rt::spawn(async move {
+		let mut interval =
+			actix_rt::time::interval(std::time::Duration::from_secs(MAINTENANCE_INTERVAL));
+
+		loop {
+			interval.tick().await;
+
+			if let Err(e) =
+				accounts::delete::purge_deleted_accounts(&pool, &settings.accounts, &meeting_hub)
+					.await
+			{
+				error!("Failed to purge deleted accounts: {}", e);
+			}
+
+			if let Err(e) =
+				storage::deletion_jobs::run_blob_deletion_jobs(&pool, &blob_storage).await
+			{
+				error!("Failed to run blob deletion jobs: {}", e);
+			}
+		}
+	});
+}
+
+/// Identifies clients by IP address for rate limiting. The default identifier of `RateLimiter` is
+/// the address including the port, with which every new connection starts with a fresh limit.
+///
+/// The `X-Forwarded-For` header is only honoured for connections from `trusted_proxies`, in which
+/// case the client is the last address in it that is not a trusted proxy itself.
+fn client_ip(req: &ServiceRequest, trusted_proxies: &[net::IpAddr]) -> Result<String, ARError> {
+	let peer = req.peer_addr().ok_or(ARError::IdentificationError)?.ip();
+
+	if !trusted_proxies.contains(&peer) {
+		return Ok(peer.to_string());
+	}
+
+	let forwarded_for = req
+		.headers()
+		.get_all("x-forwarded-for")
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.map(|address| address.trim().parse::<net::IpAddr>())
+		.collect::<Vec<_>>();
+
+	// Proxies append to the header, so only its trailing entries were added by trusted proxies.
+	for address in forwarded_for.into_iter().rev() {
+		match address {
+			Ok(address) if trusted_proxies.contains(&address) => continue,
+			Ok(address) => return Ok(address.to_string()),
+			Err(_) => break,
+		}
+	}
+
+	Ok(peer.to_string())
+}
+
+#[inline]
+async fn bootstrap_admin(
+	settings: &settings::AuthSettings,
+	persistent_connection_pool: &PersistentConnectionPool,
+) {
+	if let Err(e) = admin::accounts::bootstrap_admin(persistent_connection_pool, settings).await {
+		error!("Failed to make the first admin: {:?}", &e);
+		panic!("Failed to make the first admin: {:?}", &e);
+	}
+}
+
+#[inline]
+async fn migrate_database_schema(
+	settings: &settings::DatabaseSettings,
+	persistent_connection_pool: &PersistentConnectionPool,
+) {
+	let result = if settings.run_migrations {
+		database::migrations::run_migrations(persistent_connection_pool).await
+	} else {
+		info!("Automatic schema migrations are disabled");
+		database::migrations::check_schema_version(persistent_connection_pool).await
+	};
+
+	if let Err(e) = result {
+		error!("Failed to migrate database schema: {:?}", &e);
+		panic!("Failed to migrate database schema: {:?}", &e);
+	}
+}
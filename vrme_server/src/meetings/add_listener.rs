@@ -1,89 +1,63 @@
 //! Add a listener to a meeting session.
 
+use actix::Addr;
 use actix_web::web;
 use actix_web::{Error, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use deadpool_postgres::Client;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::auth::auth_payload::AuthPayload;
 use crate::database::postgresql::PersistentConnectionPool;
-use crate::service_errors::ServiceError;
+use crate::meetings::admission::leave_lobby;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::{MeetingHub, Publish};
+use crate::meetings::participants::{admit_listener, require_presenter};
+use crate::settings::Settings;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AddListenerRequestPayload {
 	pub listener: Uuid,
 }
 
+/// Handler for the presenter to add a listener to the meeting session. The listener bypasses the
+/// lobby and the lock of the meeting session, but not its `max_participants`: adding a listener to
+/// a full meeting session fails with `409 Conflict`.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist or has ended.
 pub async fn handle_add_listener(
 	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	hub: web::Data<Addr<MeetingHub>>,
 	meeting_id: web::Path<Uuid>,
+	payload: web::Json<AddListenerRequestPayload>,
 	auth: BearerAuth,
 ) -> Result<HttpResponse, Error> {
-	let client = pool.get().await?;
+	let mut client = pool.get().await?;
 	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
 
-	// We check that the user requesting *is* the `presenter`.
-	let mut listeners = get_meeting_session_listeners(&client, &meeting_id, &user_id).await?;
-
-	listeners.push(meeting_id.clone());
-
-	update_meeting_session_listeners(&client, &meeting_id, &user_id, listeners).await?;
-
-	Ok(HttpResponse::NoContent().finish())
-}
-
-const GET_MEETING_SESSION_LISTENERS: &str = r#"
-    SELECT
-        listeners
-    FROM meeting_sessions
-    WHERE
-        meeting_id = $1::UUID AND
-        presenter = $2::UUID
-    ;
-"#;
-
-async fn get_meeting_session_listeners(
-	client: &Client,
-	meeting_id: &Uuid,
-	user_id: &Uuid,
-) -> Result<Vec<Uuid>, ServiceError> {
-	let statement = client.prepare(GET_MEETING_SESSION_LISTENERS).await?;
-
-	let rows = client.query(&statement, &[meeting_id, user_id]).await?;
-
-	if !rows.is_empty() {
-		let listeners = rows[0].get(0);
-		Ok(listeners)
-	} else {
-		Err(ServiceError::NotFound(
-			"You are not the presenter of any meeting session".to_string(),
-		))
+	require_presenter(&client, &meeting_id, &user_id, "add listeners").await?;
+
+	let joined = admit_listener(
+		&mut client,
+		&meeting_id,
+		&payload.listener,
+		settings.meetings.default_max_participants,
+	)
+	.await?;
+	leave_lobby(&client, &meeting_id, &payload.listener).await?;
+
+	if joined {
+		hub.do_send(Publish {
+			meeting_id: *meeting_id,
+			event: MeetingEvent::ParticipantJoined {
+				user_id: payload.listener,
+			},
+		});
 	}
-}
-
-const UPDATE_MEETING_SESSION_QUERY: &str = r#"
-    UPDATE meeting_sessions
-    SET
-        listeners = $3::UUID[]
-    WHERE
-        meeting_id = $1::UUID AND
-        presenter = $2::UUID
-    ;
-"#;
 
-async fn update_meeting_session_listeners(
-	client: &Client,
-	meeting_id: &Uuid,
-	user_id: &Uuid,
-	listeners: Vec<Uuid>,
-) -> Result<(), ServiceError> {
-	let statement = client.prepare(UPDATE_MEETING_SESSION_QUERY).await?;
-
-	client
-		.query(&statement, &[meeting_id, user_id, &listeners])
-		.await?;
-
-	Ok(())
+	Ok(HttpResponse::NoContent().finish())
 }
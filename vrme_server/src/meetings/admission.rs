@@ -0,0 +1,416 @@
+//! Admission control of meeting sessions.
+//!
+//! The presenter configures who may join the meeting session at
+//! `PATCH /meetings/{meeting_id}/settings`:
+//!
+//! - `max_participants`: how many users, the presenter included, may take part in the meeting
+//!   session at once. Defaults to `meetings.default_max_participants`. Joining a full meeting
+//!   session fails with `409 Conflict`.
+//! - `lobby`: users joining with an invite wait in the lobby until the presenter admits them at
+//!   `POST /meetings/{meeting_id}/lobby/{user_id}/admit` or rejects them at
+//!   `POST /meetings/{meeting_id}/lobby/{user_id}/reject`. Waiting users learn the outcome by
+//!   joining again at `POST /meetings/join`.
+//! - `locked`: users can no longer join with an invite. The presenter may still add listeners and
+//!   admit users waiting in the lobby.
+
+use actix::Addr;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::leave::publish;
+use crate::meetings::participants::{
+	admit_listener, check_not_removed, get_participants, require_presenter,
+};
+use crate::service_errors::ServiceError;
+use crate::settings::Settings;
+
+const PRESENTER_ACTION: &str = "manage who joins the meeting session";
+
+/// Admission settings of a meeting session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AdmissionSettings {
+	/// How many users, the presenter included, may take part in the meeting session.
+	pub max_participants: u32,
+	/// Whether users joining with an invite wait in the lobby.
+	pub lobby: bool,
+	/// Whether users can no longer join with an invite.
+	pub locked: bool,
+}
+
+/// Payload to change the admission settings of a meeting session. Omitted fields are left
+/// unchanged.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateAdmissionSettingsRequest {
+	pub max_participants: Option<u32>,
+	pub lobby: Option<bool>,
+	pub locked: Option<bool>,
+}
+
+/// A user waiting in the lobby.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LobbyEntry {
+	pub user_id: Uuid,
+	/// When the user asked to join the meeting session (UTC).
+	pub requested_at: NaiveDateTime,
+}
+
+/// Where a user stands in the lobby of a meeting session.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LobbyStatus {
+	Waiting,
+	Rejected,
+}
+
+/// Handler for getting the admission settings at `GET /meetings/{meeting_id}/settings`. Only
+/// participants may get the admission settings.
+pub async fn handle_get_admission_settings(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	check_not_removed(&client, &meeting_id, &identity.user_id).await?;
+
+	match get_participants(&client, &meeting_id).await? {
+		Some(participants) if participants.contains(&identity.user_id) => {}
+		_ => {
+			return Err(ServiceError::Forbidden(
+				"Only meeting participants may get the meeting session settings".to_string(),
+			)
+			.into())
+		}
+	}
+
+	let admission = get_admission_settings(&client, &settings, &meeting_id)
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such meeting session found".to_string()))?;
+
+	Ok(HttpResponse::Ok().json(admission))
+}
+
+/// Handler for changing the admission settings at `PATCH /meetings/{meeting_id}/settings`.
+/// Responds with the new admission settings. Lowering `max_participants` below the current number
+/// of participants only keeps further users from joining.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: `max_participants` is `0`.
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist or has ended.
+pub async fn handle_update_admission_settings(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	meeting_id: web::Path<Uuid>,
+	request: web::Json<UpdateAdmissionSettingsRequest>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	if request.max_participants == Some(0) {
+		return Err(
+			ServiceError::BadRequest("`max_participants` must be at least 1".to_string()).into(),
+		);
+	}
+
+	let client = pool.get().await?;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+	update_admission_settings(&client, &meeting_id, &request).await?;
+
+	let admission = get_admission_settings(&client, &settings, &meeting_id)
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such meeting session found".to_string()))?;
+
+	info!(
+		"User `{}` changed the settings of meeting session `{}`",
+		&identity.user_id, &meeting_id
+	);
+
+	Ok(HttpResponse::Ok().json(admission))
+}
+
+/// Handler for listing the users waiting in the lobby at `GET /meetings/{meeting_id}/lobby`, in
+/// the order they asked to join. Only the presenter may list the lobby.
+pub async fn handle_list_lobby(
+	pool: web::Data<PersistentConnectionPool>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+	let lobby = list_lobby(&client, &meeting_id).await?;
+
+	Ok(HttpResponse::Ok().json(lobby))
+}
+
+/// Handler for admitting a user waiting in the lobby at
+/// `POST /meetings/{meeting_id}/lobby/{user_id}/admit`. The user joins the meeting session as a
+/// listener.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist, or the user is not waiting in its lobby.
+/// - `409 Conflict`: the meeting session is full.
+pub async fn handle_admit_from_lobby(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	hub: web::Data<Addr<MeetingHub>>,
+	path: web::Path<(Uuid, Uuid)>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, user_id) = path.into_inner();
+	let mut client = pool.get().await?;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	if get_lobby_status(&client, &meeting_id, &user_id).await? != Some(LobbyStatus::Waiting) {
+		return Err(not_waiting().into());
+	}
+
+	let joined = admit_listener(
+		&mut client,
+		&meeting_id,
+		&user_id,
+		settings.meetings.default_max_participants,
+	)
+	.await?;
+	leave_lobby(&client, &meeting_id, &user_id).await?;
+
+	if joined {
+		publish(
+			&hub,
+			&meeting_id,
+			MeetingEvent::ParticipantJoined { user_id },
+		);
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler for rejecting a user waiting in the lobby at
+/// `POST /meetings/{meeting_id}/lobby/{user_id}/reject`. The user cannot ask to join again.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist, or the user is not waiting in its lobby.
+pub async fn handle_reject_from_lobby(
+	pool: web::Data<PersistentConnectionPool>,
+	path: web::Path<(Uuid, Uuid)>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, user_id) = path.into_inner();
+	let client = pool.get().await?;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	if !reject_from_lobby(&client, &meeting_id, &user_id).await? {
+		return Err(not_waiting().into());
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+fn not_waiting() -> ServiceError {
+	ServiceError::NotFound("The user is not waiting in the lobby".to_string())
+}
+
+const GET_ADMISSION_SETTINGS_QUERY: &str = r#"
+    SELECT
+        COALESCE(max_participants, $2::INTEGER),
+        lobby,
+        locked
+    FROM meeting_sessions
+    WHERE
+        meeting_id = $1::UUID AND
+        state <> 'ended'
+    ;
+"#;
+
+/// Get the admission settings of the meeting session, if it exists and has not ended.
+pub async fn get_admission_settings(
+	client: &Client,
+	settings: &Settings,
+	meeting_id: &Uuid,
+) -> Result<Option<AdmissionSettings>, ServiceError> {
+	let default_max_participants = settings.meetings.default_max_participants as i32;
+
+	let statement = client.prepare(GET_ADMISSION_SETTINGS_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[meeting_id, &default_max_participants])
+		.await?;
+
+	Ok(row.map(|row| AdmissionSettings {
+		max_participants: row.get::<_, i32>(0) as u32,
+		lobby: row.get(1),
+		locked: row.get(2),
+	}))
+}
+
+const UPDATE_ADMISSION_SETTINGS_QUERY: &str = r#"
+    UPDATE meeting_sessions
+    SET
+        max_participants = COALESCE($2::INTEGER, max_participants),
+        lobby = COALESCE($3::BOOLEAN, lobby),
+        locked = COALESCE($4::BOOLEAN, locked)
+    WHERE
+        meeting_id = $1::UUID
+    ;
+"#;
+
+async fn update_admission_settings(
+	client: &Client,
+	meeting_id: &Uuid,
+	request: &UpdateAdmissionSettingsRequest,
+) -> Result<(), ServiceError> {
+	let max_participants = request
+		.max_participants
+		.map(|max| max.min(i32::MAX as u32) as i32);
+
+	let statement = client.prepare(UPDATE_ADMISSION_SETTINGS_QUERY).await?;
+	client
+		.execute(
+			&statement,
+			&[
+				meeting_id,
+				&max_participants,
+				&request.lobby,
+				&request.locked,
+			],
+		)
+		.await?;
+	Ok(())
+}
+
+const LIST_LOBBY_QUERY: &str = r#"
+    SELECT
+        user_id,
+        requested_at
+    FROM meeting_lobby
+    WHERE
+        meeting_id = $1::UUID AND
+        rejected_at IS NULL
+    ORDER BY
+        requested_at
+    ;
+"#;
+
+async fn list_lobby(client: &Client, meeting_id: &Uuid) -> Result<Vec<LobbyEntry>, ServiceError> {
+	let statement = client.prepare(LIST_LOBBY_QUERY).await?;
+	let rows = client.query(&statement, &[meeting_id]).await?;
+
+	Ok(rows
+		.iter()
+		.map(|row| LobbyEntry {
+			user_id: row.get(0),
+			requested_at: row.get(1),
+		})
+		.collect())
+}
+
+const GET_LOBBY_STATUS_QUERY: &str = r#"
+    SELECT
+        rejected_at IS NOT NULL
+    FROM meeting_lobby
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID
+    ;
+"#;
+
+/// Where the user stands in the lobby of the meeting session, if they asked to join it.
+pub async fn get_lobby_status(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<Option<LobbyStatus>, ServiceError> {
+	let statement = client.prepare(GET_LOBBY_STATUS_QUERY).await?;
+	let row = client.query_opt(&statement, &[meeting_id, user_id]).await?;
+
+	Ok(row.map(|row| {
+		if row.get(0) {
+			LobbyStatus::Rejected
+		} else {
+			LobbyStatus::Waiting
+		}
+	}))
+}
+
+const ENTER_LOBBY_QUERY: &str = r#"
+    INSERT INTO meeting_lobby
+        (meeting_id, user_id, requested_at)
+    VALUES
+        ($1::UUID, $2::UUID, $3::TIMESTAMP)
+    ON CONFLICT DO NOTHING
+    ;
+"#;
+
+/// Put the user in the lobby of the meeting session. Returns whether they were not in it yet.
+pub async fn enter_lobby(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(ENTER_LOBBY_QUERY).await?;
+	let entered = client
+		.execute(&statement, &[meeting_id, user_id, &now])
+		.await?;
+	Ok(entered > 0)
+}
+
+const LEAVE_LOBBY_QUERY: &str = r#"
+    DELETE FROM meeting_lobby
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID
+    ;
+"#;
+
+/// Remove the user from the lobby of the meeting session, e.g. once they joined it.
+pub async fn leave_lobby(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<(), ServiceError> {
+	let statement = client.prepare(LEAVE_LOBBY_QUERY).await?;
+	client.execute(&statement, &[meeting_id, user_id]).await?;
+	Ok(())
+}
+
+const REJECT_FROM_LOBBY_QUERY: &str = r#"
+    UPDATE meeting_lobby
+    SET
+        rejected_at = $3::TIMESTAMP
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        rejected_at IS NULL
+    ;
+"#;
+
+async fn reject_from_lobby(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(REJECT_FROM_LOBBY_QUERY).await?;
+	let rejected = client
+		.execute(&statement, &[meeting_id, user_id, &now])
+		.await?;
+	Ok(rejected > 0)
+}
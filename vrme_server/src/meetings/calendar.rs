@@ -0,0 +1,316 @@
+//! iCalendar (RFC 5545) export of scheduled meeting sessions.
+//!
+//! - `GET /meetings/{meeting_id}/calendar.ics`: a single scheduled meeting session, for its
+//!   presenter, invitees and participants.
+//! - `GET /account/calendar.ics`: a feed of every scheduled meeting session the user presents, is
+//!   invited to or took part in. Calendar applications subscribing to the feed usually cannot send
+//!   an `Authorization` header, so the feed is alternatively authenticated with `?token=`, a
+//!   calendar token issued at `POST /account/calendar/token`. Issuing a new calendar token revokes
+//!   the previous one. Requests for the feed are left out of the access log, so that calendar
+//!   tokens are not logged.
+//!
+//! Times are exported in UTC, which calendar applications convert to the local time of the user.
+//! Meeting sessions ended before they started stay in the feed, marked as cancelled, so that
+//! subscribed calendars remove them.
+
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::auth_token::AuthToken;
+use crate::auth::roles::{Identity, OptionalIdentity};
+use crate::auth::token_hash::hash_auth_token;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::participants::is_participant;
+use crate::meetings::schedule::{get_scheduled_meeting, list_scheduled_meetings, ScheduledMeeting};
+use crate::service_errors::ServiceError;
+use crate::settings::Settings;
+
+/// Identifies the product which created the calendars.
+const PRODUCT_ID: &str = "-//VRME//VRME Server//EN";
+
+/// Maximum length of a content line in octets, without the line break.
+const MAX_LINE_LEN: usize = 75;
+
+const CALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// Handler for exporting a scheduled meeting session at `GET /meetings/{meeting_id}/calendar.ics`.
+///
+/// ## Errors
+///
+/// - `404 Not Found`: the meeting session does not exist, is not scheduled, or the user is neither
+///   its presenter, an invitee nor a participant.
+pub async fn handle_get_meeting_calendar(
+	pool: web::Data<PersistentConnectionPool>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	let user_id = identity.user_id;
+
+	let not_found =
+		|| ServiceError::NotFound("No matching scheduled meeting session found".to_string());
+
+	let meeting = get_scheduled_meeting(&client, &meeting_id)
+		.await?
+		.ok_or_else(not_found)?;
+
+	let may_view =
+		meeting.is_invited(&user_id) || is_participant(&client, &meeting_id, &user_id).await?;
+
+	if !may_view {
+		return Err(not_found().into());
+	}
+
+	let calendar = write_calendar(&[meeting], &Utc::now().naive_utc());
+
+	Ok(HttpResponse::Ok()
+		.content_type(CALENDAR_CONTENT_TYPE)
+		.body(calendar))
+}
+
+/// Query of the calendar feed.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CalendarFeedQuery {
+	/// Calendar token, if the request is not authenticated with an `Authorization` header.
+	pub token: Option<String>,
+}
+
+/// Handler for the calendar feed of the user at `GET /account/calendar.ics`, authenticated either
+/// with an `Authorization` header or with `?token=`.
+///
+/// ## Errors
+///
+/// - `401 Unauthorized`: neither an `Authorization` header nor a valid calendar token is given.
+pub async fn handle_get_account_calendar(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	identity: OptionalIdentity,
+	query: web::Query<CalendarFeedQuery>,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	let user_id = match (identity.0, &query.token) {
+		(Some(identity), _) => identity.user_id,
+		(None, Some(token)) => find_calendar_token_owner(&client, &settings, token)
+			.await?
+			.ok_or_else(|| ServiceError::Unauthorized("Invalid calendar token".to_string()))?,
+		(None, None) => {
+			return Err(ServiceError::Unauthorized(
+				"Either an `Authorization` header or a calendar token is required".to_string(),
+			)
+			.into())
+		}
+	};
+
+	let meetings = list_scheduled_meetings(&client, &user_id).await?;
+	let calendar = write_calendar(&meetings, &Utc::now().naive_utc());
+
+	debug!("Served the calendar feed of user `{}`", &user_id);
+
+	Ok(HttpResponse::Ok()
+		.content_type(CALENDAR_CONTENT_TYPE)
+		.body(calendar))
+}
+
+/// Handler for issuing a new calendar token at `POST /account/calendar/token`, revoking the
+/// previous one. The token is only shown once.
+///
+/// ## Success Response
+///
+/// ```json
+/// {
+///     "token": "hkHoaTKb6LyPRHw-NX3IOzk8tvVmthM4zOyVqrPUwTQ"
+/// }
+/// ```
+pub async fn handle_issue_calendar_token(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let token = base64::encode_config(AuthToken::new().await?.token(), base64::URL_SAFE_NO_PAD);
+	let token_hash = hash_auth_token(&settings.auth, &token);
+
+	let client = pool.get().await?;
+	set_calendar_token_hash(&client, &identity.user_id, &token_hash).await?;
+
+	info!("Issued calendar token to user `{}`", &identity.user_id);
+
+	Ok(HttpResponse::Created().json(json!({ "token": token })))
+}
+
+const SET_CALENDAR_TOKEN_HASH_QUERY: &str = r#"
+    UPDATE accounts
+    SET
+        calendar_token_hash = $2::BYTEA
+    WHERE
+        user_id = $1::UUID
+    ;
+"#;
+
+async fn set_calendar_token_hash(
+	client: &Client,
+	user_id: &Uuid,
+	token_hash: &[u8],
+) -> Result<(), ServiceError> {
+	let statement = client.prepare(SET_CALENDAR_TOKEN_HASH_QUERY).await?;
+	client.execute(&statement, &[user_id, &token_hash]).await?;
+	Ok(())
+}
+
+const FIND_CALENDAR_TOKEN_OWNER_QUERY: &str = r#"
+    SELECT
+        user_id
+    FROM accounts
+    WHERE
+        calendar_token_hash = $1::BYTEA AND
+        banned_at IS NULL AND
+        deleted_at IS NULL
+    ;
+"#;
+
+async fn find_calendar_token_owner(
+	client: &Client,
+	settings: &Settings,
+	token: &str,
+) -> Result<Option<Uuid>, ServiceError> {
+	let token_hash = hash_auth_token(&settings.auth, token);
+
+	let statement = client.prepare(FIND_CALENDAR_TOKEN_OWNER_QUERY).await?;
+	let row = client.query_opt(&statement, &[&token_hash]).await?;
+	Ok(row.map(|row| row.get(0)))
+}
+
+/// Write the meeting sessions as an iCalendar object, stamped with `now`.
+fn write_calendar(meetings: &[ScheduledMeeting], now: &NaiveDateTime) -> String {
+	let mut lines = vec![
+		"BEGIN:VCALENDAR".to_string(),
+		"VERSION:2.0".to_string(),
+		format!("PRODID:{}", PRODUCT_ID),
+		"CALSCALE:GREGORIAN".to_string(),
+		"METHOD:PUBLISH".to_string(),
+	];
+
+	for meeting in meetings {
+		let schedule = &meeting.schedule;
+
+		lines.push("BEGIN:VEVENT".to_string());
+		lines.push(format!("UID:{}@vrme", meeting.meeting_id));
+		lines.push(format!("DTSTAMP:{}", format_date_time(now)));
+		lines.push(format!("DTSTART:{}", format_date_time(&schedule.start)));
+		lines.push(format!("DTEND:{}", format_date_time(&schedule.end)));
+		lines.push(format!("SUMMARY:{}", escape_text(&schedule.title)));
+		if let Some(description) = &schedule.description {
+			lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+		}
+		// Cancelling is the only change to a meeting session once scheduled, so it is the only
+		// revision calendar clients need to tell apart.
+		if meeting.cancelled {
+			lines.push("STATUS:CANCELLED".to_string());
+			lines.push("SEQUENCE:1".to_string());
+		} else {
+			lines.push("STATUS:CONFIRMED".to_string());
+			lines.push("SEQUENCE:0".to_string());
+		}
+		lines.push("END:VEVENT".to_string());
+	}
+
+	lines.push("END:VCALENDAR".to_string());
+
+	lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+}
+
+/// Format a UTC date and time, e.g. `20200601T093000Z`.
+fn format_date_time(date_time: &NaiveDateTime) -> String {
+	date_time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape a TEXT property value.
+fn escape_text(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'\\' | ';' | ',' => {
+				escaped.push('\\');
+				escaped.push(c);
+			}
+			'\n' => escaped.push_str("\\n"),
+			'\r' => {}
+			c => escaped.push(c),
+		}
+	}
+
+	escaped
+}
+
+/// Fold a content line into lines of at most `MAX_LINE_LEN` octets, continued by a leading space,
+/// without splitting UTF-8 sequences.
+fn fold_line(line: &str) -> String {
+	let mut folded = String::with_capacity(line.len());
+	let mut line_len = 0;
+
+	for c in line.chars() {
+		if line_len + c.len_utf8() > MAX_LINE_LEN {
+			folded.push_str("\r\n ");
+			line_len = 1;
+		}
+		folded.push(c);
+		line_len += c.len_utf8();
+	}
+
+	folded
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::meetings::schedule::MeetingSchedule;
+	use chrono::NaiveDate;
+
+	#[test]
+	fn test_escape_and_fold() {
+		assert_eq!(
+			escape_text("Q&A; slides, notes\\\r\nand more"),
+			"Q&A\\; slides\\, notes\\\\\\nand more"
+		);
+
+		let line = format!("SUMMARY:{}", "é".repeat(60));
+		let folded = fold_line(&line);
+
+		assert!(folded.split("\r\n").all(|part| part.len() <= MAX_LINE_LEN));
+		assert_eq!(folded.replace("\r\n ", ""), line);
+	}
+
+	#[test]
+	fn test_write_calendar() {
+		let start = NaiveDate::from_ymd(2020, 6, 1).and_hms(9, 30, 0);
+		let meeting = ScheduledMeeting {
+			meeting_id: Uuid::nil(),
+			presenter: Uuid::nil(),
+			schedule: MeetingSchedule {
+				title: "Stand-up".to_string(),
+				description: None,
+				start,
+				end: NaiveDate::from_ymd(2020, 6, 1).and_hms(10, 0, 0),
+				timezone: "Australia/Sydney".to_string(),
+				invitees: Vec::new(),
+			},
+			cancelled: false,
+		};
+
+		let calendar = write_calendar(&[meeting], &start);
+
+		assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+		assert!(calendar.contains("\r\nDTSTART:20200601T093000Z\r\nDTEND:20200601T100000Z\r\n"));
+		assert!(calendar.contains("\r\nSUMMARY:Stand-up\r\n"));
+		assert!(!calendar.contains("DESCRIPTION"));
+		assert!(calendar.contains("\r\nSTATUS:CONFIRMED\r\nSEQUENCE:0\r\n"));
+		assert!(calendar.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+	}
+}
@@ -0,0 +1,49 @@
+//! Real-time meeting session events pushed to connected participants.
+
+use actix::Message;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::meetings::participants::ParticipantRole;
+
+/// An event that happened within a meeting session.
+///
+/// Events are serialized as JSON objects tagged by their `type`, e.g.
+///
+/// ```json
+/// {
+///     "type": "participant-joined",
+///     "user_id": "123e4567-e89b-12d3-a456-426655440000"
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Message)]
+#[rtype(result = "()")]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum MeetingEvent {
+	/// A listener was added to the meeting session.
+	ParticipantJoined { user_id: Uuid },
+	/// A user asked to join the meeting session and waits in the lobby.
+	ParticipantWaiting { user_id: Uuid },
+	/// A listener left the meeting session.
+	ParticipantLeft { user_id: Uuid },
+	/// The presenter handed the meeting session over to another participant.
+	PresenterChanged { user_id: Uuid },
+	/// The presenter made a participant a co-presenter or listener.
+	ParticipantRoleChanged {
+		user_id: Uuid,
+		role: ParticipantRole,
+	},
+	/// The presenter removed a participant from the meeting session. The connections of the removed
+	/// participant are closed after this event.
+	ParticipantRemoved { user_id: Uuid },
+	/// The presenter or a co-presenter uploaded new presentation slides.
+	PresentationUploaded,
+	/// The presenter or a co-presenter deleted the presentation slides.
+	PresentationDeleted,
+	/// The presenter or a co-presenter moved the presentation to another page.
+	SlideChanged { current_page: i32, version: i64 },
+	/// The presenter started the scheduled meeting session.
+	SessionStarted,
+	/// The meeting session was terminated. No further events will be sent.
+	SessionEnded,
+}
@@ -6,35 +6,77 @@ use deadpool_postgres::Client;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::auth::roles::Identity;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::lifecycle::MeetingState;
+use crate::meetings::participants::{
+	check_not_removed, get_participants, is_participant, list_attendance,
+};
+use crate::meetings::schedule::{get_scheduled_meeting, MeetingSchedule};
 use crate::service_errors::ServiceError;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct MeetingSessionInfoResponsePayload {
 	pub presenter: Uuid,
+	pub co_presenters: Vec<Uuid>,
 	pub listeners: Vec<Uuid>,
 	pub started_at: chrono::NaiveDateTime,
+	pub state: MeetingState,
+	/// When the meeting session ended, if it did.
+	pub ended_at: Option<chrono::NaiveDateTime>,
+	/// Calendar metadata, if the meeting session is scheduled and the user presents it, is invited
+	/// to it or takes part in it.
+	pub schedule: Option<MeetingSchedule>,
 }
 
 /// Handler for getting meeting session information.
 pub async fn handle_get_meeting_session_info(
 	pool: web::Data<PersistentConnectionPool>,
 	meeting_id: web::Path<Uuid>,
+	identity: Identity,
 ) -> Result<HttpResponse, Error> {
 	let client = pool.get().await?;
 
-	let response_payload = get_meeting_session_info(&client, &meeting_id).await?;
+	let response_payload =
+		get_meeting_session_info(&client, &meeting_id, &identity.user_id).await?;
 
 	Ok(HttpResponse::Ok().json(response_payload))
 }
 
-const GET_MEETING_SESSION_INFO_QUERY: &str = r#"
+/// Handler for getting the attendance of the meeting session at
+/// `GET /meetings/{meeting_id}/participants`: everyone who took part in it, including those who
+/// left. Only those who take or took part in the meeting session may get the attendance.
+pub async fn handle_get_meeting_attendance(
+	pool: web::Data<PersistentConnectionPool>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	check_not_removed(&client, &meeting_id, &identity.user_id).await?;
+
+	let attendance = list_attendance(&client, &meeting_id).await?;
+
+	let attended = attendance
+		.iter()
+		.any(|participant| participant.user_id == identity.user_id);
+
+	if !attended && !is_participant(&client, &meeting_id, &identity.user_id).await? {
+		return Err(ServiceError::Forbidden(
+			"Only meeting participants may get the attendance".to_string(),
+		)
+		.into());
+	}
+
+	Ok(HttpResponse::Ok().json(attendance))
+}
+
+const GET_MEETING_SESSION_QUERY: &str = r#"
     SELECT
-    (
         presenter,
-        listeners,
-        started_at
-    )
+        started_at,
+        state,
+        ended_at
     FROM meeting_sessions
     WHERE
         meeting_id = $1::UUID
@@ -44,20 +86,43 @@ const GET_MEETING_SESSION_INFO_QUERY: &str = r#"
 async fn get_meeting_session_info(
 	client: &Client,
 	meeting_id: &Uuid,
+	user_id: &Uuid,
 ) -> Result<MeetingSessionInfoResponsePayload, ServiceError> {
-	let statement = client.prepare(GET_MEETING_SESSION_INFO_QUERY).await?;
-	let rows = client.query(&statement, &[meeting_id]).await?;
-
-	if !rows.is_empty() {
-		Ok(MeetingSessionInfoResponsePayload {
-			presenter: rows[0].get(0),
-			listeners: rows[0].get(1),
-			started_at: rows[0].get(2),
-		})
-	} else {
-		Err(ServiceError::NotFound(format!(
+	let not_found = || {
+		ServiceError::NotFound(format!(
 			"No associated meeting session with id {} was found",
 			meeting_id
-		)))
-	}
+		))
+	};
+
+	let statement = client.prepare(GET_MEETING_SESSION_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[meeting_id])
+		.await?
+		.ok_or_else(not_found)?;
+
+	// Ended meeting sessions have no co-presenters or listeners left.
+	let participants = get_participants(client, meeting_id).await?;
+	let takes_part = participants
+		.as_ref()
+		.is_some_and(|participants| participants.contains(user_id));
+	let (co_presenters, listeners) = participants
+		.map(|participants| (participants.co_presenters, participants.listeners))
+		.unwrap_or_default();
+
+	// The schedule names the invitees, so it is only shown to those who may export it as well.
+	let schedule = get_scheduled_meeting(client, meeting_id)
+		.await?
+		.filter(|meeting| takes_part || meeting.is_invited(user_id))
+		.map(|meeting| meeting.schedule);
+
+	Ok(MeetingSessionInfoResponsePayload {
+		presenter: row.get(0),
+		co_presenters,
+		listeners,
+		started_at: row.get(1),
+		state: row.get::<_, &str>(2).parse()?,
+		ended_at: row.get(3),
+		schedule,
+	})
 }
@@ -0,0 +1,125 @@
+//! Meeting history of a user at `GET /account/meetings`: the meeting sessions they present, are
+//! invited to or took part in, including those which ended.
+
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::lifecycle::MeetingState;
+use crate::meetings::participants::ParticipantRole;
+use crate::service_errors::ServiceError;
+
+/// Query of the meeting history.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MeetingHistoryQuery {
+	/// Only list meeting sessions in this state, e.g. `?state=ended`.
+	pub state: Option<MeetingState>,
+}
+
+/// A meeting session in the history of a user.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MeetingHistoryEntry {
+	pub meeting_id: Uuid,
+	pub presenter: Uuid,
+	pub state: MeetingState,
+	/// Title, if the meeting session is scheduled.
+	pub title: Option<String>,
+	/// When the meeting session started, or is scheduled to start (UTC).
+	pub started_at: NaiveDateTime,
+	/// When the meeting session ended (UTC), if it did.
+	pub ended_at: Option<NaiveDateTime>,
+	/// How the user took part in the meeting session, if they did.
+	pub role: Option<ParticipantRole>,
+}
+
+/// Handler for listing the meeting sessions of the user at `GET /account/meetings`, most recent
+/// first.
+///
+/// ## Success Response
+///
+/// ```json
+/// [
+///     {
+///         "meeting_id": "4c9e9f2e-8a55-4a5d-9d3c-2f3a8c1f3b7e",
+///         "presenter": "123e4567-e89b-12d3-a456-426655440000",
+///         "state": "ended",
+///         "title": null,
+///         "started_at": "2020-06-01T09:30:00",
+///         "ended_at": "2020-06-01T10:12:43",
+///         "role": "listener"
+///     }
+/// ]
+/// ```
+pub async fn handle_get_meeting_history(
+	pool: web::Data<PersistentConnectionPool>,
+	query: web::Query<MeetingHistoryQuery>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	let history = list_meeting_history(&client, &identity.user_id, query.state).await?;
+
+	Ok(HttpResponse::Ok().json(history))
+}
+
+const LIST_MEETING_HISTORY_QUERY: &str = r#"
+    SELECT
+        meeting_sessions.meeting_id,
+        meeting_sessions.presenter,
+        meeting_sessions.state,
+        meeting_sessions.title,
+        meeting_sessions.started_at,
+        meeting_sessions.ended_at,
+        meeting_participants.role
+    FROM meeting_sessions
+    LEFT JOIN meeting_participants
+        ON
+            meeting_participants.meeting_id = meeting_sessions.meeting_id AND
+            meeting_participants.user_id = $1::UUID
+    WHERE
+        (
+            meeting_sessions.presenter = $1::UUID OR
+            meeting_participants.user_id IS NOT NULL OR
+            EXISTS (
+                SELECT 1
+                FROM meeting_invitees
+                WHERE
+                    meeting_invitees.meeting_id = meeting_sessions.meeting_id AND
+                    meeting_invitees.user_id = $1::UUID
+            )
+        ) AND
+        ($2::VARCHAR(16) IS NULL OR meeting_sessions.state = $2::VARCHAR(16))
+    ORDER BY
+        meeting_sessions.started_at DESC
+    ;
+"#;
+
+async fn list_meeting_history(
+	client: &Client,
+	user_id: &Uuid,
+	state: Option<MeetingState>,
+) -> Result<Vec<MeetingHistoryEntry>, ServiceError> {
+	let state = state.map(MeetingState::as_str);
+
+	let statement = client.prepare(LIST_MEETING_HISTORY_QUERY).await?;
+	let rows = client.query(&statement, &[user_id, &state]).await?;
+
+	rows.iter()
+		.map(|row| {
+			Ok(MeetingHistoryEntry {
+				meeting_id: row.get(0),
+				presenter: row.get(1),
+				state: row.get::<_, &str>(2).parse()?,
+				title: row.get(3),
+				started_at: row.get(4),
+				ended_at: row.get(5),
+				role: row.get::<_, Option<&str>>(6).map(str::parse).transpose()?,
+			})
+		})
+		.collect()
+}
@@ -0,0 +1,95 @@
+//! In-memory hub that fans meeting session events out to connected WebSocket clients.
+//!
+//! The hub only knows about connections made to *this* server instance.
+
+use actix::prelude::*;
+use log::debug;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::meetings::events::MeetingEvent;
+
+/// Actor keeping track of which WebSocket connections are subscribed to which meeting session.
+#[derive(Default)]
+pub struct MeetingHub {
+	sessions: HashMap<Uuid, HashMap<usize, Recipient<MeetingEvent>>>,
+	next_connection_id: usize,
+}
+
+impl Actor for MeetingHub {
+	type Context = Context<Self>;
+}
+
+/// Subscribe a connection to the events of a meeting session. Returns the connection id which
+/// must be used to `Disconnect` later.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Connect {
+	pub meeting_id: Uuid,
+	pub recipient: Recipient<MeetingEvent>,
+}
+
+/// Unsubscribe a connection from the events of a meeting session.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+	pub meeting_id: Uuid,
+	pub connection_id: usize,
+}
+
+/// Push an event to every connection subscribed to the meeting session.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Publish {
+	pub meeting_id: Uuid,
+	pub event: MeetingEvent,
+}
+
+impl Handler<Connect> for MeetingHub {
+	type Result = usize;
+
+	fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
+		let connection_id = self.next_connection_id;
+		self.next_connection_id = self.next_connection_id.wrapping_add(1);
+
+		self.sessions
+			.entry(msg.meeting_id)
+			.or_default()
+			.insert(connection_id, msg.recipient);
+
+		debug!(
+			"Connection {} subscribed to meeting session {}",
+			connection_id, msg.meeting_id
+		);
+
+		connection_id
+	}
+}
+
+impl Handler<Disconnect> for MeetingHub {
+	type Result = ();
+
+	fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
+		if let Some(connections) = self.sessions.get_mut(&msg.meeting_id) {
+			connections.remove(&msg.connection_id);
+
+			if connections.is_empty() {
+				self.sessions.remove(&msg.meeting_id);
+			}
+		}
+	}
+}
+
+impl Handler<Publish> for MeetingHub {
+	type Result = ();
+
+	fn handle(&mut self, msg: Publish, _: &mut Context<Self>) {
+		if let Some(connections) = self.sessions.get(&msg.meeting_id) {
+			for recipient in connections.values() {
+				// A full or closed mailbox means the connection is going away; it will
+				// `Disconnect` itself.
+				let _ = recipient.do_send(msg.event.clone());
+			}
+		}
+	}
+}
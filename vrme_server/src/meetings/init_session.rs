@@ -9,6 +9,7 @@ use uuid::Uuid;
 
 use crate::auth::auth_payload::AuthPayload;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::participants::{add_participant, get_participants, ParticipantRole};
 use crate::service_errors::ServiceError;
 
 /// Response payload upon successful meeting session initialization.
@@ -16,13 +17,15 @@ use crate::service_errors::ServiceError;
 pub struct MeetingSessionResponsePayload {
 	pub meeting_id: Uuid,
 	pub presenter: Uuid,
+	pub co_presenters: Vec<Uuid>,
 	pub listeners: Vec<Uuid>,
 	pub started_at: chrono::NaiveDateTime,
 }
 
 /// Handler for initializing a meeting session. It must be intiated by an authenticated `presenter`.
-/// If a meeting session associated with the *presenter* already exists, then info on th existing
-/// meeting session is returned; otherwise a fresh meeting session is created and its info returned.
+/// If an ad-hoc meeting session associated with the *presenter* is still live, then info on th
+/// existing meeting session is returned; otherwise a fresh meeting session is created and its info
+/// returned.
 pub async fn handle_init_session(
 	pool: web::Data<PersistentConnectionPool>,
 	auth: BearerAuth,
@@ -41,21 +44,18 @@ const UPSERT_MEETING_SESSION_QUERY: &str = r#"
     (
         meeting_id,
         presenter,
-        listeners,
         started_at
     )
     VALUES
     (
         $1::UUID,
         $2::UUID,
-        $3::UUID[],
-        $4::TIMESTAMP
+        $3::TIMESTAMP
     )
     ON CONFLICT DO NOTHING
     RETURNING
         meeting_id,
         presenter,
-        listeners,
         started_at
     ;
 "#;
@@ -64,12 +64,13 @@ const GET_SESSION_INFO_QUERY: &str = r#"
     SELECT
         meeting_id,
         presenter,
-        listeners,
         started_at
     FROM
         meeting_sessions
     WHERE
-        presenter = $1::UUID
+        presenter = $1::UUID AND
+        scheduled_start IS NULL AND
+        state <> 'ended'
     ;
 "#;
 
@@ -80,17 +81,16 @@ async fn create_new_session_or_return_existing(
 	let upsert_statement = client.prepare(UPSERT_MEETING_SESSION_QUERY).await?;
 
 	let meeting_id = Uuid::new_v4();
-	let listeners: Vec<Uuid> = Vec::new();
 	let started_at = chrono::Utc::now().naive_utc();
 
 	let rows = client
 		.query(
 			&upsert_statement,
-			&[&meeting_id, &presenter_id, &listeners, &started_at],
+			&[&meeting_id, &presenter_id, &started_at],
 		)
 		.await?;
 
-	let (meeting_id, presenter_id, listeners_ids, started_at) = if rows.is_empty() {
+	let (meeting_id, started_at, participants) = if rows.is_empty() {
 		// Conflict: a meeting session already is associated with the `presenter_id`.
 
 		let get_session_statement = client.prepare(GET_SESSION_INFO_QUERY).await?;
@@ -99,21 +99,33 @@ async fn create_new_session_or_return_existing(
 			.query_one(&get_session_statement, &[&presenter_id])
 			.await?;
 
-		(row.get(0), row.get(1), row.get(2), row.get(3))
+		let meeting_id: Uuid = row.get(0);
+		let participants = get_participants(client, &meeting_id).await?;
+
+		(meeting_id, row.get(2), participants)
 	} else {
 		// New meeting session created successfully.
-		(
-			rows[0].get(0),
-			rows[0].get(1),
-			rows[0].get(2),
-			rows[0].get(3),
+		let meeting_id: Uuid = rows[0].get(0);
+		add_participant(
+			client,
+			&meeting_id,
+			presenter_id,
+			ParticipantRole::Presenter,
 		)
+		.await?;
+
+		(meeting_id, rows[0].get(2), None)
 	};
 
+	let (co_presenters, listeners) = participants
+		.map(|participants| (participants.co_presenters, participants.listeners))
+		.unwrap_or_default();
+
 	Ok(MeetingSessionResponsePayload {
 		meeting_id,
-		presenter: presenter_id,
-		listeners: listeners_ids,
+		presenter: *presenter_id,
+		co_presenters,
+		listeners,
 		started_at,
 	})
 }
@@ -0,0 +1,723 @@
+//! Invites to join a meeting session.
+//!
+//! Instead of adding each listener by their uuid, the presenter mints invites at
+//! `POST /meetings/{meeting_id}/invites` and listeners join by themselves at `POST /meetings/join`
+//! with either:
+//!
+//! - the invite's join code, e.g. `K7QM-3XPD`, which is short and easy to type on a VR keyboard.
+//!   Join codes are case-insensitive, the separator is optional, and characters that are easily
+//!   mistaken for one another (`0`/`O`, `1`/`I`/`L`) are never used.
+//! - the invite's link token, to be embedded in invite links: the invite id signed with
+//!   `auth.token_secret`, which cannot be guessed.
+//!
+//! An invite expires, and may be limited to a number of uses. Joining a meeting session the user
+//! already belongs to does not use up the invite. Joining is subject to the admission settings of
+//! the meeting session (see `crate::meetings::admission`). The presenter lists the invites at
+//! `GET /meetings/{meeting_id}/invites` and revokes them at
+//! `DELETE /meetings/{meeting_id}/invites/{invite_id}`.
+//!
+//! Invitees of a scheduled meeting session (see `crate::meetings::schedule`) need no invite: they
+//! join at `POST /meetings/{meeting_id}/join`, bypassing the lobby and the lock of the meeting
+//! session like listeners added by the presenter.
+
+use actix::Addr;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::{Duration, NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use log::info;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::admission::{
+	enter_lobby, get_admission_settings, get_lobby_status, leave_lobby, LobbyStatus,
+};
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::init_session::MeetingSessionResponsePayload;
+use crate::meetings::leave::publish;
+use crate::meetings::moderation::is_banned;
+use crate::meetings::participants::{admit_listener, get_participants, require_presenter};
+use crate::service_errors::ServiceError;
+use crate::settings::{AuthSettings, Settings};
+
+const PRESENTER_ACTION: &str = "manage invites";
+
+/// Characters join codes are made of.
+const JOIN_CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+
+/// Length of a join code, without separator.
+pub const JOIN_CODE_LEN: usize = 8;
+
+/// How many hours an invite is valid for unless requested otherwise.
+pub const DEFAULT_INVITE_VALIDITY: u32 = 24;
+
+/// Maximum number of hours an invite can be valid for.
+pub const MAX_INVITE_VALIDITY: u32 = 30 * 24;
+
+/// How many join codes are generated before giving up if they are all taken.
+const MAX_JOIN_CODE_ATTEMPTS: usize = 5;
+
+/// Payload to mint an invite. Both fields are optional, so `{}` mints an invite with the defaults.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateInviteRequest {
+	/// Hours until the invite expires. Defaults to `DEFAULT_INVITE_VALIDITY`.
+	pub expires_in: Option<u32>,
+	/// How many users may join with the invite. Unlimited if unset.
+	pub max_uses: Option<u32>,
+}
+
+/// An invite as shown to the presenter.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Invite {
+	pub invite_id: Uuid,
+	pub meeting_id: Uuid,
+	/// Join code to type in, e.g. `K7QM-3XPD`.
+	pub code: String,
+	/// Token to embed in invite links.
+	pub token: String,
+	/// When the invite was minted (UTC).
+	pub created_at: NaiveDateTime,
+	/// When the invite expires (UTC).
+	pub expires_at: NaiveDateTime,
+	pub max_uses: Option<i32>,
+	/// How many users joined with the invite.
+	pub uses: i32,
+}
+
+impl Invite {
+	fn from_row(key: &hmac::Key, row: &Row) -> Self {
+		let invite_id = row.get(0);
+
+		Self {
+			invite_id,
+			meeting_id: row.get(1),
+			code: format_join_code(row.get(2)),
+			token: sign_invite(key, &invite_id),
+			created_at: row.get(3),
+			expires_at: row.get(4),
+			max_uses: row.get(5),
+			uses: row.get(6),
+		}
+	}
+}
+
+/// Handler for minting an invite at `POST /meetings/{meeting_id}/invites`. Only the presenter can
+/// mint invites. Responds with `201 Created` and the `Invite`.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: `expires_in` or `max_uses` is out of range.
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist.
+pub async fn handle_create_invite(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+	request: web::Json<CreateInviteRequest>,
+) -> Result<HttpResponse, Error> {
+	let expires_in = request.expires_in.unwrap_or(DEFAULT_INVITE_VALIDITY);
+	if !(1..=MAX_INVITE_VALIDITY).contains(&expires_in) {
+		return Err(ServiceError::BadRequest(format!(
+			"`expires_in` must be between 1 and {} hours",
+			MAX_INVITE_VALIDITY
+		))
+		.into());
+	}
+
+	let max_uses = match request.max_uses {
+		Some(0) => {
+			return Err(
+				ServiceError::BadRequest("`max_uses` must be at least 1".to_string()).into(),
+			)
+		}
+		Some(max_uses) => Some(max_uses.min(i32::MAX as u32) as i32),
+		None => None,
+	};
+
+	let client = pool.get().await?;
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	let expires_at = Utc::now().naive_utc() + Duration::hours(expires_in as i64);
+	let row = insert_invite(&client, &meeting_id, &expires_at, max_uses).await?;
+	let invite = Invite::from_row(&invite_key(&settings.auth), &row);
+
+	info!(
+		"Minted invite `{}` to meeting session `{}`",
+		&invite.invite_id, &invite.meeting_id
+	);
+
+	Ok(HttpResponse::Created().json(invite))
+}
+
+/// Handler for listing the invites which can still be used at
+/// `GET /meetings/{meeting_id}/invites`. Only the presenter can list invites.
+pub async fn handle_list_invites(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	let key = invite_key(&settings.auth);
+	let invites: Vec<Invite> = list_invites(&client, &meeting_id)
+		.await?
+		.iter()
+		.map(|row| Invite::from_row(&key, row))
+		.collect();
+
+	Ok(HttpResponse::Ok().json(invites))
+}
+
+/// Handler for revoking an invite at `DELETE /meetings/{meeting_id}/invites/{invite_id}`. Only the
+/// presenter can revoke invites. Users who already joined stay in the meeting session.
+pub async fn handle_revoke_invite(
+	pool: web::Data<PersistentConnectionPool>,
+	path: web::Path<(Uuid, Uuid)>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, invite_id) = path.into_inner();
+
+	let client = pool.get().await?;
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	if !revoke_invite(&client, &meeting_id, &invite_id).await? {
+		return Err(ServiceError::NotFound("No matching invite found".to_string()).into());
+	}
+
+	info!(
+		"Revoked invite `{}` to meeting session `{}`",
+		&invite_id, &meeting_id
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Required payload to join a meeting session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JoinMeetingRequest {
+	/// Join code or link token of an invite.
+	pub code: String,
+}
+
+/// Handler for joining a meeting session as a listener with an invite at `POST /meetings/join`.
+/// Responds with the meeting session, or with `202 Accepted` while the user waits in the lobby:
+///
+/// ```json
+/// {
+///     "meeting_id": "4c9e9f2e-8a55-4a5d-9d3c-2f3a8c1f3b7e",
+///     "status": "waiting"
+/// }
+/// ```
+///
+/// Users waiting in the lobby join again with the same invite to learn whether they were admitted.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the meeting session is locked, or the presenter rejected or banned the user.
+/// - `404 Not Found`: no invite matches the code, or it was revoked, has expired or is used up.
+/// - `409 Conflict`: the meeting session is full.
+pub async fn handle_join_meeting(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	hub: web::Data<Addr<MeetingHub>>,
+	identity: Identity,
+	request: web::Json<JoinMeetingRequest>,
+) -> Result<HttpResponse, Error> {
+	let invalid_code = || {
+		ServiceError::NotFound("The join code is invalid, has expired or is used up".to_string())
+	};
+
+	let invite_ref = match verify_invite_token(&invite_key(&settings.auth), &request.code) {
+		Some(invite_id) => InviteRef::Id(invite_id),
+		None => InviteRef::Code(normalize_join_code(&request.code).ok_or_else(invalid_code)?),
+	};
+
+	let user_id = identity.user_id;
+	let mut client = pool.get().await?;
+
+	let outcome = join_meeting(
+		&mut client,
+		&settings,
+		&JoinRef::Invite(invite_ref),
+		&user_id,
+	)
+	.await?
+	.ok_or_else(invalid_code)?;
+
+	Ok(join_response(&hub, &user_id, outcome))
+}
+
+/// Handler for joining a scheduled meeting session the user is invited to as a listener at
+/// `POST /meetings/{meeting_id}/join`. Responds with the meeting session.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the presenter banned the user.
+/// - `404 Not Found`: the meeting session does not exist, has ended, or the user is not invited to
+///   it.
+/// - `409 Conflict`: the meeting session is full.
+pub async fn handle_join_scheduled_meeting(
+	pool: web::Data<PersistentConnectionPool>,
+	settings: web::Data<Settings>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let user_id = identity.user_id;
+	let mut client = pool.get().await?;
+
+	let outcome = join_meeting(
+		&mut client,
+		&settings,
+		&JoinRef::Invitee(*meeting_id),
+		&user_id,
+	)
+	.await?
+	.ok_or_else(|| {
+		ServiceError::NotFound(
+			"No scheduled meeting session you are invited to was found".to_string(),
+		)
+	})?;
+
+	Ok(join_response(&hub, &user_id, outcome))
+}
+
+/// Publish the outcome of the user joining a meeting session, and respond with it.
+fn join_response(hub: &Addr<MeetingHub>, user_id: &Uuid, outcome: JoinOutcome) -> HttpResponse {
+	let user_id = *user_id;
+
+	let (meeting_session, joined) = match outcome {
+		JoinOutcome::Joined(meeting_session, joined) => (meeting_session, joined),
+		JoinOutcome::Waiting(meeting_id, entered) => {
+			if entered {
+				publish(
+					hub,
+					&meeting_id,
+					MeetingEvent::ParticipantWaiting { user_id },
+				);
+			}
+
+			return HttpResponse::Accepted()
+				.json(json!({ "meeting_id": meeting_id, "status": "waiting" }));
+		}
+	};
+
+	if joined {
+		publish(
+			hub,
+			&meeting_session.meeting_id,
+			MeetingEvent::ParticipantJoined { user_id },
+		);
+
+		info!(
+			"User `{}` joined meeting session `{}`",
+			&user_id, &meeting_session.meeting_id
+		);
+	}
+
+	HttpResponse::Ok().json(meeting_session)
+}
+
+/// An invite as referred to by a listener.
+#[derive(Debug)]
+enum InviteRef {
+	/// Normalized join code.
+	Code(String),
+	/// Invite id out of a link token.
+	Id(Uuid),
+}
+
+/// What entitles a listener to join a meeting session.
+#[derive(Debug)]
+enum JoinRef {
+	/// An invite, subject to the admission settings of its meeting session.
+	Invite(InviteRef),
+	/// Being invited to the scheduled meeting session with the given id.
+	Invitee(Uuid),
+}
+
+async fn insert_invite(
+	client: &Client,
+	meeting_id: &Uuid,
+	expires_at: &NaiveDateTime,
+	max_uses: Option<i32>,
+) -> Result<Row, ServiceError> {
+	let now = Utc::now().naive_utc();
+	let invite_id = Uuid::new_v4();
+
+	let statement = client.prepare(INSERT_INVITE_QUERY).await?;
+
+	for _ in 0..MAX_JOIN_CODE_ATTEMPTS {
+		let code = generate_join_code();
+		let row = client
+			.query_opt(
+				&statement,
+				&[&invite_id, meeting_id, &code, &now, expires_at, &max_uses],
+			)
+			.await?;
+
+		if let Some(row) = row {
+			return Ok(row);
+		}
+	}
+
+	Err(ServiceError::InternalServerError(
+		"Failed to generate a unique join code".to_string(),
+	))
+}
+
+async fn list_invites(client: &Client, meeting_id: &Uuid) -> Result<Vec<Row>, ServiceError> {
+	let now = Utc::now().naive_utc();
+	let statement = client.prepare(LIST_INVITES_QUERY).await?;
+	Ok(client.query(&statement, &[meeting_id, &now]).await?)
+}
+
+/// Revoke the invite. Returns whether it existed and was not yet revoked.
+async fn revoke_invite(
+	client: &Client,
+	meeting_id: &Uuid,
+	invite_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+	let statement = client.prepare(REVOKE_INVITE_QUERY).await?;
+	let revoked = client
+		.execute(&statement, &[invite_id, meeting_id, &now])
+		.await?;
+	Ok(revoked > 0)
+}
+
+/// Outcome of joining a meeting session.
+#[derive(Debug)]
+enum JoinOutcome {
+	/// The user takes part in the meeting session. Whether they just joined it.
+	Joined(MeetingSessionResponsePayload, bool),
+	/// The user waits in the lobby of the meeting session. Whether they just entered it.
+	Waiting(Uuid, bool),
+}
+
+/// Add the user to the meeting session as a listener, unless they already take part in it.
+///
+/// With an invite, the user is added to the lobby instead if the meeting session has one, unless
+/// they already wait in it, and the invite is used up. Invitees of a scheduled meeting session
+/// bypass its lobby and lock. Returns `None` if the invite cannot be used or the user is not
+/// invited.
+async fn join_meeting(
+	client: &mut Client,
+	settings: &Settings,
+	join_ref: &JoinRef,
+	user_id: &Uuid,
+) -> Result<Option<JoinOutcome>, ServiceError> {
+	let row = match join_ref {
+		JoinRef::Invite(invite_ref) => {
+			let (code, invite_id) = match invite_ref {
+				InviteRef::Code(code) => (Some(code), None),
+				InviteRef::Id(invite_id) => (None, Some(invite_id)),
+			};
+			let now = Utc::now().naive_utc();
+
+			let statement = client.prepare(FIND_INVITE_QUERY).await?;
+			client
+				.query_opt(&statement, &[&code, &invite_id, &now])
+				.await?
+		}
+		JoinRef::Invitee(meeting_id) => {
+			let statement = client.prepare(FIND_INVITATION_QUERY).await?;
+			client.query_opt(&statement, &[meeting_id, user_id]).await?
+		}
+	};
+
+	let row = match row {
+		Some(row) => row,
+		None => return Ok(None),
+	};
+
+	let invite_id: Option<Uuid> = row.get(0);
+	let meeting_id: Uuid = row.get(1);
+
+	let mut participants = match get_participants(client, &meeting_id).await? {
+		Some(participants) => participants,
+		None => return Ok(None),
+	};
+
+	let mut joined = false;
+
+	if !participants.contains(user_id) {
+		if is_banned(client, &meeting_id, user_id).await? {
+			return Err(ServiceError::Forbidden(
+				"You are banned from the meeting session".to_string(),
+			));
+		}
+
+		if let Some(invite_id) = invite_id {
+			let admission = match get_admission_settings(client, settings, &meeting_id).await? {
+				Some(admission) => admission,
+				None => return Ok(None),
+			};
+
+			match get_lobby_status(client, &meeting_id, user_id).await? {
+				Some(LobbyStatus::Waiting) => {
+					return Ok(Some(JoinOutcome::Waiting(meeting_id, false)))
+				}
+				Some(LobbyStatus::Rejected) => {
+					return Err(ServiceError::Forbidden(
+						"The presenter rejected your request to join the meeting session"
+							.to_string(),
+					))
+				}
+				None => {}
+			}
+
+			if admission.locked {
+				return Err(ServiceError::Forbidden(
+					"The meeting session is locked".to_string(),
+				));
+			}
+
+			let statement = client.prepare(USE_INVITE_QUERY).await?;
+			if client.execute(&statement, &[&invite_id]).await? == 0 {
+				return Ok(None);
+			}
+
+			if admission.lobby {
+				let entered = enter_lobby(client, &meeting_id, user_id).await?;
+				return Ok(Some(JoinOutcome::Waiting(meeting_id, entered)));
+			}
+		}
+
+		joined = match admit_listener(
+			client,
+			&meeting_id,
+			user_id,
+			settings.meetings.default_max_participants,
+		)
+		.await
+		{
+			Ok(joined) => joined,
+			Err(e) => {
+				// Joining a full meeting session does not use up the invite.
+				if let Some(invite_id) = invite_id {
+					let statement = client.prepare(RELEASE_INVITE_QUERY).await?;
+					client.execute(&statement, &[&invite_id]).await?;
+				}
+				return Err(e);
+			}
+		};
+
+		if invite_id.is_none() {
+			leave_lobby(client, &meeting_id, user_id).await?;
+		}
+
+		if joined {
+			participants.listeners.push(*user_id);
+		}
+	}
+
+	let meeting_session = MeetingSessionResponsePayload {
+		meeting_id,
+		presenter: participants.presenter,
+		co_presenters: participants.co_presenters,
+		listeners: participants.listeners,
+		started_at: row.get(2),
+	};
+
+	Ok(Some(JoinOutcome::Joined(meeting_session, joined)))
+}
+
+const INSERT_INVITE_QUERY: &str = r#"
+    INSERT INTO meeting_invites
+        (invite_id, meeting_id, code, created_at, expires_at, max_uses)
+    VALUES
+        ($1::UUID, $2::UUID, $3::VARCHAR(16), $4::TIMESTAMP, $5::TIMESTAMP, $6::INTEGER)
+    ON CONFLICT (code) DO NOTHING
+    RETURNING
+        invite_id,
+        meeting_id,
+        code,
+        created_at,
+        expires_at,
+        max_uses,
+        uses
+    ;
+"#;
+
+const LIST_INVITES_QUERY: &str = r#"
+    SELECT
+        invite_id,
+        meeting_id,
+        code,
+        created_at,
+        expires_at,
+        max_uses,
+        uses
+    FROM meeting_invites
+    WHERE
+        meeting_id = $1::UUID AND
+        revoked_at IS NULL AND
+        expires_at > $2::TIMESTAMP AND
+        (max_uses IS NULL OR uses < max_uses)
+    ORDER BY
+        created_at
+    ;
+"#;
+
+const REVOKE_INVITE_QUERY: &str = r#"
+    UPDATE meeting_invites
+    SET
+        revoked_at = $3::TIMESTAMP
+    WHERE
+        invite_id = $1::UUID AND
+        meeting_id = $2::UUID AND
+        revoked_at IS NULL
+    ;
+"#;
+
+const FIND_INVITE_QUERY: &str = r#"
+    SELECT
+        meeting_invites.invite_id,
+        meeting_sessions.meeting_id,
+        meeting_sessions.started_at
+    FROM meeting_invites
+    INNER JOIN meeting_sessions
+        ON meeting_sessions.meeting_id = meeting_invites.meeting_id
+    WHERE
+        (meeting_invites.code = $1::VARCHAR(16) OR meeting_invites.invite_id = $2::UUID) AND
+        meeting_invites.revoked_at IS NULL AND
+        meeting_invites.expires_at > $3::TIMESTAMP
+    ;
+"#;
+
+// Selects the same columns as `FIND_INVITE_QUERY`, without an invite.
+const FIND_INVITATION_QUERY: &str = r#"
+    SELECT
+        NULL::UUID,
+        meeting_sessions.meeting_id,
+        meeting_sessions.started_at
+    FROM meeting_invitees
+    INNER JOIN meeting_sessions
+        ON meeting_sessions.meeting_id = meeting_invitees.meeting_id
+    WHERE
+        meeting_invitees.meeting_id = $1::UUID AND
+        meeting_invitees.user_id = $2::UUID
+    ;
+"#;
+
+// Checking and incrementing `uses` in one statement keeps concurrent joins from exceeding
+// `max_uses`.
+const USE_INVITE_QUERY: &str = r#"
+    UPDATE meeting_invites
+    SET
+        uses = uses + 1
+    WHERE
+        invite_id = $1::UUID AND
+        (max_uses IS NULL OR uses < max_uses)
+    ;
+"#;
+
+const RELEASE_INVITE_QUERY: &str = r#"
+    UPDATE meeting_invites
+    SET
+        uses = uses - 1
+    WHERE
+        invite_id = $1::UUID AND
+        uses > 0
+    ;
+"#;
+
+fn generate_join_code() -> String {
+	use rand::Rng;
+
+	let mut rng = rand::thread_rng();
+	(0..JOIN_CODE_LEN)
+		.map(|_| JOIN_CODE_ALPHABET[rng.gen_range(0, JOIN_CODE_ALPHABET.len())] as char)
+		.collect()
+}
+
+/// Format a normalized join code for display, e.g. `K7QM3XPD` as `K7QM-3XPD`.
+fn format_join_code(code: &str) -> String {
+	let (first, second) = code.split_at(code.len() / 2);
+	format!("{}-{}", first, second)
+}
+
+/// Normalize a join code as typed in by a user, or `None` if it cannot be a join code.
+fn normalize_join_code(input: &str) -> Option<String> {
+	let code: String = input
+		.chars()
+		.filter(|c| *c != '-' && !c.is_whitespace())
+		.map(|c| c.to_ascii_uppercase())
+		.collect();
+
+	if code.len() == JOIN_CODE_LEN && code.bytes().all(|b| JOIN_CODE_ALPHABET.contains(&b)) {
+		Some(code)
+	} else {
+		None
+	}
+}
+
+/// Key that link tokens are signed with.
+fn invite_key(auth_settings: &AuthSettings) -> hmac::Key {
+	hmac::Key::new(hmac::HMAC_SHA256, auth_settings.token_secret.as_bytes())
+}
+
+/// Link token of an invite: `{invite_id}.{signature}`.
+fn sign_invite(key: &hmac::Key, invite_id: &Uuid) -> String {
+	let signature = hmac::sign(key, invite_id.as_bytes());
+
+	format!(
+		"{}.{}",
+		invite_id.to_simple(),
+		base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+	)
+}
+
+/// Get the invite id out of a link token, or `None` if it is not a validly signed link token.
+fn verify_invite_token(key: &hmac::Key, token: &str) -> Option<Uuid> {
+	let mut parts = token.trim().splitn(2, '.');
+	let invite_id = Uuid::parse_str(parts.next()?).ok()?;
+	let signature = base64::decode_config(parts.next()?, base64::URL_SAFE_NO_PAD).ok()?;
+
+	hmac::verify(key, invite_id.as_bytes(), &signature).ok()?;
+	Some(invite_id)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_join_code_normalization() {
+		let code = generate_join_code();
+		assert_eq!(normalize_join_code(&code), Some(code.clone()));
+		assert_eq!(normalize_join_code(&format_join_code(&code)), Some(code));
+
+		assert_eq!(
+			normalize_join_code(" k7qm-3xpd "),
+			Some("K7QM3XPD".to_string())
+		);
+		assert_eq!(normalize_join_code("K7QM-3XP"), None);
+		assert_eq!(normalize_join_code("K7QM-3XP0"), None);
+	}
+
+	#[test]
+	fn test_invite_token_is_verified() {
+		let key = hmac::Key::new(hmac::HMAC_SHA256, b"first secret");
+		let other_key = hmac::Key::new(hmac::HMAC_SHA256, b"second secret");
+		let invite_id = Uuid::new_v4();
+		let token = sign_invite(&key, &invite_id);
+
+		assert_eq!(verify_invite_token(&key, &token), Some(invite_id));
+		assert_eq!(verify_invite_token(&other_key, &token), None);
+
+		let forged = format!("{}{}", Uuid::new_v4().to_simple(), &token[32..]);
+		assert_eq!(verify_invite_token(&key, &forged), None);
+		assert_eq!(verify_invite_token(&key, "K7QM-3XPD"), None);
+	}
+}
@@ -1,102 +1,104 @@
-//! Handler for a listener leaving the meeting session.
+//! Handler for a participant leaving the meeting session.
 
+use actix::Addr;
 use actix_web::web;
 use actix_web::{Error, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::Utc;
 use deadpool_postgres::Client;
 use uuid::Uuid;
 
 use crate::auth::auth_payload::AuthPayload;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::{MeetingHub, Publish};
+use crate::meetings::lifecycle::end_meeting_session;
+use crate::meetings::participants::{
+	check_not_removed, get_participants, remove_participant, transfer_presenter,
+};
 use crate::service_errors::ServiceError;
 
 /// Handler for leaving meeting session.
 ///
-/// If the _presenter_ leaves the meeting session, the meeting session terminates.
+/// If the _presenter_ leaves the meeting session, the co-presenter who joined first takes over, or
+/// the meeting session ends if there is none. A co-presenter or listener leaving only records when
+/// they left.
 pub async fn handle_leave_meeting_session(
 	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
 	meeting_id: web::Path<Uuid>,
 	auth: BearerAuth,
 ) -> Result<HttpResponse, Error> {
 	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
 
-	let client = pool.get().await?;
+	let mut client = pool.get().await?;
 
-	if is_presenter(&client, &meeting_id, &user_id).await? {
-		delete_meeting_session(&client, &meeting_id).await?;
-		Ok(HttpResponse::NoContent().finish())
-	} else if is_listener(&client, &meeting_id, &user_id).await? {
-		delete_meeting_session(&client, &meeting_id).await?;
-		Ok(HttpResponse::NoContent().finish())
-	} else {
-		Err(
+	check_not_removed(&client, &meeting_id, &user_id).await?;
+
+	match get_participants(&client, &meeting_id).await? {
+		Some(participants) if participants.is_presenter(&user_id) => {
+			let successor = hand_over_on_leave(
+				&mut client,
+				&meeting_id,
+				&user_id,
+				&participants.co_presenters,
+			)
+			.await?;
+
+			match successor {
+				Some(successor) => {
+					publish(&hub, &meeting_id, MeetingEvent::ParticipantLeft { user_id });
+					publish(
+						&hub,
+						&meeting_id,
+						MeetingEvent::PresenterChanged { user_id: successor },
+					);
+				}
+				None => {
+					end_meeting_session(&client, &meeting_id).await?;
+					publish(&hub, &meeting_id, MeetingEvent::SessionEnded);
+				}
+			}
+			Ok(HttpResponse::NoContent().finish())
+		}
+		Some(participants) if participants.contains(&user_id) => {
+			remove_participant(&client, &meeting_id, &user_id).await?;
+			publish(&hub, &meeting_id, MeetingEvent::ParticipantLeft { user_id });
+			Ok(HttpResponse::NoContent().finish())
+		}
+		_ => Err(
 			ServiceError::NotFound("User does not belong to the meeting session".to_string())
 				.into(),
-		)
-	}
-}
-
-async fn is_presenter(
-	client: &Client,
-	meeting_id: &Uuid,
-	user_id: &Uuid,
-) -> Result<bool, ServiceError> {
-	if let Some((presenter_id, _)) = get_participants(&client, &meeting_id).await? {
-		Ok(&presenter_id == user_id)
-	} else {
-		Ok(false)
+		),
 	}
 }
 
-const GET_PARTICIPANTS_QUERY: &str = r#"
-    SELECT
-        presenter,
-        listeners
-    FROM
-        meeting_sessions
-    WHERE
-        meeting_id = $1::UUID
-    ;
-"#;
-
-async fn get_participants(
-	client: &Client,
+/// Hand the meeting session over from the leaving presenter to the first of the co-presenters who
+/// can take over. Returns the new presenter, if any.
+async fn hand_over_on_leave(
+	client: &mut Client,
 	meeting_id: &Uuid,
-) -> Result<Option<(Uuid, Vec<Uuid>)>, ServiceError> {
-	let statement = client.prepare(GET_PARTICIPANTS_QUERY).await?;
-
-	let rows = client.query(&statement, &[meeting_id]).await?;
+	presenter: &Uuid,
+	co_presenters: &[Uuid],
+) -> Result<Option<Uuid>, ServiceError> {
+	let now = Utc::now().naive_utc();
 
-	if rows.is_empty() {
-		Ok(None)
-	} else {
-		let (presenter, listeners) = (rows[0].get(0), rows[0].get(1));
-		Ok(Some((presenter, listeners)))
+	for co_presenter in co_presenters {
+		match transfer_presenter(client, meeting_id, presenter, co_presenter, Some(now)).await {
+			Ok(true) => return Ok(Some(*co_presenter)),
+			// The co-presenter left in the meantime or presents another ad-hoc meeting session.
+			Ok(false) | Err(ServiceError::Conflict(_)) => {}
+			Err(e) => return Err(e),
+		}
 	}
-}
 
-async fn is_listener(
-	client: &Client,
-	meeting_id: &Uuid,
-	user_id: &Uuid,
-) -> Result<bool, ServiceError> {
-	if let Some((_, listeners_ids)) = get_participants(&client, &meeting_id).await? {
-		Ok(listeners_ids.contains(user_id))
-	} else {
-		Ok(false)
-	}
+	Ok(None)
 }
 
-const DELETE_MEETING_SESSION_QUERY: &str = r#"
-    DELETE FROM
-        meeting_sessions
-    WHERE
-        meeting_id = $1::UUID
-    ;
-"#;
-
-async fn delete_meeting_session(client: &Client, meeting_id: &Uuid) -> Result<(), ServiceError> {
-	let statement = client.prepare(DELETE_MEETING_SESSION_QUERY).await?;
-	client.query(&statement, &[meeting_id]).await?;
-	Ok(())
+/// Publish `event` to the clients listening to the meeting session.
+pub(crate) fn publish(hub: &Addr<MeetingHub>, meeting_id: &Uuid, event: MeetingEvent) {
+	hub.do_send(Publish {
+		meeting_id: *meeting_id,
+		event,
+	});
 }
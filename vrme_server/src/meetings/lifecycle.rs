@@ -0,0 +1,218 @@
+//! Lifecycle of meeting sessions.
+//!
+//! A meeting session is `scheduled` until its presenter starts it at
+//! `POST /meetings/{meeting_id}/start`, `live` while it takes place, and `ended` once its presenter
+//! ends it at `POST /meetings/{meeting_id}/end` or leaves it. Ad-hoc meeting sessions are live as
+//! soon as they are initialized. Ending a meeting session which has not started cancels it.
+//!
+//! Ended meeting sessions are not deleted: together with their attendance they remain the history
+//! of their participants (see `crate::meetings::history`). They no longer have any participants,
+//! so every operation on them other than reading their history fails with `404 Not Found`.
+
+use actix::Addr;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::Utc;
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::init_session::MeetingSessionResponsePayload;
+use crate::meetings::leave::publish;
+use crate::meetings::participants::{add_participant, require_presenter, ParticipantRole};
+use crate::service_errors::ServiceError;
+
+const PRESENTER_ACTION: &str = "start or end the meeting session";
+
+/// State of a meeting session.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeetingState {
+	Scheduled,
+	Live,
+	Ended,
+}
+
+impl MeetingState {
+	/// Name of the state as stored in `meeting_sessions.state`.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Scheduled => "scheduled",
+			Self::Live => "live",
+			Self::Ended => "ended",
+		}
+	}
+}
+
+impl FromStr for MeetingState {
+	type Err = ServiceError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"scheduled" => Ok(Self::Scheduled),
+			"live" => Ok(Self::Live),
+			"ended" => Ok(Self::Ended),
+			other => Err(ServiceError::InternalServerError(format!(
+				"Unknown meeting session state `{}`",
+				other
+			))),
+		}
+	}
+}
+
+/// Handler for starting a scheduled meeting session at `POST /meetings/{meeting_id}/start`. Only
+/// the presenter may start the meeting session, at any time before its scheduled end. Responds with
+/// the started meeting session.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist or has ended.
+/// - `409 Conflict`: the meeting session is already live, or its scheduled end has passed.
+pub async fn handle_start_meeting_session(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	let user_id = identity.user_id;
+
+	let participants = require_presenter(&client, &meeting_id, &user_id, PRESENTER_ACTION).await?;
+	let started_at = start_meeting_session(&client, &meeting_id, &user_id).await?;
+
+	publish(&hub, &meeting_id, MeetingEvent::SessionStarted);
+
+	info!(
+		"User `{}` started meeting session `{}`",
+		&user_id, &meeting_id
+	);
+
+	Ok(HttpResponse::Ok().json(MeetingSessionResponsePayload {
+		meeting_id: *meeting_id,
+		presenter: participants.presenter,
+		co_presenters: participants.co_presenters,
+		listeners: participants.listeners,
+		started_at,
+	}))
+}
+
+/// Handler for ending a meeting session at `POST /meetings/{meeting_id}/end`. Only the presenter
+/// may end the meeting session. Connected participants are notified that the meeting session
+/// ended.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist or has already ended.
+pub async fn handle_end_meeting_session(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+	let user_id = identity.user_id;
+
+	require_presenter(&client, &meeting_id, &user_id, PRESENTER_ACTION).await?;
+
+	if !end_meeting_session(&client, &meeting_id).await? {
+		return Err(ServiceError::NotFound("No such meeting session found".to_string()).into());
+	}
+
+	publish(&hub, &meeting_id, MeetingEvent::SessionEnded);
+
+	info!(
+		"User `{}` ended meeting session `{}`",
+		&user_id, &meeting_id
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+const START_MEETING_SESSION_QUERY: &str = r#"
+    UPDATE meeting_sessions
+    SET
+        state = 'live',
+        started_at = $2::TIMESTAMP
+    WHERE
+        meeting_id = $1::UUID AND
+        state = 'scheduled' AND
+        scheduled_end > $2::TIMESTAMP
+    RETURNING
+        started_at
+    ;
+"#;
+
+/// Start the scheduled meeting session, adding the presenter to its participants. Returns when it
+/// started.
+async fn start_meeting_session(
+	client: &Client,
+	meeting_id: &Uuid,
+	presenter: &Uuid,
+) -> Result<chrono::NaiveDateTime, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(START_MEETING_SESSION_QUERY).await?;
+	let row = client
+		.query_opt(&statement, &[meeting_id, &now])
+		.await?
+		.ok_or_else(|| {
+			ServiceError::Conflict(
+				"The meeting session is already live or its scheduled end has passed".to_string(),
+			)
+		})?;
+
+	add_participant(client, meeting_id, presenter, ParticipantRole::Presenter).await?;
+
+	Ok(row.get(0))
+}
+
+// Data-modifying CTEs are executed whether or not the main query reads from them. `cancelled` is
+// computed from the state before the update.
+const END_MEETING_SESSION_QUERY: &str = r#"
+    WITH ended AS (
+        UPDATE meeting_sessions
+        SET
+            state = 'ended',
+            ended_at = $2::TIMESTAMP,
+            cancelled = meeting_sessions.state = 'scheduled'
+        WHERE
+            meeting_id = $1::UUID AND
+            state <> 'ended'
+        RETURNING
+            meeting_id
+    ),
+    departed AS (
+        UPDATE meeting_participants
+        SET
+            left_at = $2::TIMESTAMP
+        FROM ended
+        WHERE
+            meeting_participants.meeting_id = ended.meeting_id AND
+            meeting_participants.left_at IS NULL
+    )
+    SELECT
+        meeting_id
+    FROM ended
+    ;
+"#;
+
+/// End the meeting session, recording that all its participants left it. Returns whether it had
+/// not ended yet.
+pub(crate) async fn end_meeting_session(
+	client: &Client,
+	meeting_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(END_MEETING_SESSION_QUERY).await?;
+	let row = client.query_opt(&statement, &[meeting_id, &now]).await?;
+	Ok(row.is_some())
+}
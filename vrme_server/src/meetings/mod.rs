@@ -1,6 +1,18 @@
 //! Meeting session logic.
 
 pub mod add_listener;
+pub mod admission;
+pub mod calendar;
+pub mod events;
 pub mod get_session_info;
+pub mod history;
+pub mod hub;
 pub mod init_session;
+pub mod invites;
 pub mod leave;
+pub mod lifecycle;
+pub mod moderation;
+pub mod participants;
+pub mod presenters;
+pub mod schedule;
+pub mod ws;
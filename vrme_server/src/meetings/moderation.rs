@@ -0,0 +1,241 @@
+//! Removing participants from meeting sessions and banning them.
+//!
+//! The presenter removes a co-presenter or listener at
+//! `DELETE /meetings/{meeting_id}/participants/{user_id}`. Connected clients are notified with a
+//! `participant-removed` event, after which the WebSocket connections of the removed user are
+//! closed; their next request about the meeting session fails with `403 Forbidden`.
+//!
+//! A removed user may join again, unless the presenter also banned them with `?ban=true`. Banned
+//! users can neither join with an invite nor be added or admitted, until the presenter lifts the
+//! ban at `DELETE /meetings/{meeting_id}/bans/{user_id}`.
+
+use actix::Addr;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::error::SqlState;
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::admission::leave_lobby;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::leave::publish;
+use crate::meetings::participants::{kick_participant, require_presenter};
+use crate::service_errors::ServiceError;
+
+const PRESENTER_ACTION: &str = "remove and ban participants";
+
+/// Query of removing a participant.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RemoveParticipantQuery {
+	/// Whether to also ban the user from the meeting session.
+	#[serde(default)]
+	pub ban: bool,
+}
+
+/// A user banned from a meeting session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Ban {
+	pub user_id: Uuid,
+	/// When the user was banned (UTC).
+	pub banned_at: NaiveDateTime,
+}
+
+/// Handler for removing a participant at `DELETE /meetings/{meeting_id}/participants/{user_id}`,
+/// and with `?ban=true` banning them. Users who do not take part in the meeting session can be
+/// banned as well.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the user to remove is the presenter.
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist, the user to remove does not take part in
+///   it and is not to be banned, or the user to ban does not exist.
+pub async fn handle_remove_participant(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	path: web::Path<(Uuid, Uuid)>,
+	query: web::Query<RemoveParticipantQuery>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, user_id) = path.into_inner();
+	let client = pool.get().await?;
+
+	let participants =
+		require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	if participants.is_presenter(&user_id) {
+		return Err(ServiceError::BadRequest(
+			"The presenter cannot be removed; hand the meeting session over first".to_string(),
+		)
+		.into());
+	}
+
+	// Banning first keeps the user from rejoining in between.
+	if query.ban {
+		ban_user(&client, &meeting_id, &user_id).await?;
+		leave_lobby(&client, &meeting_id, &user_id).await?;
+	}
+
+	let removed = kick_participant(&client, &meeting_id, &user_id).await?;
+
+	if !removed && !query.ban {
+		return Err(ServiceError::NotFound(
+			"No such co-presenter or listener of the meeting session found".to_string(),
+		)
+		.into());
+	}
+
+	if removed {
+		publish(
+			&hub,
+			&meeting_id,
+			MeetingEvent::ParticipantRemoved { user_id },
+		);
+	}
+
+	info!(
+		"User `{}` removed user `{}` from meeting session `{}`{}",
+		&identity.user_id,
+		&user_id,
+		&meeting_id,
+		if query.ban { " and banned them" } else { "" }
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler for listing the users banned from the meeting session at
+/// `GET /meetings/{meeting_id}/bans`. Only the presenter may list the bans.
+pub async fn handle_list_bans(
+	pool: web::Data<PersistentConnectionPool>,
+	meeting_id: web::Path<Uuid>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let client = pool.get().await?;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+	let bans = list_bans(&client, &meeting_id).await?;
+
+	Ok(HttpResponse::Ok().json(bans))
+}
+
+/// Handler for lifting a ban at `DELETE /meetings/{meeting_id}/bans/{user_id}`.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist, or the user is not banned from it.
+pub async fn handle_lift_ban(
+	pool: web::Data<PersistentConnectionPool>,
+	path: web::Path<(Uuid, Uuid)>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, user_id) = path.into_inner();
+	let client = pool.get().await?;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	if !lift_ban(&client, &meeting_id, &user_id).await? {
+		return Err(ServiceError::NotFound(
+			"The user is not banned from the meeting session".to_string(),
+		)
+		.into());
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+const BAN_USER_QUERY: &str = r#"
+    INSERT INTO meeting_bans
+        (meeting_id, user_id, banned_at)
+    VALUES
+        ($1::UUID, $2::UUID, $3::TIMESTAMP)
+    ON CONFLICT DO NOTHING
+    ;
+"#;
+
+async fn ban_user(client: &Client, meeting_id: &Uuid, user_id: &Uuid) -> Result<(), ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(BAN_USER_QUERY).await?;
+	match client
+		.execute(&statement, &[meeting_id, user_id, &now])
+		.await
+	{
+		Ok(_) => Ok(()),
+		Err(e) if e.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) => {
+			Err(ServiceError::NotFound("No such account found".to_string()))
+		}
+		Err(e) => Err(e.into()),
+	}
+}
+
+const LIFT_BAN_QUERY: &str = r#"
+    DELETE FROM meeting_bans
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID
+    ;
+"#;
+
+async fn lift_ban(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let statement = client.prepare(LIFT_BAN_QUERY).await?;
+	let lifted = client.execute(&statement, &[meeting_id, user_id]).await?;
+	Ok(lifted > 0)
+}
+
+const LIST_BANS_QUERY: &str = r#"
+    SELECT
+        user_id,
+        banned_at
+    FROM meeting_bans
+    WHERE
+        meeting_id = $1::UUID
+    ORDER BY
+        banned_at
+    ;
+"#;
+
+async fn list_bans(client: &Client, meeting_id: &Uuid) -> Result<Vec<Ban>, ServiceError> {
+	let statement = client.prepare(LIST_BANS_QUERY).await?;
+	let rows = client.query(&statement, &[meeting_id]).await?;
+
+	Ok(rows
+		.iter()
+		.map(|row| Ban {
+			user_id: row.get(0),
+			banned_at: row.get(1),
+		})
+		.collect())
+}
+
+pub(crate) const IS_BANNED_QUERY: &str = r#"
+    SELECT
+        1
+    FROM meeting_bans
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID
+    ;
+"#;
+
+/// Whether the user is banned from the meeting session.
+pub async fn is_banned(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let statement = client.prepare(IS_BANNED_QUERY).await?;
+	let row = client.query_opt(&statement, &[meeting_id, user_id]).await?;
+	Ok(row.is_some())
+}
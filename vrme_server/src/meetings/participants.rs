@@ -0,0 +1,575 @@
+//! Participants of meeting sessions.
+//!
+//! Each participant is a row of `meeting_participants`, recording their role and when they joined
+//! and left the meeting session. Leaving only sets `left_at`, so that the rows double as the
+//! attendance history of the meeting session. `meeting_sessions.presenter` is the current
+//! presenter, who can hand the meeting session over to another participant. Co-presenters share the
+//! presentation controls of the presenter: they may upload and delete the presentation and change
+//! its current page.
+//!
+//! Every query about who takes part in a meeting session goes through this module.
+
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tokio_postgres::error::SqlState;
+use uuid::Uuid;
+
+use crate::meetings::moderation::IS_BANNED_QUERY;
+use crate::service_errors::ServiceError;
+
+/// How a user takes part in a meeting session.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParticipantRole {
+	Presenter,
+	CoPresenter,
+	Listener,
+}
+
+impl ParticipantRole {
+	/// Name of the role as stored in `meeting_participants.role`.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Presenter => "presenter",
+			Self::CoPresenter => "co-presenter",
+			Self::Listener => "listener",
+		}
+	}
+}
+
+impl FromStr for ParticipantRole {
+	type Err = ServiceError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"presenter" => Ok(Self::Presenter),
+			"co-presenter" => Ok(Self::CoPresenter),
+			"listener" => Ok(Self::Listener),
+			other => Err(ServiceError::InternalServerError(format!(
+				"Unknown participant role `{}`",
+				other
+			))),
+		}
+	}
+}
+
+/// Attendance of a user in a meeting session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Participant {
+	pub user_id: Uuid,
+	pub role: ParticipantRole,
+	/// When the user (last) joined the meeting session (UTC).
+	pub joined_at: NaiveDateTime,
+	/// When the user left the meeting session (UTC), if they did.
+	pub left_at: Option<NaiveDateTime>,
+}
+
+/// Who currently takes part in a meeting session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participants {
+	pub presenter: Uuid,
+	pub co_presenters: Vec<Uuid>,
+	pub listeners: Vec<Uuid>,
+}
+
+impl Participants {
+	pub fn is_presenter(&self, user_id: &Uuid) -> bool {
+		&self.presenter == user_id
+	}
+
+	/// Whether the user is the presenter or a co-presenter.
+	pub fn can_present(&self, user_id: &Uuid) -> bool {
+		self.is_presenter(user_id) || self.co_presenters.contains(user_id)
+	}
+
+	/// Whether the user is the presenter, a co-presenter or a listener.
+	pub fn contains(&self, user_id: &Uuid) -> bool {
+		self.can_present(user_id) || self.listeners.contains(user_id)
+	}
+}
+
+const GET_PARTICIPANTS_QUERY: &str = r#"
+    SELECT
+        meeting_sessions.presenter,
+        COALESCE(
+            array_agg(meeting_participants.user_id ORDER BY meeting_participants.joined_at)
+                FILTER (WHERE meeting_participants.role = 'co-presenter'),
+            '{}'
+        ),
+        COALESCE(
+            array_agg(meeting_participants.user_id ORDER BY meeting_participants.joined_at)
+                FILTER (WHERE meeting_participants.role = 'listener'),
+            '{}'
+        )
+    FROM meeting_sessions
+    LEFT JOIN meeting_participants
+        ON
+            meeting_participants.meeting_id = meeting_sessions.meeting_id AND
+            meeting_participants.role <> 'presenter' AND
+            meeting_participants.left_at IS NULL
+    WHERE
+        meeting_sessions.meeting_id = $1::UUID AND
+        meeting_sessions.state <> 'ended'
+    GROUP BY
+        meeting_sessions.meeting_id
+    ;
+"#;
+
+/// Get the current participants of the meeting session, if it exists and has not ended.
+pub async fn get_participants(
+	client: &Client,
+	meeting_id: &Uuid,
+) -> Result<Option<Participants>, ServiceError> {
+	let statement = client.prepare(GET_PARTICIPANTS_QUERY).await?;
+	let row = client.query_opt(&statement, &[meeting_id]).await?;
+
+	Ok(row.map(|row| Participants {
+		presenter: row.get(0),
+		co_presenters: row.get(1),
+		listeners: row.get(2),
+	}))
+}
+
+/// Get the participants of the meeting session, requiring the user to be its presenter.
+///
+/// ## Errors
+///
+/// - `403 Forbidden`: the user is not the presenter, with the message "Only the presenter can
+///   {action}".
+/// - `404 Not Found`: the meeting session does not exist or has ended.
+pub async fn require_presenter(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+	action: &str,
+) -> Result<Participants, ServiceError> {
+	match get_participants(client, meeting_id).await? {
+		Some(participants) if participants.is_presenter(user_id) => Ok(participants),
+		Some(_) => Err(ServiceError::Forbidden(format!(
+			"Only the presenter can {}",
+			action
+		))),
+		None => Err(ServiceError::NotFound(format!(
+			"No associated meeting session with id {} was found",
+			meeting_id
+		))),
+	}
+}
+
+/// Whether the user is the presenter or a co-presenter of the meeting session.
+pub async fn can_present(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	Ok(get_participants(client, meeting_id)
+		.await?
+		.is_some_and(|participants| participants.can_present(user_id)))
+}
+
+/// Whether the user takes part in the meeting session.
+pub async fn is_participant(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	Ok(get_participants(client, meeting_id)
+		.await?
+		.is_some_and(|participants| participants.contains(user_id)))
+}
+
+const LIST_ATTENDANCE_QUERY: &str = r#"
+    SELECT
+        user_id,
+        role,
+        joined_at,
+        left_at
+    FROM meeting_participants
+    WHERE
+        meeting_id = $1::UUID
+    ORDER BY
+        joined_at
+    ;
+"#;
+
+/// List everyone who took part in the meeting session, including those who left.
+pub async fn list_attendance(
+	client: &Client,
+	meeting_id: &Uuid,
+) -> Result<Vec<Participant>, ServiceError> {
+	let statement = client.prepare(LIST_ATTENDANCE_QUERY).await?;
+	let rows = client.query(&statement, &[meeting_id]).await?;
+
+	rows.iter()
+		.map(|row| {
+			Ok(Participant {
+				user_id: row.get(0),
+				role: row.get::<_, &str>(1).parse()?,
+				joined_at: row.get(2),
+				left_at: row.get(3),
+			})
+		})
+		.collect()
+}
+
+// Rejoining a meeting session after leaving it reuses the row of the previous attendance.
+const ADD_PARTICIPANT_QUERY: &str = r#"
+    INSERT INTO meeting_participants
+        (meeting_id, user_id, role, joined_at)
+    VALUES
+        ($1::UUID, $2::UUID, $3::VARCHAR(16), $4::TIMESTAMP)
+    ON CONFLICT (meeting_id, user_id) DO UPDATE
+    SET
+        role = EXCLUDED.role,
+        joined_at = EXCLUDED.joined_at,
+        left_at = NULL,
+        removed_at = NULL
+    WHERE
+        meeting_participants.left_at IS NOT NULL
+    ;
+"#;
+
+/// Add the user to the meeting session. Returns whether they joined, i.e. `false` if they already
+/// take part in it, or fails with `404 Not Found` if the account or meeting session does not
+/// exist.
+pub async fn add_participant(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+	role: ParticipantRole,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(ADD_PARTICIPANT_QUERY).await?;
+	match client
+		.execute(&statement, &[meeting_id, user_id, &role.as_str(), &now])
+		.await
+	{
+		Ok(added) => Ok(added > 0),
+		Err(e) if e.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) => Err(
+			ServiceError::NotFound("No such account or meeting session found".to_string()),
+		),
+		Err(e) => Err(e.into()),
+	}
+}
+
+const REMOVE_PARTICIPANT_QUERY: &str = r#"
+    UPDATE meeting_participants
+    SET
+        left_at = $3::TIMESTAMP
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        left_at IS NULL
+    ;
+"#;
+
+/// Record that the user left the meeting session. Returns whether they took part in it.
+///
+/// See `kick_participant` for the presenter removing a participant.
+pub async fn remove_participant(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(REMOVE_PARTICIPANT_QUERY).await?;
+	let removed = client
+		.execute(&statement, &[meeting_id, user_id, &now])
+		.await?;
+	Ok(removed > 0)
+}
+
+const KICK_PARTICIPANT_QUERY: &str = r#"
+    UPDATE meeting_participants
+    SET
+        left_at = $3::TIMESTAMP,
+        removed_at = $3::TIMESTAMP
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        role <> 'presenter' AND
+        left_at IS NULL
+    ;
+"#;
+
+/// Remove a co-presenter or listener from the meeting session. Returns whether they took part in
+/// it.
+pub async fn kick_participant(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+
+	let statement = client.prepare(KICK_PARTICIPANT_QUERY).await?;
+	let kicked = client
+		.execute(&statement, &[meeting_id, user_id, &now])
+		.await?;
+	Ok(kicked > 0)
+}
+
+const WAS_REMOVED_QUERY: &str = r#"
+    SELECT
+        1
+    FROM meeting_participants
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        removed_at IS NOT NULL
+    ;
+"#;
+
+/// Fails with `403 Forbidden` if the presenter removed the user from the meeting session and they
+/// did not rejoin it since. Handlers for participants check this first, so that removed users learn
+/// why they no longer have access.
+pub async fn check_not_removed(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+) -> Result<(), ServiceError> {
+	let statement = client.prepare(WAS_REMOVED_QUERY).await?;
+
+	match client.query_opt(&statement, &[meeting_id, user_id]).await? {
+		Some(_) => Err(ServiceError::Forbidden(
+			"You were removed from the meeting session".to_string(),
+		)),
+		None => Ok(()),
+	}
+}
+
+const SET_PARTICIPANT_ROLE_QUERY: &str = r#"
+    UPDATE meeting_participants
+    SET
+        role = $3::VARCHAR(16)
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        role <> 'presenter' AND
+        left_at IS NULL
+    ;
+"#;
+
+/// Change the role of a co-presenter or listener of the meeting session. The presenter is changed
+/// with `transfer_presenter` instead. Returns whether the user takes part in the meeting session as
+/// a co-presenter or listener.
+pub async fn set_participant_role(
+	client: &Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+	role: ParticipantRole,
+) -> Result<bool, ServiceError> {
+	let statement = client.prepare(SET_PARTICIPANT_ROLE_QUERY).await?;
+	let updated = client
+		.execute(&statement, &[meeting_id, user_id, &role.as_str()])
+		.await?;
+	Ok(updated > 0)
+}
+
+const SET_PRESENTER_QUERY: &str = r#"
+    UPDATE meeting_sessions
+    SET
+        presenter = $3::UUID
+    WHERE
+        meeting_id = $1::UUID AND
+        presenter = $2::UUID AND
+        state <> 'ended'
+    ;
+"#;
+
+const PROMOTE_TO_PRESENTER_QUERY: &str = r#"
+    UPDATE meeting_participants
+    SET
+        role = 'presenter'
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        role <> 'presenter' AND
+        left_at IS NULL
+    ;
+"#;
+
+// A former presenter who leaves keeps the `presenter` role in the attendance.
+const DEMOTE_PRESENTER_QUERY: &str = r#"
+    UPDATE meeting_participants
+    SET
+        role = CASE WHEN $3::TIMESTAMP IS NULL THEN 'listener' ELSE role END,
+        left_at = $3::TIMESTAMP
+    WHERE
+        meeting_id = $1::UUID AND
+        user_id = $2::UUID AND
+        left_at IS NULL
+    ;
+"#;
+
+/// Hand the meeting session over from the presenter `from` to the co-presenter or listener `to`.
+/// The former presenter stays on as a listener, or leaves the meeting session at `left_at` if
+/// given. Returns whether `from` was the presenter and `to` a co-presenter or listener, or fails
+/// with `409 Conflict` if `to` already presents another ad-hoc meeting session.
+pub async fn transfer_presenter(
+	client: &mut Client,
+	meeting_id: &Uuid,
+	from: &Uuid,
+	to: &Uuid,
+	left_at: Option<NaiveDateTime>,
+) -> Result<bool, ServiceError> {
+	let transaction = client.transaction().await?;
+
+	let statement = transaction.prepare(SET_PRESENTER_QUERY).await?;
+	match transaction
+		.execute(&statement, &[meeting_id, from, to])
+		.await
+	{
+		Ok(0) => return Ok(false),
+		Ok(_) => {}
+		Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+			return Err(ServiceError::Conflict(
+				"The user already presents another ad-hoc meeting session".to_string(),
+			))
+		}
+		Err(e) => return Err(e.into()),
+	}
+
+	let statement = transaction.prepare(PROMOTE_TO_PRESENTER_QUERY).await?;
+	if transaction.execute(&statement, &[meeting_id, to]).await? == 0 {
+		return Ok(false);
+	}
+
+	let statement = transaction.prepare(DEMOTE_PRESENTER_QUERY).await?;
+	transaction
+		.execute(&statement, &[meeting_id, from, &left_at])
+		.await?;
+
+	transaction.commit().await?;
+	Ok(true)
+}
+
+const LOCK_MEETING_SESSION_QUERY: &str = r#"
+    SELECT
+        COALESCE(max_participants, $2::INTEGER)
+    FROM meeting_sessions
+    WHERE
+        meeting_id = $1::UUID AND
+        state <> 'ended'
+    FOR UPDATE
+    ;
+"#;
+
+// The presenter is counted separately, since scheduled meeting sessions have no row for the
+// presenter until they start.
+const COUNT_PARTICIPANTS_QUERY: &str = r#"
+    SELECT
+        count(*),
+        COALESCE(bool_or(user_id = $2::UUID), FALSE)
+    FROM meeting_participants
+    WHERE
+        meeting_id = $1::UUID AND
+        role <> 'presenter' AND
+        left_at IS NULL
+    ;
+"#;
+
+/// Whether a meeting session with `participant_count` participants besides the presenter is full,
+/// i.e. it has as many participants, the presenter included, as `max_participants`.
+fn is_full(participant_count: i64, max_participants: i32) -> bool {
+	participant_count + 1 >= i64::from(max_participants)
+}
+
+/// Add the user to the meeting session as a listener unless it is full, i.e. it already has as
+/// many participants, the presenter included, as its `max_participants` or else
+/// `default_max_participants`. Returns whether they joined like `add_participant`, or fails with
+/// `403 Forbidden` if the user is banned from the meeting session or `409 Conflict` if it is full.
+pub async fn admit_listener(
+	client: &mut Client,
+	meeting_id: &Uuid,
+	user_id: &Uuid,
+	default_max_participants: u32,
+) -> Result<bool, ServiceError> {
+	let now = Utc::now().naive_utc();
+	let default_max_participants = default_max_participants as i32;
+
+	let transaction = client.transaction().await?;
+
+	// Locking the meeting session serializes concurrent joins, so that they cannot exceed the limit.
+	let statement = transaction.prepare(LOCK_MEETING_SESSION_QUERY).await?;
+	let max_participants: i32 = transaction
+		.query_opt(&statement, &[meeting_id, &default_max_participants])
+		.await?
+		.ok_or_else(|| ServiceError::NotFound("No such meeting session found".to_string()))?
+		.get(0);
+
+	let statement = transaction.prepare(IS_BANNED_QUERY).await?;
+	if transaction
+		.query_opt(&statement, &[meeting_id, user_id])
+		.await?
+		.is_some()
+	{
+		return Err(ServiceError::Forbidden(
+			"The user is banned from the meeting session".to_string(),
+		));
+	}
+
+	let statement = transaction.prepare(COUNT_PARTICIPANTS_QUERY).await?;
+	let row = transaction
+		.query_one(&statement, &[meeting_id, user_id])
+		.await?;
+	let (participant_count, already_joined): (i64, bool) = (row.get(0), row.get(1));
+
+	if already_joined {
+		return Ok(false);
+	}
+
+	if is_full(participant_count, max_participants) {
+		return Err(ServiceError::Conflict(format!(
+			"The meeting session is full: at most {} users may take part in it",
+			max_participants
+		)));
+	}
+
+	let statement = transaction.prepare(ADD_PARTICIPANT_QUERY).await?;
+	let added = match transaction
+		.execute(
+			&statement,
+			&[
+				meeting_id,
+				user_id,
+				&ParticipantRole::Listener.as_str(),
+				&now,
+			],
+		)
+		.await
+	{
+		Ok(added) => added,
+		Err(e) if e.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) => {
+			return Err(ServiceError::NotFound("No such account found".to_string()))
+		}
+		Err(e) => return Err(e.into()),
+	};
+
+	transaction.commit().await?;
+	Ok(added > 0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_is_full() {
+		// The presenter alone fills a meeting session for a single participant.
+		assert!(is_full(0, 1));
+
+		assert!(!is_full(0, 2));
+		assert!(is_full(1, 2));
+
+		assert!(!is_full(8, 10));
+		assert!(is_full(9, 10));
+		assert!(is_full(10, 10));
+
+		assert!(!is_full(0, i32::MAX));
+		assert!(is_full(i64::from(i32::MAX) - 1, i32::MAX));
+	}
+}
@@ -0,0 +1,148 @@
+//! Handing a meeting session over and appointing co-presenters.
+//!
+//! - `POST /meetings/{meeting_id}/presenter`: the presenter hands the meeting session over to a
+//!   co-presenter or listener, staying on as a listener.
+//! - `PUT /meetings/{meeting_id}/participants/{user_id}/role`: the presenter makes a listener a
+//!   co-presenter, or a co-presenter a listener again.
+//!
+//! When the presenter leaves, the co-presenter who joined first takes over (see
+//! `crate::meetings::leave`).
+
+use actix::Addr;
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use log::info;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::MeetingHub;
+use crate::meetings::leave::publish;
+use crate::meetings::participants::{
+	require_presenter, set_participant_role, transfer_presenter, ParticipantRole,
+};
+use crate::service_errors::ServiceError;
+
+const PRESENTER_ACTION: &str = "change who presents the meeting session";
+
+/// Required payload to hand a meeting session over.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TransferPresenterRequest {
+	/// The co-presenter or listener to become the presenter.
+	pub presenter: Uuid,
+}
+
+/// Handler for handing the meeting session over at `POST /meetings/{meeting_id}/presenter`.
+/// Connected participants are notified with a `presenter-changed` event.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the user hands the meeting session over to themselves.
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist, or the new presenter does not take part
+///   in it.
+/// - `409 Conflict`: the new presenter already presents another ad-hoc meeting session.
+pub async fn handle_transfer_presenter(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	request: web::Json<TransferPresenterRequest>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let mut client = pool.get().await?;
+	let user_id = identity.user_id;
+	let presenter = request.presenter;
+
+	let participants = require_presenter(&client, &meeting_id, &user_id, PRESENTER_ACTION).await?;
+
+	if presenter == user_id {
+		return Err(ServiceError::BadRequest("You already are the presenter".to_string()).into());
+	}
+
+	if !participants.contains(&presenter) {
+		return Err(ServiceError::NotFound(
+			"The new presenter does not take part in the meeting session".to_string(),
+		)
+		.into());
+	}
+
+	if !transfer_presenter(&mut client, &meeting_id, &user_id, &presenter, None).await? {
+		return Err(ServiceError::Conflict(
+			"The participants of the meeting session changed, please try again".to_string(),
+		)
+		.into());
+	}
+
+	publish(
+		&hub,
+		&meeting_id,
+		MeetingEvent::PresenterChanged { user_id: presenter },
+	);
+
+	info!(
+		"User `{}` handed meeting session `{}` over to user `{}`",
+		&user_id, &meeting_id, &presenter
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Required payload to change the role of a participant.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetParticipantRoleRequest {
+	/// Either `co-presenter` or `listener`.
+	pub role: ParticipantRole,
+}
+
+/// Handler for changing the role of a participant at
+/// `PUT /meetings/{meeting_id}/participants/{user_id}/role`. Connected participants are notified
+/// with a `participant-role-changed` event.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: the role is `presenter`; the meeting session is handed over at
+///   `POST /meetings/{meeting_id}/presenter` instead.
+/// - `403 Forbidden`: the user is not the presenter.
+/// - `404 Not Found`: the meeting session does not exist, or the participant is not a co-presenter
+///   or listener of it.
+pub async fn handle_set_participant_role(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	path: web::Path<(Uuid, Uuid)>,
+	request: web::Json<SetParticipantRoleRequest>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, participant) = path.into_inner();
+	let client = pool.get().await?;
+	let role = request.role;
+
+	require_presenter(&client, &meeting_id, &identity.user_id, PRESENTER_ACTION).await?;
+
+	if role == ParticipantRole::Presenter {
+		return Err(ServiceError::BadRequest(format!(
+			"Hand the meeting session over at `POST /meetings/{}/presenter` instead",
+			meeting_id
+		))
+		.into());
+	}
+
+	if !set_participant_role(&client, &meeting_id, &participant, role).await? {
+		return Err(ServiceError::NotFound(
+			"No such co-presenter or listener of the meeting session found".to_string(),
+		)
+		.into());
+	}
+
+	publish(
+		&hub,
+		&meeting_id,
+		MeetingEvent::ParticipantRoleChanged {
+			user_id: participant,
+			role,
+		},
+	);
+
+	Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,392 @@
+//! Scheduled meeting sessions.
+//!
+//! Besides their single ad-hoc meeting session started at `POST /meetings`, presenters schedule
+//! any number of meeting sessions at `POST /meetings/schedule`. A scheduled meeting session has a
+//! title, an optional description, start and end times, the timezone it is planned in and the
+//! users invited to it. Invitees find it in their calendar feed (see `crate::meetings::calendar`),
+//! and join it at `POST /meetings/{meeting_id}/join` without an invite (see
+//! `crate::meetings::invites`).
+
+use actix_web::web;
+use actix_web::{Error, HttpResponse};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::auth::roles::Identity;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::service_errors::ServiceError;
+
+/// Maximum length of a meeting session's title, in characters.
+pub const TITLE_MAX_LEN: usize = 200;
+
+/// Maximum length of a meeting session's description, in characters.
+pub const DESCRIPTION_MAX_LEN: usize = 5000;
+
+/// Maximum length of a timezone name.
+pub const TIMEZONE_MAX_LEN: usize = 64;
+
+/// Maximum number of users invited to a meeting session.
+pub const MAX_INVITEES: usize = 500;
+
+/// Required payload to schedule a meeting session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScheduleMeetingRequest {
+	pub title: String,
+	pub description: Option<String>,
+	/// RFC 3339 date and time, e.g. `2020-06-01T09:30:00+10:00`.
+	pub start: DateTime<FixedOffset>,
+	/// RFC 3339 date and time, after `start`.
+	pub end: DateTime<FixedOffset>,
+	/// IANA timezone the meeting session is planned in, e.g. `Australia/Sydney`.
+	pub timezone: String,
+	/// Users to invite.
+	#[serde(default)]
+	pub invitees: Vec<Uuid>,
+}
+
+/// Calendar metadata of a scheduled meeting session.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MeetingSchedule {
+	pub title: String,
+	pub description: Option<String>,
+	/// When the meeting session starts (UTC).
+	pub start: NaiveDateTime,
+	/// When the meeting session ends (UTC).
+	pub end: NaiveDateTime,
+	pub timezone: String,
+	pub invitees: Vec<Uuid>,
+}
+
+/// A scheduled meeting session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScheduledMeeting {
+	pub meeting_id: Uuid,
+	pub presenter: Uuid,
+	#[serde(flatten)]
+	pub schedule: MeetingSchedule,
+	/// Whether the meeting session was ended before it started.
+	pub cancelled: bool,
+}
+
+impl ScheduledMeeting {
+	fn from_row(row: &Row) -> Self {
+		Self {
+			meeting_id: row.get(0),
+			presenter: row.get(1),
+			schedule: MeetingSchedule {
+				title: row.get(2),
+				description: row.get(3),
+				start: row.get(4),
+				end: row.get(5),
+				timezone: row.get(6),
+				invitees: row.get(7),
+			},
+			cancelled: row.get(8),
+		}
+	}
+
+	/// Whether the user presents the meeting session or is invited to it.
+	pub fn is_invited(&self, user_id: &Uuid) -> bool {
+		self.presenter == *user_id || self.schedule.invitees.contains(user_id)
+	}
+}
+
+/// Handler for scheduling a meeting session at `POST /meetings/schedule`. The user scheduling the
+/// meeting session is its presenter. Responds with `201 Created` and the `ScheduledMeeting`.
+///
+/// ## Errors
+///
+/// - `400 Bad Request`: a field is invalid, or the meeting session would start in the past.
+/// - `404 Not Found`: an invitee does not exist.
+pub async fn handle_schedule_meeting(
+	pool: web::Data<PersistentConnectionPool>,
+	request: web::Json<ScheduleMeetingRequest>,
+	identity: Identity,
+) -> Result<HttpResponse, Error> {
+	let request = request.into_inner();
+	validate_request_payload(&request, &Utc::now().naive_utc())?;
+
+	let presenter = identity.user_id;
+
+	let mut invitees = request.invitees;
+	invitees.sort();
+	invitees.dedup();
+	invitees.retain(|invitee| invitee != &presenter);
+
+	let meeting = ScheduledMeeting {
+		meeting_id: Uuid::new_v4(),
+		presenter,
+		schedule: MeetingSchedule {
+			title: request.title.trim().to_string(),
+			description: request.description,
+			start: request.start.naive_utc(),
+			end: request.end.naive_utc(),
+			timezone: request.timezone,
+			invitees,
+		},
+		cancelled: false,
+	};
+
+	let mut client = pool.get().await?;
+	insert_scheduled_meeting(&mut client, &meeting).await?;
+
+	info!(
+		"User `{}` scheduled meeting session `{}`",
+		&presenter, &meeting.meeting_id
+	);
+
+	Ok(HttpResponse::Created().json(meeting))
+}
+
+fn validate_request_payload(
+	req: &ScheduleMeetingRequest,
+	now: &NaiveDateTime,
+) -> Result<(), ServiceError> {
+	let title_len = req.title.trim().chars().count();
+	if title_len == 0 || title_len > TITLE_MAX_LEN {
+		return Err(ServiceError::BadRequest(format!(
+			"`title` must be between 1 and {} characters long",
+			TITLE_MAX_LEN
+		)));
+	}
+
+	if let Some(description) = &req.description {
+		if description.chars().count() > DESCRIPTION_MAX_LEN {
+			return Err(ServiceError::BadRequest(format!(
+				"`description` cannot be longer than {} characters",
+				DESCRIPTION_MAX_LEN
+			)));
+		}
+	}
+
+	if req.end <= req.start {
+		return Err(ServiceError::BadRequest(
+			"`end` must be after `start`".to_string(),
+		));
+	}
+
+	if &req.start.naive_utc() < now {
+		return Err(ServiceError::BadRequest(
+			"`start` cannot be in the past".to_string(),
+		));
+	}
+
+	validate_timezone(&req.timezone)?;
+
+	if req.invitees.len() > MAX_INVITEES {
+		return Err(ServiceError::BadRequest(format!(
+			"At most {} users can be invited",
+			MAX_INVITEES
+		)));
+	}
+
+	Ok(())
+}
+
+/// Checks the shape of an IANA timezone name, e.g. `UTC` or `America/Argentina/Buenos_Aires`.
+fn validate_timezone(timezone: &str) -> Result<(), ServiceError> {
+	let valid = timezone.len() <= TIMEZONE_MAX_LEN
+		&& timezone.split('/').all(|part| {
+			!part.is_empty()
+				&& part
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c))
+		});
+
+	if valid {
+		Ok(())
+	} else {
+		Err(ServiceError::BadRequest(
+			"`timezone` must be an IANA timezone name, e.g. `Australia/Sydney`".to_string(),
+		))
+	}
+}
+
+const INSERT_SCHEDULED_MEETING_QUERY: &str = r#"
+    INSERT INTO meeting_sessions
+        (
+            meeting_id,
+            presenter,
+            started_at,
+            title,
+            description,
+            scheduled_start,
+            scheduled_end,
+            timezone,
+            state
+        )
+    VALUES
+        (
+            $1::UUID,
+            $2::UUID,
+            $3::TIMESTAMP,
+            $4::VARCHAR(200),
+            $5::TEXT,
+            $3::TIMESTAMP,
+            $6::TIMESTAMP,
+            $7::VARCHAR(64),
+            'scheduled'
+        )
+    ;
+"#;
+
+const INSERT_INVITEES_QUERY: &str = r#"
+    INSERT INTO meeting_invitees
+        (meeting_id, user_id, invited_at)
+    SELECT
+        $1::UUID, invitee, $3::TIMESTAMP
+    FROM unnest($2::UUID[]) AS invitee
+    ;
+"#;
+
+/// Insert the meeting session together with its invitees. Until the meeting session is started, its
+/// `started_at` is when it is scheduled to start.
+async fn insert_scheduled_meeting(
+	client: &mut Client,
+	meeting: &ScheduledMeeting,
+) -> Result<(), ServiceError> {
+	let schedule = &meeting.schedule;
+	let now = Utc::now().naive_utc();
+
+	let transaction = client.transaction().await?;
+
+	let statement = transaction.prepare(INSERT_SCHEDULED_MEETING_QUERY).await?;
+	transaction
+		.execute(
+			&statement,
+			&[
+				&meeting.meeting_id,
+				&meeting.presenter,
+				&schedule.start,
+				&schedule.title,
+				&schedule.description,
+				&schedule.end,
+				&schedule.timezone,
+			],
+		)
+		.await?;
+
+	let statement = transaction.prepare(INSERT_INVITEES_QUERY).await?;
+	match transaction
+		.execute(&statement, &[&meeting.meeting_id, &schedule.invitees, &now])
+		.await
+	{
+		Ok(_) => {}
+		Err(e) if e.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) => {
+			return Err(ServiceError::NotFound(
+				"No matching account found for an invitee".to_string(),
+			))
+		}
+		Err(e) => return Err(e.into()),
+	}
+
+	transaction.commit().await?;
+	Ok(())
+}
+
+const GET_SCHEDULED_MEETING_QUERY: &str = r#"
+    SELECT
+        meeting_id,
+        presenter,
+        title,
+        description,
+        scheduled_start,
+        scheduled_end,
+        timezone,
+        ARRAY(
+            SELECT user_id
+            FROM meeting_invitees
+            WHERE meeting_invitees.meeting_id = meeting_sessions.meeting_id
+            ORDER BY invited_at, user_id
+        ),
+        cancelled
+    FROM meeting_sessions
+    WHERE
+        meeting_id = $1::UUID AND
+        scheduled_start IS NOT NULL
+    ;
+"#;
+
+/// Get the meeting session, if it exists and is scheduled.
+pub async fn get_scheduled_meeting(
+	client: &Client,
+	meeting_id: &Uuid,
+) -> Result<Option<ScheduledMeeting>, ServiceError> {
+	let statement = client.prepare(GET_SCHEDULED_MEETING_QUERY).await?;
+	let row = client.query_opt(&statement, &[meeting_id]).await?;
+	Ok(row.as_ref().map(ScheduledMeeting::from_row))
+}
+
+const LIST_SCHEDULED_MEETINGS_QUERY: &str = r#"
+    SELECT
+        meeting_id,
+        presenter,
+        title,
+        description,
+        scheduled_start,
+        scheduled_end,
+        timezone,
+        ARRAY(
+            SELECT user_id
+            FROM meeting_invitees
+            WHERE meeting_invitees.meeting_id = meeting_sessions.meeting_id
+            ORDER BY invited_at, user_id
+        ),
+        cancelled
+    FROM meeting_sessions
+    WHERE
+        scheduled_start IS NOT NULL AND
+        (
+            presenter = $1::UUID OR
+            EXISTS (
+                SELECT 1
+                FROM meeting_invitees
+                WHERE
+                    meeting_invitees.meeting_id = meeting_sessions.meeting_id AND
+                    meeting_invitees.user_id = $1::UUID
+            ) OR
+            EXISTS (
+                SELECT 1
+                FROM meeting_participants
+                WHERE
+                    meeting_participants.meeting_id = meeting_sessions.meeting_id AND
+                    meeting_participants.user_id = $1::UUID
+            )
+        )
+    ORDER BY
+        scheduled_start
+    ;
+"#;
+
+/// List the scheduled meeting sessions the user presents, is invited to or took part in.
+pub async fn list_scheduled_meetings(
+	client: &Client,
+	user_id: &Uuid,
+) -> Result<Vec<ScheduledMeeting>, ServiceError> {
+	let statement = client.prepare(LIST_SCHEDULED_MEETINGS_QUERY).await?;
+	let rows = client.query(&statement, &[user_id]).await?;
+	Ok(rows.iter().map(ScheduledMeeting::from_row).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_validate_timezone() {
+		assert!(validate_timezone("UTC").is_ok());
+		assert!(validate_timezone("Australia/Sydney").is_ok());
+		assert!(validate_timezone("America/Argentina/Buenos_Aires").is_ok());
+		assert!(validate_timezone("Etc/GMT+10").is_ok());
+
+		assert!(validate_timezone("").is_err());
+		assert!(validate_timezone("/Sydney").is_err());
+		assert!(validate_timezone("Australia//Sydney").is_err());
+		assert!(validate_timezone("Australia/Sydney Harbour").is_err());
+	}
+}
@@ -0,0 +1,160 @@
+//! WebSocket endpoint pushing real-time meeting session events to participants.
+
+use actix::prelude::*;
+use actix_web::web;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::auth::auth_payload::AuthPayload;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::{Connect, Disconnect, MeetingHub};
+use crate::meetings::participants::{check_not_removed, get_participants};
+use crate::service_errors::ServiceError;
+
+/// How often heartbeat pings are sent to the client.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long without any message from the client before the connection is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Handler for upgrading to a WebSocket connection at `GET /meetings/{meeting_id}/ws`.
+///
+/// Only participants (the presenter, a co-presenter or a listener) of the meeting session may
+/// connect. The server pushes each `MeetingEvent` as a JSON text frame; messages sent by the client
+/// are ignored apart from pings and close frames.
+pub async fn handle_meeting_ws(
+	req: HttpRequest,
+	stream: web::Payload,
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
+	let client = pool.get().await?;
+
+	check_not_removed(&client, &meeting_id, &user_id).await?;
+
+	match get_participants(&client, &meeting_id).await? {
+		Some(participants) if participants.contains(&user_id) => {}
+		_ => {
+			return Err(ServiceError::Forbidden(
+				"Only meeting participants may subscribe to meeting session events".to_string(),
+			)
+			.into())
+		}
+	}
+
+	let socket = MeetingSocket {
+		meeting_id: meeting_id.into_inner(),
+		user_id,
+		connection_id: None,
+		hub: hub.get_ref().clone(),
+		last_heartbeat: Instant::now(),
+	};
+
+	ws::start(socket, &req, stream)
+}
+
+/// A single participant's WebSocket connection.
+struct MeetingSocket {
+	meeting_id: Uuid,
+	user_id: Uuid,
+	/// Assigned by the `MeetingHub` once subscribed.
+	connection_id: Option<usize>,
+	hub: Addr<MeetingHub>,
+	last_heartbeat: Instant,
+}
+
+impl MeetingSocket {
+	fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+		ctx.run_interval(HEARTBEAT_INTERVAL, |socket, ctx| {
+			if Instant::now().duration_since(socket.last_heartbeat) > CLIENT_TIMEOUT {
+				ctx.stop();
+			} else {
+				ctx.ping(b"");
+			}
+		});
+	}
+}
+
+impl Actor for MeetingSocket {
+	type Context = ws::WebsocketContext<Self>;
+
+	fn started(&mut self, ctx: &mut Self::Context) {
+		self.heartbeat(ctx);
+
+		self.hub
+			.send(Connect {
+				meeting_id: self.meeting_id,
+				recipient: ctx.address().recipient(),
+			})
+			.into_actor(self)
+			.then(|result, socket, ctx| {
+				match result {
+					Ok(connection_id) => socket.connection_id = Some(connection_id),
+					Err(_) => ctx.stop(),
+				}
+				fut::ready(())
+			})
+			.wait(ctx);
+	}
+
+	fn stopping(&mut self, _: &mut Self::Context) -> Running {
+		if let Some(connection_id) = self.connection_id {
+			self.hub.do_send(Disconnect {
+				meeting_id: self.meeting_id,
+				connection_id,
+			});
+		}
+		Running::Stop
+	}
+}
+
+impl Handler<MeetingEvent> for MeetingSocket {
+	type Result = ();
+
+	fn handle(&mut self, event: MeetingEvent, ctx: &mut Self::Context) {
+		if let Ok(json) = serde_json::to_string(&event) {
+			ctx.text(json);
+		}
+
+		match event {
+			MeetingEvent::SessionEnded => {
+				ctx.close(Some(ws::CloseCode::Normal.into()));
+				ctx.stop();
+			}
+			MeetingEvent::ParticipantRemoved { user_id } if user_id == self.user_id => {
+				ctx.close(Some(ws::CloseCode::Policy.into()));
+				ctx.stop();
+			}
+			_ => {}
+		}
+	}
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MeetingSocket {
+	fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+		match msg {
+			Ok(ws::Message::Ping(msg)) => {
+				self.last_heartbeat = Instant::now();
+				ctx.pong(&msg);
+			}
+			Ok(ws::Message::Pong(_)) => {
+				self.last_heartbeat = Instant::now();
+			}
+			Ok(ws::Message::Close(reason)) => {
+				ctx.close(reason);
+				ctx.stop();
+			}
+			Ok(_) => {
+				self.last_heartbeat = Instant::now();
+			}
+			Err(_) => ctx.stop(),
+		}
+	}
+}
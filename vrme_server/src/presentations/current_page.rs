@@ -0,0 +1,197 @@
+//! Handlers for reading and changing which page of the presentation is currently being presented.
+//!
+//! Only the presenter and co-presenters may change the current page; every participant may read
+//! it. Listeners can either subscribe to `slide-changed` events over the meeting session WebSocket,
+//! or long-poll `GET /meetings/{meeting_id}/presentation/current` with the last seen `ETag`.
+
+use actix::Addr;
+use actix_web::http::header;
+use actix_web::web;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::auth::auth_payload::AuthPayload;
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::{MeetingHub, Publish};
+use crate::meetings::participants::{check_not_removed, get_participants};
+use crate::presentations::pages::{get_presentation_state, set_current_page, PresentationState};
+use crate::service_errors::ServiceError;
+
+/// Upper limit on how long a client may ask to wait for the current page to change, in seconds.
+pub const MAX_WAIT_SECONDS: u64 = 30;
+
+/// How often the presentation state is re-checked while long-polling.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Query parameters of `GET /meetings/{meeting_id}/presentation/current`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetCurrentPageQuery {
+	/// If given together with an `If-None-Match` header, wait up to this many seconds (clamped to
+	/// `MAX_WAIT_SECONDS`) for the state to differ from the given `ETag` before responding.
+	pub wait: Option<u64>,
+}
+
+/// Required payload for `PUT /meetings/{meeting_id}/presentation/current`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetCurrentPageRequest {
+	/// Zero-based index of the page to present.
+	pub current_page: i32,
+}
+
+/// Handler for getting the presentation's current page at
+/// `GET /meetings/{meeting_id}/presentation/current`.
+///
+/// ## Success Response
+///
+/// ```json
+/// {
+///     "current_page": 0,
+///     "page_count": 12,
+///     "version": 3
+/// }
+/// ```
+///
+/// The response carries an `ETag` header derived from `version`.
+///
+/// ## Waiting for Changes
+///
+/// If the request carries an `If-None-Match` header matching the current `ETag`, the server
+/// responds with `304 Not Modified`. When additionally `?wait=<seconds>` is supplied, the server
+/// holds the request until the state changes (responding `200 OK` with the new state) or the wait
+/// times out (responding `304 Not Modified`).
+pub async fn handle_get_current_page(
+	req: HttpRequest,
+	pool: web::Data<PersistentConnectionPool>,
+	meeting_id: web::Path<Uuid>,
+	query: web::Query<GetCurrentPageQuery>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
+
+	{
+		let client = pool.get().await?;
+		check_not_removed(&client, &meeting_id, &user_id).await?;
+
+		match get_participants(&client, &meeting_id).await? {
+			Some(participants) if participants.contains(&user_id) => {}
+			_ => {
+				return Err(ServiceError::Forbidden(
+					"Only meeting participants may view the presentation".to_string(),
+				)
+				.into())
+			}
+		}
+	}
+
+	let if_none_match = req
+		.headers()
+		.get(header::IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string);
+	let wait = Duration::from_secs(query.wait.unwrap_or(0).min(MAX_WAIT_SECONDS));
+	let deadline = Instant::now() + wait;
+
+	loop {
+		// The connection is only held for the duration of each check so that long-polling clients
+		// do not exhaust the connection pool.
+		let state = {
+			let client = pool.get().await?;
+			get_presentation_state(&client, &meeting_id)
+				.await?
+				.ok_or_else(|| {
+					ServiceError::NotFound("No presentation found for meeting session".to_string())
+				})?
+		};
+
+		let etag = state.etag();
+
+		if if_none_match.as_deref() != Some(etag.as_str()) {
+			return Ok(make_state_response(&state));
+		}
+
+		if Instant::now() >= deadline {
+			return Ok(HttpResponse::NotModified()
+				.header(header::ETAG, etag)
+				.finish());
+		}
+
+		actix_rt::time::delay_for(POLL_INTERVAL).await;
+	}
+}
+
+/// Handler for the presenter or a co-presenter to change the presentation's current page at
+/// `PUT /meetings/{meeting_id}/presentation/current`.
+///
+/// ## Required Payload
+///
+/// ```json
+/// {
+///     "current_page": 1
+/// }
+/// ```
+///
+/// Connected participants are notified with a `slide-changed` event.
+pub async fn handle_set_current_page(
+	pool: web::Data<PersistentConnectionPool>,
+	hub: web::Data<Addr<MeetingHub>>,
+	meeting_id: web::Path<Uuid>,
+	req: web::Json<SetCurrentPageRequest>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
+	let client = pool.get().await?;
+
+	check_not_removed(&client, &meeting_id, &user_id).await?;
+
+	match get_participants(&client, &meeting_id).await? {
+		Some(participants) if participants.can_present(&user_id) => {}
+		_ => {
+			return Err(ServiceError::Forbidden(
+				"Only the presenter or a co-presenter may change the current page".to_string(),
+			)
+			.into())
+		}
+	}
+
+	if req.current_page < 0 {
+		return Err(
+			ServiceError::BadRequest("`current_page` cannot be negative".to_string()).into(),
+		);
+	}
+
+	let state = match set_current_page(&client, &meeting_id, req.current_page).await? {
+		Some(state) => state,
+		None => {
+			return Err(match get_presentation_state(&client, &meeting_id).await? {
+				Some(state) => ServiceError::UnprocessableEntity(format!(
+					"The presentation only has {} pages",
+					state.page_count
+				)),
+				None => {
+					ServiceError::NotFound("No presentation found for meeting session".to_string())
+				}
+			}
+			.into());
+		}
+	};
+
+	hub.do_send(Publish {
+		meeting_id: *meeting_id,
+		event: MeetingEvent::SlideChanged {
+			current_page: state.current_page,
+			version: state.version,
+		},
+	});
+
+	Ok(make_state_response(&state))
+}
+
+fn make_state_response(state: &PresentationState) -> HttpResponse {
+	HttpResponse::Ok()
+		.header(header::ETAG, state.etag())
+		.json(state)
+}
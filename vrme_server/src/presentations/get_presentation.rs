@@ -1,92 +1,98 @@
 //! Handler for getting presentation file for the given meeting session.
 
-use actix_files as afs;
-use actix_web::http::header::ContentType;
 use actix_web::web;
-use actix_web::Error;
+use actix_web::{Error, HttpRequest, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use deadpool_postgres::Client;
+use bytes::Bytes;
 use uuid::Uuid;
 
 use crate::auth::auth_payload::AuthPayload;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::participants::{check_not_removed, is_participant};
+use crate::presentations::pages::{get_presentation_state, page_key, PresentationState};
 use crate::service_errors::ServiceError;
+use crate::storage::{blob_response, BlobStorage};
 
-/// Handler for getting presentation file. Only meeting participants may get the presentation file.
+/// Handler for getting the page of the presentation that is currently being presented. Only meeting
+/// participants may get the presentation file.
 pub async fn handle_get_presentation(
+	req: HttpRequest,
 	pool: web::Data<PersistentConnectionPool>,
+	storage: web::Data<BlobStorage>,
 	meeting_id: web::Path<Uuid>,
 	auth: BearerAuth,
-) -> Result<afs::NamedFile, Error> {
+) -> Result<HttpResponse, Error> {
 	let client = pool.get().await?;
 	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
 
+	check_not_removed(&client, &meeting_id, &user_id).await?;
+
 	if !is_participant(&client, &meeting_id, &user_id).await? {
-		return Err(ServiceError::Unauthorized(
+		return Err(ServiceError::Forbidden(
 			"Only meeting participants may get presentation file".to_string(),
 		)
 		.into());
 	}
 
-	let file = get_presentation_file(&meeting_id).await?;
+	let state = get_presentation_state(&client, &meeting_id)
+		.await?
+		.ok_or_else(no_presentation_error)?;
 
-	Ok(file
-		.use_last_modified(true)
-		.set_content_type(ContentType::png().0))
+	let page = get_presentation_page(&storage, &meeting_id, &state, state.current_page).await?;
+	Ok(blob_response(&req, page, "image/png"))
 }
 
-async fn get_presentation_file(meeting_id: &Uuid) -> Result<afs::NamedFile, ServiceError> {
-	let raw_path = format!("data/presentations/{}.png", meeting_id);
-	let path = std::path::PathBuf::from(&raw_path);
-
-	web::block(move || -> Result<afs::NamedFile, ServiceError> {
-		if path.exists() {
-			Ok(afs::NamedFile::open(&path)?)
-		} else {
-			Err(ServiceError::NotFound(
-				"No presentation file found for meeting session".to_string(),
-			))
-		}
-	})
-	.await
-	.map_err(|e| e.into())
-}
+/// Handler for getting the zero-based page `{index}` of the presentation at
+/// `GET /meetings/{meeting_id}/presentation/pages/{index}`. Only meeting participants may get the
+/// presentation file.
+pub async fn handle_get_presentation_page(
+	req: HttpRequest,
+	pool: web::Data<PersistentConnectionPool>,
+	storage: web::Data<BlobStorage>,
+	path: web::Path<(Uuid, i32)>,
+	auth: BearerAuth,
+) -> Result<HttpResponse, Error> {
+	let (meeting_id, index) = path.into_inner();
+	let client = pool.get().await?;
+	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
 
-const GET_PARTICIPANTS_QUERY: &str = r#"
-    SELECT
-        presenter,
-        listeners
-    FROM
-        meeting_sessions
-    WHERE
-        meeting_id = $1::UUID
-    ;
-"#;
+	check_not_removed(&client, &meeting_id, &user_id).await?;
 
-async fn get_participants(
-	client: &Client,
-	meeting_id: &Uuid,
-) -> Result<Option<(Uuid, Vec<Uuid>)>, ServiceError> {
-	let statement = client.prepare(GET_PARTICIPANTS_QUERY).await?;
+	if !is_participant(&client, &meeting_id, &user_id).await? {
+		return Err(ServiceError::Forbidden(
+			"Only meeting participants may get presentation file".to_string(),
+		)
+		.into());
+	}
 
-	let rows = client.query(&statement, &[meeting_id]).await?;
+	let state = get_presentation_state(&client, &meeting_id)
+		.await?
+		.ok_or_else(no_presentation_error)?;
 
-	if rows.is_empty() {
-		Ok(None)
-	} else {
-		let (presenter, listeners) = (rows[0].get(0), rows[0].get(1));
-		Ok(Some((presenter, listeners)))
+	if index < 0 || index >= state.page_count {
+		return Err(ServiceError::NotFound(format!(
+			"The presentation only has {} pages",
+			state.page_count
+		))
+		.into());
 	}
+
+	let page = get_presentation_page(&storage, &meeting_id, &state, index).await?;
+	Ok(blob_response(&req, page, "image/png"))
+}
+
+fn no_presentation_error() -> ServiceError {
+	ServiceError::NotFound("No presentation file found for meeting session".to_string())
 }
 
-async fn is_participant(
-	client: &Client,
+async fn get_presentation_page(
+	storage: &BlobStorage,
 	meeting_id: &Uuid,
-	user_id: &Uuid,
-) -> Result<bool, ServiceError> {
-	if let Some((presenter, listeners)) = get_participants(client, meeting_id).await? {
-		Ok(user_id == &presenter || listeners.contains(user_id))
-	} else {
-		Ok(false)
-	}
+	state: &PresentationState,
+	index: i32,
+) -> Result<Bytes, ServiceError> {
+	storage
+		.get(&page_key(meeting_id, state.upload_id, index))
+		.await?
+		.ok_or_else(no_presentation_error)
 }
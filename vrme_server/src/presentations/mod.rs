@@ -1,5 +1,8 @@
 //! Handling uploading, downloading and removal of presentation slides.
 
+pub mod current_page;
 pub mod delete;
 pub mod get_presentation;
+pub mod pages;
+pub mod rasterise;
 pub mod upload;
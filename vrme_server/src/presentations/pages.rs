@@ -0,0 +1,313 @@
+//! Multi-page presentation state: where the pages of a deck are stored and which page is currently
+//! being shown.
+
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::service_errors::ServiceError;
+use crate::storage::deletion_jobs::enqueue_blob_deletions;
+
+/// Upper limit on the number of pages a single presentation may have.
+pub const PRESENTATION_PAGE_LIMIT: usize = 500;
+
+/// The navigation state of the presentation of a meeting session.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PresentationState {
+	/// Zero-based index of the page currently being presented.
+	pub current_page: i32,
+	/// Number of pages in the presentation.
+	pub page_count: i32,
+	/// Incremented whenever the presentation is re-uploaded or the current page changes.
+	pub version: i64,
+	/// Identifies the upload that the pages were stored by. `None` for presentations uploaded
+	/// before pages were stored per upload.
+	#[serde(skip)]
+	pub upload_id: Option<Uuid>,
+}
+
+impl PresentationState {
+	/// Strong `ETag` identifying this state.
+	pub fn etag(&self) -> String {
+		format!("\"{}\"", self.version)
+	}
+
+	fn from_row(row: &Row) -> Self {
+		Self {
+			current_page: row.get(0),
+			page_count: row.get(1),
+			version: row.get(2),
+			upload_id: row.get(3),
+		}
+	}
+}
+
+/// Blob key prefix of the files of one upload of the meeting session's presentation.
+fn upload_prefix(meeting_id: &Uuid, upload_id: Option<Uuid>) -> String {
+	match upload_id {
+		Some(upload_id) => format!("presentations/{}/{}", meeting_id, upload_id),
+		None => format!("presentations/{}", meeting_id),
+	}
+}
+
+/// Blob key of the PNG of the zero-based page `index` of an upload of the meeting session's
+/// presentation.
+pub fn page_key(meeting_id: &Uuid, upload_id: Option<Uuid>, index: i32) -> String {
+	format!("{}/{}.png", upload_prefix(meeting_id, upload_id), index)
+}
+
+/// Blob key of the originally uploaded PDF document of an upload of the meeting session's
+/// presentation, if it was uploaded as a PDF.
+pub fn original_key(meeting_id: &Uuid, upload_id: Option<Uuid>) -> String {
+	format!("{}/original.pdf", upload_prefix(meeting_id, upload_id))
+}
+
+/// Blob keys of the first `page_count` pages and the original document of an upload of the
+/// meeting session's presentation.
+pub fn presentation_keys(
+	meeting_id: &Uuid,
+	upload_id: Option<Uuid>,
+	page_count: i32,
+) -> Vec<String> {
+	(0..page_count)
+		.map(|index| page_key(meeting_id, upload_id, index))
+		.chain(std::iter::once(original_key(meeting_id, upload_id)))
+		.collect()
+}
+
+/// Queue the removal of the first `page_count` pages and the original document of an upload of
+/// the meeting session's presentation, e.g. of an upload that failed halfway.
+pub async fn delete_presentation_files(
+	client: &mut Client,
+	meeting_id: &Uuid,
+	upload_id: Option<Uuid>,
+	page_count: i32,
+) -> Result<(), ServiceError> {
+	let transaction = client.transaction().await?;
+	enqueue_blob_deletions(
+		&transaction,
+		&presentation_keys(meeting_id, upload_id, page_count),
+	)
+	.await?;
+	transaction.commit().await?;
+	Ok(())
+}
+
+const GET_PRESENTATION_STATE_QUERY: &str = r#"
+    SELECT
+        current_page,
+        page_count,
+        version,
+        upload_id
+    FROM presentations
+    WHERE
+        meeting_id = $1::UUID
+    ;
+"#;
+
+/// Get the navigation state of the meeting session's presentation, if one was uploaded.
+pub async fn get_presentation_state(
+	client: &Client,
+	meeting_id: &Uuid,
+) -> Result<Option<PresentationState>, ServiceError> {
+	let statement = client.prepare(GET_PRESENTATION_STATE_QUERY).await?;
+	let rows = client.query(&statement, &[meeting_id]).await?;
+
+	if rows.is_empty() {
+		Ok(None)
+	} else {
+		Ok(Some(PresentationState::from_row(&rows[0])))
+	}
+}
+
+// Serializes uploads to the same meeting session, so that each one sees the upload it replaces.
+const LOCK_MEETING_SESSION_QUERY: &str = r#"
+    SELECT 1
+    FROM meeting_sessions
+    WHERE
+        meeting_id = $1::UUID
+    FOR UPDATE
+    ;
+"#;
+
+const UPSERT_PRESENTATION_QUERY: &str = r#"
+    WITH previous AS (
+        SELECT
+            upload_id,
+            page_count
+        FROM presentations
+        WHERE
+            meeting_id = $1::UUID
+    ),
+    upserted AS (
+        INSERT INTO presentations
+            (meeting_id, page_count, current_page, version, updated_at, upload_id, uploaded_by)
+        VALUES
+            ($1::UUID, $2::INT, 0, 1, $3::TIMESTAMP, $4::UUID, $5::UUID)
+        ON CONFLICT
+            (meeting_id)
+        DO UPDATE SET
+            page_count = EXCLUDED.page_count,
+            current_page = 0,
+            version = presentations.version + 1,
+            updated_at = EXCLUDED.updated_at,
+            upload_id = EXCLUDED.upload_id,
+            uploaded_by = EXCLUDED.uploaded_by
+        RETURNING
+            current_page,
+            page_count,
+            version,
+            upload_id
+    )
+    SELECT
+        upserted.current_page,
+        upserted.page_count,
+        upserted.version,
+        upserted.upload_id,
+        previous.upload_id,
+        previous.page_count
+    FROM upserted
+    LEFT JOIN previous ON TRUE
+    ;
+"#;
+
+/// Record a freshly uploaded presentation of `page_count` pages stored by the upload `upload_id`
+/// of the user `uploaded_by`, resetting the current page to the first page. The files of the
+/// replaced upload are queued for deletion.
+pub async fn upsert_presentation(
+	client: &mut Client,
+	meeting_id: &Uuid,
+	upload_id: &Uuid,
+	uploaded_by: &Uuid,
+	page_count: i32,
+) -> Result<PresentationState, ServiceError> {
+	let transaction = client.transaction().await?;
+
+	let statement = transaction.prepare(LOCK_MEETING_SESSION_QUERY).await?;
+	transaction.execute(&statement, &[meeting_id]).await?;
+
+	let statement = transaction.prepare(UPSERT_PRESENTATION_QUERY).await?;
+	let updated_at = chrono::Utc::now().naive_utc();
+	let row = transaction
+		.query_one(
+			&statement,
+			&[meeting_id, &page_count, &updated_at, upload_id, uploaded_by],
+		)
+		.await?;
+
+	// Only set if a presentation was replaced.
+	if let Some(previous_page_count) = row.get::<_, Option<i32>>(5) {
+		let previous_upload_id: Option<Uuid> = row.get(4);
+		enqueue_blob_deletions(
+			&transaction,
+			&presentation_keys(meeting_id, previous_upload_id, previous_page_count),
+		)
+		.await?;
+	}
+
+	transaction.commit().await?;
+	Ok(PresentationState::from_row(&row))
+}
+
+const GET_UPLOADED_PRESENTATIONS_QUERY: &str = r#"
+    SELECT
+        current_page,
+        page_count,
+        version,
+        upload_id,
+        meeting_id
+    FROM presentations
+    WHERE
+        uploaded_by = $1::UUID
+    ;
+"#;
+
+/// Get the presentations the user uploaded, with the meeting sessions they belong to.
+pub async fn get_uploaded_presentations(
+	client: &Client,
+	user_id: &Uuid,
+) -> Result<Vec<(Uuid, PresentationState)>, ServiceError> {
+	let statement = client.prepare(GET_UPLOADED_PRESENTATIONS_QUERY).await?;
+	let rows = client.query(&statement, &[user_id]).await?;
+
+	Ok(rows
+		.iter()
+		.map(|row| (row.get(4), PresentationState::from_row(row)))
+		.collect())
+}
+
+const SET_CURRENT_PAGE_QUERY: &str = r#"
+    UPDATE presentations
+    SET
+        current_page = $2::INT,
+        version = version + 1,
+        updated_at = $3::TIMESTAMP
+    WHERE
+        meeting_id = $1::UUID AND
+        $2::INT < page_count
+    RETURNING
+        current_page,
+        page_count,
+        version,
+        upload_id
+    ;
+"#;
+
+/// Move the presentation to the zero-based page `index`. Returns `None` if there is no
+/// presentation or it has no such page.
+pub async fn set_current_page(
+	client: &Client,
+	meeting_id: &Uuid,
+	index: i32,
+) -> Result<Option<PresentationState>, ServiceError> {
+	let statement = client.prepare(SET_CURRENT_PAGE_QUERY).await?;
+	let updated_at = chrono::Utc::now().naive_utc();
+	let rows = client
+		.query(&statement, &[meeting_id, &index, &updated_at])
+		.await?;
+
+	if rows.is_empty() {
+		Ok(None)
+	} else {
+		Ok(Some(PresentationState::from_row(&rows[0])))
+	}
+}
+
+const DELETE_PRESENTATION_QUERY: &str = r#"
+    DELETE FROM presentations
+    WHERE
+        meeting_id = $1::UUID
+    RETURNING
+        current_page,
+        page_count,
+        version,
+        upload_id
+    ;
+"#;
+
+/// Forget the meeting session's presentation and queue its files for deletion, returning its last
+/// state if there was one.
+pub async fn delete_presentation(
+	client: &mut Client,
+	meeting_id: &Uuid,
+) -> Result<Option<PresentationState>, ServiceError> {
+	let transaction = client.transaction().await?;
+
+	let statement = transaction.prepare(DELETE_PRESENTATION_QUERY).await?;
+	let state = transaction
+		.query_opt(&statement, &[meeting_id])
+		.await?
+		.map(|row| PresentationState::from_row(&row));
+
+	if let Some(state) = &state {
+		enqueue_blob_deletions(
+			&transaction,
+			&presentation_keys(meeting_id, state.upload_id, state.page_count),
+		)
+		.await?;
+	}
+
+	transaction.commit().await?;
+	Ok(state)
+}
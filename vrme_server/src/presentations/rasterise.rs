@@ -0,0 +1,143 @@
+//! Server-side rasterisation of PDF presentations into one PNG per page.
+//!
+//! Uses [hayro](https://github.com/LaurenzV/hayro), a pure-Rust PDF renderer, so no system
+//! libraries such as `poppler` or `pdfium` are required.
+
+use bytes::Bytes;
+use hayro::hayro_interpret::InterpreterSettings;
+use hayro::hayro_syntax::Pdf;
+use hayro::vello_cpu::color::palette::css::WHITE;
+use hayro::{render, PixmapSettings, RenderCache, RenderSettings};
+use std::sync::Arc;
+
+use crate::presentations::pages::PRESENTATION_PAGE_LIMIT;
+use crate::service_errors::ServiceError;
+
+/// Width in pixels that every rendered page is scaled to, preserving its aspect ratio.
+pub const RENDER_WIDTH: f32 = 1920.0;
+
+/// Upper limit on the height in pixels of a rendered page, so that unusually tall pages do not
+/// blow up memory usage.
+const MAX_RENDER_HEIGHT: f32 = 8192.0;
+
+/// Render every page of the PDF document to a PNG image.
+///
+/// This is CPU-intensive and blocking, so it should be run on the thread pool via `web::block`.
+///
+/// # Errors
+///
+/// - `ServiceError::UnprocessableEntity` if the document cannot be parsed (including encrypted
+///   documents), has no pages or has more than `PRESENTATION_PAGE_LIMIT` pages.
+pub fn rasterise_pdf(data: Bytes) -> Result<Vec<Vec<u8>>, ServiceError> {
+	let pdf = Pdf::new(Arc::new(data)).map_err(|e| {
+		ServiceError::UnprocessableEntity(format!("Unable to read PDF document: {:?}", e))
+	})?;
+
+	let pages = pdf.pages();
+
+	if pages.is_empty() {
+		return Err(ServiceError::UnprocessableEntity(
+			"The PDF document has no pages".to_string(),
+		));
+	}
+
+	if pages.len() > PRESENTATION_PAGE_LIMIT {
+		return Err(ServiceError::UnprocessableEntity(format!(
+			"A presentation may have at most {} pages",
+			PRESENTATION_PAGE_LIMIT
+		)));
+	}
+
+	let cache = RenderCache::new();
+	let interpreter_settings = InterpreterSettings::default();
+	let render_settings = RenderSettings::default();
+
+	pages
+		.iter()
+		.map(|page| {
+			let (width, height) = page.render_dimensions();
+			let scale = (RENDER_WIDTH / width).min(MAX_RENDER_HEIGHT / height);
+
+			let pixmap = render(
+				page,
+				&cache,
+				&interpreter_settings,
+				&render_settings,
+				&PixmapSettings {
+					x_scale: scale,
+					y_scale: scale,
+					bg_color: WHITE,
+				},
+			);
+
+			pixmap.into_png().map_err(|e| {
+				ServiceError::InternalServerError(format!("Failed to encode page as PNG: {}", e))
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Build a minimal PDF document with `page_count` blank A4 pages.
+	fn make_pdf(page_count: usize) -> Vec<u8> {
+		let mut objects = vec![
+			"<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+			format!(
+				"<< /Type /Pages /Kids [{}] /Count {} >>",
+				(0..page_count)
+					.map(|i| format!("{} 0 R", i + 3))
+					.collect::<Vec<_>>()
+					.join(" "),
+				page_count
+			),
+		];
+		for _ in 0..page_count {
+			objects.push("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] >>".to_string());
+		}
+
+		let mut pdf = b"%PDF-1.4\n".to_vec();
+		let mut offsets = Vec::new();
+		for (i, object) in objects.iter().enumerate() {
+			offsets.push(pdf.len());
+			pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).bytes());
+		}
+
+		let xref_offset = pdf.len();
+		pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+		for offset in offsets {
+			pdf.extend(format!("{:010} 00000 n \n", offset).bytes());
+		}
+		pdf.extend(
+			format!(
+				"trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+				objects.len() + 1,
+				xref_offset
+			)
+			.bytes(),
+		);
+
+		pdf
+	}
+
+	#[test]
+	fn test_renders_one_png_per_page() {
+		let pages = rasterise_pdf(Bytes::from(make_pdf(3))).unwrap();
+		assert_eq!(pages.len(), 3);
+
+		for page in pages {
+			assert!(page.starts_with(b"\x89PNG\r\n\x1a\n"));
+		}
+	}
+
+	#[test]
+	fn test_invalid_pdf_is_unprocessable() {
+		let result = rasterise_pdf(Bytes::from_static(b"definitely not a PDF"));
+		assert!(matches!(
+			result.unwrap_err(),
+			ServiceError::UnprocessableEntity(_)
+		));
+	}
+}
@@ -1,97 +1,199 @@
 //! Handler for uploading presentation slides.
 
+use actix::Addr;
 use actix_multipart::{Field, Multipart};
+use actix_web::error::BlockingError;
 use actix_web::http::header::ContentType;
 use actix_web::web;
 use actix_web::{Error, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use bytes::Bytes;
-use deadpool_postgres::Client;
+use bytes::{Bytes, BytesMut};
 use futures::{StreamExt, TryStreamExt};
-use std::fs::File;
-use std::io::Write;
+use log::warn;
 use uuid::Uuid;
 
 use crate::auth::auth_payload::AuthPayload;
 use crate::database::postgresql::PersistentConnectionPool;
+use crate::meetings::events::MeetingEvent;
+use crate::meetings::hub::{MeetingHub, Publish};
+use crate::meetings::participants::{can_present, check_not_removed};
+use crate::presentations::pages::{
+	delete_presentation_files, original_key, page_key, upsert_presentation, PRESENTATION_PAGE_LIMIT,
+};
+use crate::presentations::rasterise::rasterise_pdf;
 use crate::service_errors::ServiceError;
+use crate::storage::BlobStorage;
 
-/// Handler for the presenter to upload presentation slides for the given meeting session.
+/// Upper limit on the size of an uploaded PDF document, in bytes.
+pub const PDF_SIZE_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Upper limit on the size of each uploaded PNG page, in bytes.
+pub const PNG_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// File formats accepted for presentation slides.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SlideFormat {
+	/// A single page, as a PNG image.
+	Png,
+	/// A whole presentation, as a PDF document.
+	Pdf,
+}
+
+/// Handler for the presenter or a co-presenter to upload presentation slides for the given meeting
+/// session.
+///
+/// The `multipart/form-data` payload is either:
+///
+/// - a single `application/pdf` part holding the whole presentation. The original document is kept
+///   and every page is rendered to PNG on the server; or
+/// - one `image/png` part per page of the presentation, in order.
+///
+/// Uploading replaces any previous presentation of the meeting session and resets the current page
+/// to the first page. The rendered pages are served at
+/// `GET /meetings/{meeting_id}/presentation/pages/{index}`.
 pub async fn handle_upload_presentation_slides(
 	pool: web::Data<PersistentConnectionPool>,
+	storage: web::Data<BlobStorage>,
+	hub: web::Data<Addr<MeetingHub>>,
 	auth: BearerAuth,
 	meeting_id: web::Path<Uuid>,
 	mut payload: Multipart,
 ) -> Result<HttpResponse, Error> {
 	let user_id = AuthPayload::from_bearer_auth(&auth)?.uuid;
-	let client = pool.get().await?;
+	let mut client = pool.get().await?;
+
+	check_not_removed(&client, &meeting_id, &user_id).await?;
 
-	if !is_presenter(&client, &meeting_id, &user_id).await? {
-		return Err(ServiceError::Unauthorized(
-			"Cannot modify the presentation slide if you are not the presenter".to_string(),
+	if !can_present(&client, &meeting_id, &user_id).await? {
+		return Err(ServiceError::Forbidden(
+			"Cannot modify the presentation slide if you are not a presenter".to_string(),
 		)
 		.into());
 	}
 
-	if let Ok(Some(mut field)) = payload.try_next().await {
-		// TODO: use PDF instead of images (limited by front-end).
-		check_content_type(&field)?;
-
-		let mut file = create_presentation_file(&meeting_id).await?;
-
-		while let Some(chunk) = field.next().await {
-			let data = chunk?;
-			file = write_to_presentation_file(file, data).await?;
+	// Each upload stores its pages under its own keys, so that a failed upload does not leave a
+	// partially overwritten presentation behind.
+	let upload_id = Uuid::new_v4();
+	let mut page_count = 0;
+
+	if let Err(e) = store_pages(
+		&storage,
+		&meeting_id,
+		&upload_id,
+		&mut payload,
+		&mut page_count,
+	)
+	.await
+	{
+		// Report the failed upload rather than a failure to clean up after it.
+		if let Err(cleanup_error) =
+			delete_presentation_files(&mut client, &meeting_id, Some(upload_id), page_count).await
+		{
+			warn!(
+				"Failed to queue the deletion of a failed upload of meeting session `{}`: {}",
+				&meeting_id, cleanup_error
+			);
 		}
+		return Err(e);
 	}
 
-	Ok(HttpResponse::Created().finish())
-}
+	let state =
+		upsert_presentation(&mut client, &meeting_id, &upload_id, &user_id, page_count).await?;
 
-async fn is_presenter(
-	client: &Client,
-	meeting_id: &Uuid,
-	user_id: &Uuid,
-) -> Result<bool, ServiceError> {
-	if let Some((presenter_id, _)) = get_participants(&client, &meeting_id).await? {
-		Ok(&presenter_id == user_id)
-	} else {
-		Ok(false)
-	}
+	hub.do_send(Publish {
+		meeting_id: *meeting_id,
+		event: MeetingEvent::PresentationUploaded,
+	});
+
+	Ok(HttpResponse::Created().json(state))
 }
 
-const GET_PARTICIPANTS_QUERY: &str = r#"
-    SELECT
-        presenter,
-        listeners
-    FROM
-        meeting_sessions
-    WHERE
-        meeting_id = $1::UUID
-    ;
-"#;
-
-async fn get_participants(
-	client: &Client,
+/// Store the pages in `payload` under the upload `upload_id`, counting stored pages in
+/// `page_count` so that they can be cleaned up should the upload fail.
+async fn store_pages(
+	storage: &BlobStorage,
 	meeting_id: &Uuid,
-) -> Result<Option<(Uuid, Vec<Uuid>)>, ServiceError> {
-	let statement = client.prepare(GET_PARTICIPANTS_QUERY).await?;
+	upload_id: &Uuid,
+	payload: &mut Multipart,
+	page_count: &mut i32,
+) -> Result<(), Error> {
+	let upload_id = Some(*upload_id);
+	let mut uploaded_pdf = false;
+
+	while let Some(mut field) = payload.try_next().await? {
+		let format = check_content_type(&field)?;
+
+		if uploaded_pdf || (format == SlideFormat::Pdf && *page_count > 0) {
+			return Err(ServiceError::BadRequest(
+				"A PDF presentation must be uploaded on its own".to_string(),
+			)
+			.into());
+		}
 
-	let rows = client.query(&statement, &[meeting_id]).await?;
+		if format == SlideFormat::Pdf {
+			let data = read_field(&mut field, PDF_SIZE_LIMIT).await?;
+			storage
+				.put(
+					&original_key(meeting_id, upload_id),
+					data.clone(),
+					"application/pdf",
+				)
+				.await
+				.map_err(ServiceError::from)?;
+
+			for page in rasterise(data).await? {
+				storage
+					.put(
+						&page_key(meeting_id, upload_id, *page_count),
+						Bytes::from(page),
+						"image/png",
+					)
+					.await
+					.map_err(ServiceError::from)?;
+				*page_count += 1;
+			}
+
+			uploaded_pdf = true;
+			continue;
+		}
 
-	if rows.is_empty() {
-		Ok(None)
-	} else {
-		let (presenter, listeners) = (rows[0].get(0), rows[0].get(1));
-		Ok(Some((presenter, listeners)))
+		if *page_count as usize >= PRESENTATION_PAGE_LIMIT {
+			return Err(ServiceError::UnprocessableEntity(format!(
+				"A presentation may have at most {} pages",
+				PRESENTATION_PAGE_LIMIT
+			))
+			.into());
+		}
+
+		let data = read_field(&mut field, PNG_SIZE_LIMIT).await?;
+		storage
+			.put(
+				&page_key(meeting_id, upload_id, *page_count),
+				data,
+				"image/png",
+			)
+			.await
+			.map_err(ServiceError::from)?;
+		*page_count += 1;
 	}
+
+	if *page_count == 0 {
+		return Err(ServiceError::BadRequest(
+			"The presentation must have at least one page".to_string(),
+		)
+		.into());
+	}
+
+	Ok(())
 }
 
-fn check_content_type(field: &Field) -> Result<(), ServiceError> {
+fn check_content_type(field: &Field) -> Result<SlideFormat, ServiceError> {
 	use std::ops::Deref;
 
 	if field.content_type() == ContentType::png().deref() {
-		Ok(())
+		Ok(SlideFormat::Png)
+	} else if field.content_type().essence_str() == "application/pdf" {
+		Ok(SlideFormat::Pdf)
 	} else {
 		Err(ServiceError::UnsupportedMediaType(
 			"The provided file format is not supported for presentation".to_string(),
@@ -99,24 +201,34 @@ fn check_content_type(field: &Field) -> Result<(), ServiceError> {
 	}
 }
 
-async fn create_presentation_file(meeting_id: &Uuid) -> Result<File, ServiceError> {
-	// TODO: support PDF file format (limited by front-end).
-	let file_path = format!("data/presentations/{}.png", meeting_id);
+/// Buffer the whole uploaded file in memory, rejecting files larger than `limit` bytes.
+async fn read_field(field: &mut Field, limit: usize) -> Result<Bytes, Error> {
+	let mut data = BytesMut::new();
 
-	// Delegate blocking fs calls to thread pool.
-	web::block(move || File::create(&file_path))
-		.await
-		.map_err(|_| {
-			ServiceError::InternalServerError(
-				"Encountered error when trying to create presentation file".to_string(),
-			)
-		})
+	while let Some(chunk) = field.next().await {
+		let chunk = chunk?;
+
+		if data.len() + chunk.len() > limit {
+			return Err(ServiceError::UnprocessableEntity(format!(
+				"Each uploaded file may be at most {} MiB",
+				limit / 1024 / 1024
+			))
+			.into());
+		}
+
+		data.extend_from_slice(&chunk);
+	}
+
+	Ok(data.freeze())
 }
 
-async fn write_to_presentation_file(mut file: File, data: Bytes) -> Result<File, ServiceError> {
-	web::block(move || -> Result<File, ServiceError> {
-		file.write_all(&data).map(|_| file).map_err(|e| e.into())
-	})
-	.await
-	.map_err(|e| e.into())
+/// Render the pages of the PDF document on the thread pool.
+async fn rasterise(data: Bytes) -> Result<Vec<Vec<u8>>, ServiceError> {
+	web::block(move || rasterise_pdf(data))
+		.await
+		.map_err(|e| match e {
+			// Keep rejections of the document itself, rather than reporting them as internal errors.
+			BlockingError::Error(e) => e,
+			BlockingError::Canceled => e.into(),
+		})
 }
@@ -5,6 +5,7 @@ use actix_web::{error::ResponseError, HttpResponse};
 use base64::DecodeError;
 use deadpool_postgres::PoolError;
 use derive_more::Display;
+use log::error;
 use rand::Error as RandError;
 use serde::Serialize;
 use serde_json::json;
@@ -13,6 +14,10 @@ use std::convert::{From, Into};
 use tokio_pg_mapper::Error as TPGMError;
 use tokio_postgres::error::Error as TPGError;
 
+use crate::database::error::DatabaseError;
+use crate::mail::error::MailError;
+use crate::storage::error::StorageError;
+
 /// Client-facing service errors.
 #[derive(Debug, Display, Serialize, PartialEq)]
 pub enum ServiceError {
@@ -68,6 +73,12 @@ impl From<PoolError> for ServiceError {
 	}
 }
 
+impl From<DatabaseError> for ServiceError {
+	fn from(e: DatabaseError) -> Self {
+		Self::InternalServerError(e.to_string())
+	}
+}
+
 impl ResponseError for ServiceError {
 	/// Facilitates automatic conversion from `ServiceError` into HTTP error responses in JSON.
 	fn error_response(&self) -> HttpResponse {
@@ -165,3 +176,17 @@ impl From<std::io::Error> for ServiceError {
 		Self::InternalServerError(format!("I/O error: {}", e.to_string()))
 	}
 }
+
+impl From<StorageError> for ServiceError {
+	fn from(e: StorageError) -> Self {
+		// The details may reveal paths or responses of the storage service, so they are only logged.
+		error!("Storage error: {}", e);
+		Self::InternalServerError("Failed to access the blob storage".to_string())
+	}
+}
+
+impl From<MailError> for ServiceError {
+	fn from(e: MailError) -> Self {
+		Self::InternalServerError(format!("Mail error: {}", e))
+	}
+}
@@ -9,11 +9,13 @@
 //! - YAML
 //! - JSON
 
+use crate::types::hashed_password::argon2_hasher;
 use config::{Config, ConfigError, Environment, File};
 use derive_more::Display;
 use log::{debug, error, info, warn};
 use serde::Deserialize;
 use std::env;
+use std::fmt;
 use std::net::IpAddr;
 
 /// Errors encountered when trying to determine the settings for the
@@ -29,6 +31,9 @@ pub enum SettingsError {
 	/// Failed to parse settings file. Contains illegal syntax.
 	#[display(fmt = "invalid syntax: `{}`", "_0")]
 	InvalidSyntax(String),
+	/// Settings are syntactically valid, but a value is not acceptable.
+	#[display(fmt = "invalid value: {}", "_0")]
+	InvalidValue(String),
 	/// Other settings errors.
 	#[display(fmt = "settings error: `{:?}`", "_0")]
 	Other(Box<dyn std::error::Error>),
@@ -53,13 +58,29 @@ pub struct Settings {
 	pub server: ServerSettings,
 	pub auth: AuthSettings,
 	pub rate_limiting: RateLimitingSettings,
+	/// Account lifecycle settings. Defaults to deleting accounts right away.
+	#[serde(default)]
+	pub accounts: AccountsSettings,
+	/// Meeting session settings. Defaults to 50 participants per meeting session.
+	#[serde(default)]
+	pub meetings: MeetingsSettings,
+	/// Where uploaded avatars and presentations are stored. Defaults to the `data/` directory.
+	#[serde(default = "default_storage")]
+	pub storage: StorageSettings,
+	/// How outgoing emails are delivered. Defaults to only logging them.
+	#[serde(default = "default_mail")]
+	pub mail: MailSettings,
 	/// Optional TLS support. Omitting the `tls` section in the configuration file will only run the
 	/// non-TLS server at the desired port specified in `server.port`.
 	pub tls: Option<TlsSettings>,
 }
 
+/// Stands in for secrets when settings are debug-printed, e.g. in the `debug!` log of the final
+/// settings.
+const REDACTED: &str = "<redacted>";
+
 /// Database settings.
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Deserialize, Clone)]
 pub struct DatabaseSettings {
 	pub username: String,
 	pub password: String,
@@ -69,12 +90,34 @@ pub struct DatabaseSettings {
 	/// Max number of database connections to maintain in a connection pool.
 	#[serde(default = "default_pool_size")]
 	pub pool_size: usize,
+	/// Whether pending schema migrations should be applied automatically on startup. When
+	/// disabled, the server only checks that the database schema is not newer than itself.
+	#[serde(default = "default_run_migrations")]
+	pub run_migrations: bool,
+}
+
+impl fmt::Debug for DatabaseSettings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("DatabaseSettings")
+			.field("username", &self.username)
+			.field("password", &REDACTED)
+			.field("hostname", &self.hostname)
+			.field("port", &self.port)
+			.field("database_name", &self.database_name)
+			.field("pool_size", &self.pool_size)
+			.field("run_migrations", &self.run_migrations)
+			.finish()
+	}
 }
 
 fn default_pool_size() -> usize {
 	32
 }
 
+fn default_run_migrations() -> bool {
+	true
+}
+
 /// Logging settings.
 #[derive(Debug, Deserialize, Clone)]
 pub struct LoggingSettings {
@@ -117,7 +160,7 @@ fn default_json_size_limit() -> usize {
 }
 
 /// Authentication settings.
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Deserialize, Clone)]
 pub struct AuthSettings {
 	/// Length of the `auth_token` in bytes.
 	#[serde(default = "default_auth_token_length")]
@@ -140,8 +183,82 @@ pub struct AuthSettings {
 	/// their `auth_token` was explicitly removed.
 	#[serde(default = "default_auth_token_validity_duration")]
 	pub auth_token_validity_duration: u32,
+
+	/// Minimum number of seconds between two writes of an auth session's `last_used` time. Within
+	/// this interval, authenticated requests do not write to the database at all, at the cost of
+	/// `last_used` (and thus expiry) lagging behind by at most this long.
+	#[serde(default = "default_last_used_refresh_interval")]
+	pub last_used_refresh_interval: u64,
+
+	/// Optional absolute lifetime of auth sessions in hours. When set, an `auth_token` expires this
+	/// many hours after login no matter how recently it was used.
+	#[serde(default)]
+	pub max_session_lifetime: Option<u32>,
+
+	/// Secret key that `auth_token`s are hashed with before being stored. Must be at least
+	/// `MIN_TOKEN_SECRET_LEN` bytes long, and should be generated randomly, e.g. with
+	/// `openssl rand -base64 48`. Changing it invalidates every auth session.
+	pub token_secret: String,
+
+	/// Parameters of the `Argon2id` algorithm that passwords are hashed with. Existing password
+	/// hashes computed with other parameters are recomputed upon the next successful login of
+	/// their user, so the cost can be raised at any time.
+	#[serde(default = "default_argon2")]
+	pub argon2: Argon2Settings,
+
+	/// Whether users must verify their email address before they can login. Regardless of this
+	/// setting, a verification email is sent upon registration.
+	#[serde(default)]
+	pub require_email_verification: bool,
+
+	/// Email address of the account to make the first admin. Upon startup, the account is made an
+	/// admin if it has verified its email address and no admin exists yet, since admins cannot be
+	/// appointed at `PUT /admin/accounts/{uuid}/role`.
+	#[serde(default)]
+	pub initial_admin_email: Option<String>,
+}
+
+impl fmt::Debug for AuthSettings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("AuthSettings")
+			.field("auth_token_length", &self.auth_token_length)
+			.field(
+				"auth_token_validity_duration",
+				&self.auth_token_validity_duration,
+			)
+			.field(
+				"last_used_refresh_interval",
+				&self.last_used_refresh_interval,
+			)
+			.field("max_session_lifetime", &self.max_session_lifetime)
+			.field("token_secret", &REDACTED)
+			.field("argon2", &self.argon2)
+			.field(
+				"require_email_verification",
+				&self.require_email_verification,
+			)
+			.field("initial_admin_email", &self.initial_admin_email)
+			.finish()
+	}
 }
 
+/// `Argon2id` password hashing parameters.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Argon2Settings {
+	/// Memory size in KiB.
+	#[serde(default = "default_argon2_memory_cost")]
+	pub memory_cost: u32,
+	/// Number of iterations.
+	#[serde(default = "default_argon2_time_cost")]
+	pub time_cost: u32,
+	/// Degree of parallelism.
+	#[serde(default = "default_argon2_parallelism")]
+	pub parallelism: u32,
+}
+
+/// Minimum length of `auth.token_secret` in bytes.
+pub const MIN_TOKEN_SECRET_LEN: usize = 32;
+
 /// Default `auth_token` length in bytes. The `auth_token` should be strong enough (partly by
 /// being long enough, and also from being generated by cryptographically-strong CSPRNG that at
 /// periodically samples from true random entropy sources.
@@ -154,6 +271,33 @@ fn default_auth_token_validity_duration() -> u32 {
 	chrono::Duration::days(30).num_hours() as u32
 }
 
+/// Default `last_used` refresh interval is once per minute.
+fn default_last_used_refresh_interval() -> u64 {
+	60
+}
+
+/// Default `Argon2id` parameters are the minimum recommended by OWASP: 19 MiB of memory, 2
+/// iterations and a parallelism of 1.
+fn default_argon2() -> Argon2Settings {
+	Argon2Settings {
+		memory_cost: default_argon2_memory_cost(),
+		time_cost: default_argon2_time_cost(),
+		parallelism: default_argon2_parallelism(),
+	}
+}
+
+fn default_argon2_memory_cost() -> u32 {
+	19 * 1024
+}
+
+fn default_argon2_time_cost() -> u32 {
+	2
+}
+
+fn default_argon2_parallelism() -> u32 {
+	1
+}
+
 /// Rate limiting settings.
 #[derive(Debug, Deserialize, Clone)]
 pub struct RateLimitingSettings {
@@ -163,6 +307,15 @@ pub struct RateLimitingSettings {
 	/// How many requests are permitted for each IP address in the duration.
 	#[serde(default = "default_max_requests")]
 	pub max_requests: usize,
+	/// How many `GET /accounts/uuid` lookups are permitted for each IP address in the duration, on
+	/// top of `max_requests`.
+	#[serde(default = "default_uuid_lookup_max_requests")]
+	pub uuid_lookup_max_requests: usize,
+	/// Addresses of reverse proxies in front of the server. Only requests from these addresses may
+	/// name the client's IP address in an `X-Forwarded-For` header; otherwise anyone could dodge
+	/// rate limits by making up addresses.
+	#[serde(default)]
+	pub trusted_proxies: Vec<IpAddr>,
 }
 
 /// Default cooldown duration is `60` seconds.
@@ -175,6 +328,203 @@ fn default_max_requests() -> usize {
 	100
 }
 
+/// Default max uuid lookups per duration is `10`.
+fn default_uuid_lookup_max_requests() -> usize {
+	10
+}
+
+/// Account lifecycle settings.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AccountsSettings {
+	/// How many hours a deleted account is kept before it is purged, during which the user can
+	/// restore it at `POST /account/restore`. Accounts are purged right away if unset.
+	pub deletion_grace_period: Option<u32>,
+}
+
+/// Meeting session settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MeetingsSettings {
+	/// How many users, the presenter included, may take part in a meeting session whose presenter
+	/// did not set its own limit.
+	#[serde(default = "default_max_participants")]
+	pub default_max_participants: u32,
+}
+
+impl Default for MeetingsSettings {
+	fn default() -> Self {
+		Self {
+			default_max_participants: default_max_participants(),
+		}
+	}
+}
+
+fn default_max_participants() -> u32 {
+	50
+}
+
+/// Blob storage settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageSettings {
+	/// Which backend to store blobs in.
+	#[serde(default = "default_storage_backend")]
+	pub backend: StorageBackend,
+	/// Directory that blobs are stored under when using the `local` backend.
+	#[serde(default = "default_storage_root")]
+	pub root: String,
+	/// Bucket that blobs are stored in when using the `s3` backend.
+	pub s3: Option<S3Settings>,
+}
+
+/// Blob storage backends.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+	/// Files on the local filesystem. Each server instance has its own files, unless `root` is a
+	/// shared mount.
+	Local,
+	/// An S3-compatible object store shared by all server instances.
+	S3,
+}
+
+/// S3-compatible object store settings.
+#[derive(Deserialize, Clone)]
+pub struct S3Settings {
+	/// Base URL of the object store, e.g. `https://s3.eu-west-1.amazonaws.com` or
+	/// `http://127.0.0.1:9000` for a local MinIO.
+	pub endpoint: String,
+	/// Region the bucket is in. MinIO accepts any region, by default `us-east-1`.
+	#[serde(default = "default_s3_region")]
+	pub region: String,
+	/// Name of the bucket. The bucket must already exist.
+	pub bucket: String,
+	pub access_key_id: String,
+	pub secret_access_key: String,
+}
+
+impl fmt::Debug for S3Settings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("S3Settings")
+			.field("endpoint", &self.endpoint)
+			.field("region", &self.region)
+			.field("bucket", &self.bucket)
+			.field("access_key_id", &self.access_key_id)
+			.field("secret_access_key", &REDACTED)
+			.finish()
+	}
+}
+
+fn default_storage() -> StorageSettings {
+	StorageSettings {
+		backend: default_storage_backend(),
+		root: default_storage_root(),
+		s3: None,
+	}
+}
+
+fn default_storage_backend() -> StorageBackend {
+	StorageBackend::Local
+}
+
+fn default_storage_root() -> String {
+	"data".to_string()
+}
+
+fn default_s3_region() -> String {
+	"us-east-1".to_string()
+}
+
+/// Mail settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MailSettings {
+	/// Which backend delivers emails.
+	#[serde(default = "default_mail_backend")]
+	pub backend: MailBackend,
+	/// Sender of outgoing emails, e.g. `"VRME <noreply@example.com>"`.
+	#[serde(default = "default_mail_from")]
+	pub from: String,
+	/// Directory that emails are written to when using the `file` backend.
+	#[serde(default = "default_mail_directory")]
+	pub directory: String,
+	/// SMTP server that emails are submitted to when using the `smtp` backend.
+	pub smtp: Option<SmtpSettings>,
+}
+
+/// Mailer backends.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailBackend {
+	/// Only log emails. Nothing is delivered.
+	Log,
+	/// Write each email to a `.eml` file in `directory`. Nothing is delivered.
+	File,
+	/// Submit emails to an SMTP server.
+	Smtp,
+}
+
+/// SMTP server settings.
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+	/// Hostname of the SMTP server.
+	pub host: String,
+	/// Port of the SMTP server. Defaults to `465` with `tls`, `587` with `starttls` and `25`
+	/// otherwise.
+	pub port: Option<u16>,
+	/// How the connection to the SMTP server is secured.
+	#[serde(default = "default_smtp_security")]
+	pub security: SmtpSecurity,
+	pub username: Option<String>,
+	pub password: Option<String>,
+}
+
+impl fmt::Debug for SmtpSettings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SmtpSettings")
+			.field("host", &self.host)
+			.field("port", &self.port)
+			.field("security", &self.security)
+			.field("username", &self.username)
+			.field("password", &self.password.as_ref().map(|_| REDACTED))
+			.finish()
+	}
+}
+
+/// SMTP connection security.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+	/// TLS from the start of the connection (implicit TLS).
+	Tls,
+	/// Upgrade the connection to TLS with `STARTTLS`, which the server must support.
+	StartTls,
+	/// Plaintext. Only suitable for a local mail catcher.
+	None,
+}
+
+fn default_mail() -> MailSettings {
+	MailSettings {
+		backend: default_mail_backend(),
+		from: default_mail_from(),
+		directory: default_mail_directory(),
+		smtp: None,
+	}
+}
+
+fn default_mail_backend() -> MailBackend {
+	MailBackend::Log
+}
+
+fn default_mail_from() -> String {
+	"VRME <noreply@localhost>".to_string()
+}
+
+fn default_mail_directory() -> String {
+	"mail".to_string()
+}
+
+fn default_smtp_security() -> SmtpSecurity {
+	SmtpSecurity::StartTls
+}
+
 /// TLS settings.
 #[derive(Debug, Deserialize, Clone)]
 pub struct TlsSettings {
@@ -217,7 +567,6 @@ impl Settings {
 		cfg.merge(File::with_name("config/default"))?;
 
 		info!("Read config from `config/default`");
-		debug!("Provided config from `config/default`:\n {:#?}", &cfg);
 
 		// Then, we add `RUN_MODE`-determined configuration. Defaults to
 		// `development` mode, which takes configuration file at
@@ -227,12 +576,10 @@ impl Settings {
 				"development" => {
 					cfg.merge(File::with_name("config/default").required(false))?;
 					info!("Reading config from `config/development`");
-					debug!("Provided config from `config/development`:\n {:#?}", &cfg);
 				}
 				"production" => {
 					cfg.merge(File::with_name("config/production").required(false))?;
 					info!("Reading config from `config/production`");
-					debug!("Provided config from `config/production`:\n {:#?}", &cfg);
 				}
 				other => {
 					warn!(
@@ -258,7 +605,33 @@ impl Settings {
 
 		info!("Mixed in configuration from environment variables");
 
-		match cfg.try_into() {
+		match cfg.try_into::<Settings>() {
+			Ok(validated_settings)
+				if validated_settings.auth.token_secret.len() < MIN_TOKEN_SECRET_LEN =>
+			{
+				error!("Settings are invalid!");
+				Err(SettingsError::InvalidValue(format!(
+					"`auth.token_secret` must be at least {} bytes long",
+					MIN_TOKEN_SECRET_LEN
+				)))
+			}
+			Ok(validated_settings) if argon2_hasher(&validated_settings.auth.argon2).is_err() => {
+				error!("Settings are invalid!");
+				Err(SettingsError::InvalidValue(
+					"`auth.argon2` parameters are out of range".to_string(),
+				))
+			}
+			// Stored as `INTEGER` in the database.
+			Ok(validated_settings)
+				if validated_settings.meetings.default_max_participants == 0
+					|| validated_settings.meetings.default_max_participants > i32::MAX as u32 =>
+			{
+				error!("Settings are invalid!");
+				Err(SettingsError::InvalidValue(format!(
+					"`meetings.default_max_participants` must be between 1 and {}",
+					i32::MAX
+				)))
+			}
 			Ok(validated_settings) => {
 				info!("Settings are validated");
 				debug!("Final settings:\n {:#?}", &validated_settings);
@@ -0,0 +1,162 @@
+//! Retryable blob deletions.
+//!
+//! Blobs that must go away together with database rows, e.g. the avatar of a deleted account, are
+//! not removed from the blob store right away. Instead, a deletion job is recorded in the same
+//! transaction that deletes the rows, and `run_blob_deletion_jobs` later removes the blobs,
+//! retrying failed removals with exponential backoff. A blob is thus never left behind because the
+//! blob store was unavailable, nor removed while the rows referring to it are kept by a rollback.
+
+use crate::database::postgresql::PersistentConnectionPool;
+use crate::service_errors::ServiceError;
+use crate::storage::BlobStorage;
+use chrono::{Duration, NaiveDateTime, Utc};
+use log::warn;
+use tokio_postgres::Transaction;
+
+/// How many jobs are claimed at once by `run_blob_deletion_jobs`.
+pub const BLOB_DELETION_BATCH_SIZE: i64 = 100;
+
+/// Upper limit on the delay between two attempts of a job, in minutes.
+pub const BLOB_DELETION_MAX_BACKOFF: i64 = 24 * 60;
+
+/// How long in minutes a claimed job is not handed out again, in case the server stops while
+/// running it.
+const BLOB_DELETION_LEASE: i64 = 10;
+
+const ENQUEUE_BLOB_DELETIONS_QUERY: &str = r#"
+    INSERT INTO blob_deletion_jobs
+        (blob_key, next_attempt_at, created_at)
+    SELECT
+        blob_key, $2::TIMESTAMP, $2::TIMESTAMP
+    FROM unnest($1::TEXT[]) AS blob_key
+    ;
+"#;
+
+/// Record jobs to remove the blobs under `keys` as part of `transaction`.
+pub async fn enqueue_blob_deletions(
+	transaction: &Transaction<'_>,
+	keys: &[String],
+) -> Result<(), ServiceError> {
+	if keys.is_empty() {
+		return Ok(());
+	}
+
+	let now = Utc::now().naive_utc();
+
+	let statement = transaction.prepare(ENQUEUE_BLOB_DELETIONS_QUERY).await?;
+	transaction.execute(&statement, &[&keys, &now]).await?;
+	Ok(())
+}
+
+// Claiming a job pushes its next attempt back by the lease, so that concurrent runners, e.g. on
+// other server instances, skip it.
+const CLAIM_JOBS_QUERY: &str = r#"
+    UPDATE blob_deletion_jobs
+    SET
+        attempts = attempts + 1,
+        next_attempt_at = $2::TIMESTAMP
+    WHERE
+        job_id IN (
+            SELECT job_id
+            FROM blob_deletion_jobs
+            WHERE
+                next_attempt_at <= $1::TIMESTAMP
+            ORDER BY
+                next_attempt_at
+            LIMIT $3::BIGINT
+            FOR UPDATE SKIP LOCKED
+        )
+    RETURNING
+        job_id,
+        blob_key,
+        attempts
+    ;
+"#;
+
+const COMPLETE_JOB_QUERY: &str = r#"
+    DELETE FROM blob_deletion_jobs
+    WHERE
+        job_id = $1::BIGINT
+    ;
+"#;
+
+const RETRY_JOB_QUERY: &str = r#"
+    UPDATE blob_deletion_jobs
+    SET
+        next_attempt_at = $2::TIMESTAMP,
+        last_error = $3::TEXT
+    WHERE
+        job_id = $1::BIGINT
+    ;
+"#;
+
+/// Run the deletion jobs which are due. Returns how many blobs were removed.
+pub async fn run_blob_deletion_jobs(
+	pool: &PersistentConnectionPool,
+	storage: &BlobStorage,
+) -> Result<usize, ServiceError> {
+	let client = pool.get().await?;
+	let now = Utc::now().naive_utc();
+	let lease_end = now + Duration::minutes(BLOB_DELETION_LEASE);
+
+	let statement = client.prepare(CLAIM_JOBS_QUERY).await?;
+	let jobs = client
+		.query(&statement, &[&now, &lease_end, &BLOB_DELETION_BATCH_SIZE])
+		.await?;
+
+	let mut removed = 0;
+
+	for job in jobs {
+		let (job_id, key, attempts): (i64, String, i32) = (job.get(0), job.get(1), job.get(2));
+
+		match storage.delete(&key).await {
+			Ok(()) => {
+				let statement = client.prepare(COMPLETE_JOB_QUERY).await?;
+				client.execute(&statement, &[&job_id]).await?;
+				removed += 1;
+			}
+			Err(e) => {
+				warn!(
+					"Failed to remove blob `{}` (attempt {}): {}",
+					&key, attempts, e
+				);
+
+				let next_attempt_at = next_attempt_at(&now, attempts);
+				let statement = client.prepare(RETRY_JOB_QUERY).await?;
+				client
+					.execute(&statement, &[&job_id, &next_attempt_at, &e.to_string()])
+					.await?;
+			}
+		}
+	}
+
+	Ok(removed)
+}
+
+/// When to retry a job after its `attempts`-th attempt failed: after 1, 2, 4, ... minutes, up to
+/// `BLOB_DELETION_MAX_BACKOFF`.
+fn next_attempt_at(now: &NaiveDateTime, attempts: i32) -> NaiveDateTime {
+	let backoff = 2i64
+		.checked_pow(attempts.saturating_sub(1) as u32)
+		.unwrap_or(i64::MAX)
+		.min(BLOB_DELETION_MAX_BACKOFF);
+
+	*now + Duration::minutes(backoff)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_next_attempt_at_backs_off_exponentially() {
+		let now = Utc::now().naive_utc();
+		let delay = |attempts| (next_attempt_at(&now, attempts) - now).num_minutes();
+
+		assert_eq!(delay(1), 1);
+		assert_eq!(delay(2), 2);
+		assert_eq!(delay(5), 16);
+		assert_eq!(delay(20), BLOB_DELETION_MAX_BACKOFF);
+		assert_eq!(delay(100), BLOB_DELETION_MAX_BACKOFF);
+	}
+}
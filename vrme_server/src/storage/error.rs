@@ -0,0 +1,41 @@
+//! Errors emitted by blob storage backends.
+
+use actix_web::error::BlockingError;
+use derive_more::Display;
+use std::convert::From;
+
+/// Errors related to blob storage.
+#[derive(Debug, Display)]
+pub enum StorageError {
+	/// The storage settings are incomplete or invalid.
+	#[display(fmt = "invalid storage settings: {}", "_0")]
+	InvalidSettings(String),
+	/// The blob key is not a valid relative `/`-separated path.
+	#[display(fmt = "invalid blob key: `{}`", "_0")]
+	InvalidKey(String),
+	/// Failed to read or write a blob on the local filesystem.
+	#[display(fmt = "I/O error: {}", "_0")]
+	IOError(std::io::Error),
+	/// The remote storage service failed or rejected the request.
+	#[display(fmt = "storage backend error: {}", "_0")]
+	BackendError(String),
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+	fn from(e: std::io::Error) -> Self {
+		Self::IOError(e)
+	}
+}
+
+impl From<BlockingError<StorageError>> for StorageError {
+	fn from(e: BlockingError<StorageError>) -> Self {
+		match e {
+			BlockingError::Error(e) => e,
+			BlockingError::Canceled => {
+				Self::BackendError("blocking storage operation was canceled".to_string())
+			}
+		}
+	}
+}
@@ -0,0 +1,99 @@
+//! Blob storage on the local filesystem.
+
+use actix_web::web;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::error::StorageError;
+use super::{validate_key, BlobStore};
+
+/// Stores each blob as a file at `{root}/{key}`.
+///
+/// Only suitable for a single server instance, unless `root` is on a filesystem shared between all
+/// instances.
+pub struct LocalBlobStore {
+	root: PathBuf,
+}
+
+impl LocalBlobStore {
+	/// Create a store rooted at the directory `root`, relative to the working directory unless
+	/// absolute.
+	pub fn new<P: AsRef<Path>>(root: P) -> Self {
+		Self {
+			root: root.as_ref().to_path_buf(),
+		}
+	}
+
+	fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+		validate_key(key)?;
+		Ok(self.root.join(key))
+	}
+}
+
+#[async_trait(?Send)]
+impl BlobStore for LocalBlobStore {
+	async fn put(&self, key: &str, data: Bytes, _content_type: &str) -> Result<(), StorageError> {
+		let path = self.path(key)?;
+
+		// Delegate blocking fs calls to thread pool.
+		web::block(move || -> Result<(), StorageError> {
+			if let Some(parent) = path.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+
+			// Write to a temporary file first and then rename it over the target, so that readers
+			// never observe a partially written blob.
+			let temp_path = path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+			std::fs::write(&temp_path, &data)?;
+			std::fs::rename(&temp_path, &path).map_err(|e| {
+				let _ = std::fs::remove_file(&temp_path);
+				e.into()
+			})
+		})
+		.await
+		.map_err(|e| e.into())
+	}
+
+	async fn get(&self, key: &str) -> Result<Option<Bytes>, StorageError> {
+		let path = self.path(key)?;
+
+		web::block(move || -> Result<Option<Bytes>, StorageError> {
+			match std::fs::read(&path) {
+				Ok(data) => Ok(Some(Bytes::from(data))),
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+				Err(e) => Err(e.into()),
+			}
+		})
+		.await
+		.map_err(|e| e.into())
+	}
+
+	async fn delete(&self, key: &str) -> Result<(), StorageError> {
+		let path = self.path(key)?;
+		let root = self.root.clone();
+
+		web::block(move || -> Result<(), StorageError> {
+			match std::fs::remove_file(&path) {
+				Ok(()) => {}
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+				Err(e) => return Err(e.into()),
+			}
+
+			// Clean up directories left empty, e.g. of a deleted presentation. Removing a
+			// non-empty directory fails, which stops the clean up.
+			let mut dir = path.parent();
+			while let Some(d) = dir {
+				if d == root || std::fs::remove_dir(d).is_err() {
+					break;
+				}
+				dir = d.parent();
+			}
+
+			Ok(())
+		})
+		.await
+		.map_err(|e| e.into())
+	}
+}
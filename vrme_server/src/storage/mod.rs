@@ -0,0 +1,147 @@
+//! Blob storage for uploaded files such as avatars and presentations.
+//!
+//! Handlers never touch the filesystem directly; instead they go through the `BlobStore` trait so
+//! that every server instance behind a load balancer sees the same files when a shared backend
+//! such as S3 is configured.
+//!
+//! Blobs are addressed by `/`-separated keys, e.g. `avatars/{uuid}.png`.
+
+pub mod deletion_jobs;
+pub mod error;
+pub mod local;
+pub mod s3;
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::info;
+use ring::digest;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::settings::{StorageBackend, StorageSettings};
+use error::StorageError;
+use local::LocalBlobStore;
+use s3::S3BlobStore;
+
+/// A key-value store for binary blobs.
+///
+/// Futures returned by the store need not be `Send`, as they are always awaited on the worker
+/// thread of the request being handled.
+#[async_trait(?Send)]
+pub trait BlobStore: Send + Sync {
+	/// Store `data` under `key`, replacing any blob previously stored under it.
+	async fn put(&self, key: &str, data: Bytes, content_type: &str) -> Result<(), StorageError>;
+
+	/// Get the blob stored under `key`, if any.
+	async fn get(&self, key: &str) -> Result<Option<Bytes>, StorageError>;
+
+	/// Remove the blob stored under `key`. Removing a blob that does not exist is not an error.
+	async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// The blob store shared by all workers, as configured in `Settings.storage`.
+#[derive(Clone)]
+pub struct BlobStorage(Arc<dyn BlobStore>);
+
+impl BlobStorage {
+	/// Initialize the blob store backend selected by `storage_settings`.
+	pub fn from_settings(storage_settings: &StorageSettings) -> Result<Self, StorageError> {
+		match storage_settings.backend {
+			StorageBackend::Local => {
+				info!(
+					"Using local blob storage rooted at `{}`",
+					&storage_settings.root
+				);
+				Ok(Self(Arc::new(LocalBlobStore::new(&storage_settings.root))))
+			}
+			StorageBackend::S3 => {
+				let s3_settings = storage_settings.s3.as_ref().ok_or_else(|| {
+					StorageError::InvalidSettings(
+						"`storage.s3` is required when using the `s3` backend".to_string(),
+					)
+				})?;
+				info!(
+					"Using S3 blob storage in bucket `{}` at `{}`",
+					&s3_settings.bucket, &s3_settings.endpoint
+				);
+				Ok(Self(Arc::new(S3BlobStore::from_settings(s3_settings)?)))
+			}
+		}
+	}
+}
+
+impl Deref for BlobStorage {
+	type Target = dyn BlobStore;
+
+	fn deref(&self) -> &Self::Target {
+		&*self.0
+	}
+}
+
+/// Check that `key` is a relative `/`-separated path which cannot escape the store, e.g. through
+/// `..` segments.
+pub fn validate_key(key: &str) -> Result<(), StorageError> {
+	let is_valid = !key.is_empty()
+		&& key.split('/').all(|segment| {
+			!segment.is_empty()
+				&& segment != "."
+				&& segment != ".."
+				&& segment
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+		});
+
+	if is_valid {
+		Ok(())
+	} else {
+		Err(StorageError::InvalidKey(key.to_string()))
+	}
+}
+
+/// Build a response serving the blob `data`, with an `ETag` derived from its content so that
+/// clients can revalidate their cached copy with `If-None-Match`.
+pub fn blob_response(req: &HttpRequest, data: Bytes, content_type: &str) -> HttpResponse {
+	let hash = digest::digest(&digest::SHA256, &data);
+	let etag = format!(
+		"\"{}\"",
+		hash.as_ref()
+			.iter()
+			.map(|b| format!("{:02x}", b))
+			.collect::<String>()
+	);
+
+	let if_none_match = req
+		.headers()
+		.get(header::IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok());
+
+	if if_none_match == Some(etag.as_str()) {
+		HttpResponse::NotModified()
+			.header(header::ETAG, etag)
+			.finish()
+	} else {
+		HttpResponse::Ok()
+			.header(header::ETAG, etag)
+			.content_type(content_type)
+			.body(data)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_validate_key() {
+		assert!(validate_key("avatars/default.png").is_ok());
+		assert!(validate_key("presentations/0a1b/2c3d/0.png").is_ok());
+
+		assert!(validate_key("").is_err());
+		assert!(validate_key("/etc/passwd").is_err());
+		assert!(validate_key("avatars/../../etc/passwd").is_err());
+		assert!(validate_key("avatars//default.png").is_err());
+		assert!(validate_key("avatars/").is_err());
+	}
+}
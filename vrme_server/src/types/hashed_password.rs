@@ -1,58 +1,175 @@
 //! Hashed password newtype.
+//!
+//! Passwords are stored as self-describing strings in the
+//! [PHC string format](https://github.com/P-H-C/phc-string-format/blob/master/phc-sf-spec.md),
+//! which record the algorithm and its parameters next to the salt and hash, e.g.
+//!
+//! ```text
+//! $argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>
+//! ```
+//!
+//! New passwords are hashed with `Argon2id` using the parameters in `Settings.auth.argon2`.
+//! Passwords hashed before that are stored as `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>` and
+//! are still accepted; they are rehashed with `Argon2id` upon the next successful login.
 
 use crate::service_errors::ServiceError;
+use crate::settings::Argon2Settings;
+use actix_web::error::BlockingError;
 use actix_web::web;
+use argon2::password_hash::{PasswordHash, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use rand;
 use ring::pbkdf2;
-use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::num::NonZeroU32;
 
-/// Hashed password type.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct HashedPassword {
-	/// Number of iterations of `PBKDF2`.
-	pub iteration_count: u32,
-	/// 16-byte `salt` generated from a secure random number generator.
-	pub salt: Vec<u8>,
-	/// 32-bytes of the output of `PBKDF2`.
-	pub hash: Vec<u8>,
-}
+/// Hashed password type, holding a PHC string.
+#[derive(Debug, Clone)]
+pub struct HashedPassword(String);
 
 impl HashedPassword {
 	/// Construct a new `HashedPassword`.
 	///
-	/// Uses the `PBKDF2` algorithm:
-	///
-	/// - See `PBKDF2_ALGORITHM` and `PBKDF2_ITERATIONS` for the exact algorithm and number of
-	///   iterations used to compute the final hash.
-	pub async fn new(client_hash: &[u8; HASHED_PASSWORD_LEN]) -> Result<Self, ServiceError> {
+	/// Uses the `Argon2id` algorithm with a fresh random `SALT_LEN`-byte salt and the parameters
+	/// given in `argon2_settings`.
+	pub async fn new(
+		client_hash: &[u8; HASHED_PASSWORD_LEN],
+		argon2_settings: &Argon2Settings,
+	) -> Result<Self, ServiceError> {
 		use rand::RngCore;
 
-		let client_hash = client_hash.clone();
+		let client_hash = *client_hash;
+		let argon2_settings = argon2_settings.clone();
 
 		web::block(move || -> Result<Self, ServiceError> {
 			let mut salt = [0u8; SALT_LEN];
 			rand::thread_rng().try_fill_bytes(&mut salt)?;
+			let salt = SaltString::encode_b64(&salt).map_err(password_hash_error)?;
 
-			let mut hash = [0u8; HASHED_PASSWORD_LEN];
-
-			pbkdf2::derive(
-				PBKDF2_ALGORITHM,
-				NonZeroU32::new(PBKDF2_ITERATIONS as u32).unwrap(),
-				&salt,
-				&client_hash,
-				&mut hash,
-			);
+			let phc = argon2_hasher(&argon2_settings)?
+				.hash_password(&client_hash, &salt)
+				.map_err(password_hash_error)?
+				.to_string();
 
-			Ok(Self {
-				iteration_count: PBKDF2_ITERATIONS as u32,
-				salt: salt.to_vec(),
-				hash: hash.to_vec(),
-			})
+			Ok(Self(phc))
 		})
 		.await
 		.map_err(|e| e.into())
 	}
+
+	/// Wrap a PHC string as read from the database.
+	pub fn from_phc_string(phc: String) -> Self {
+		Self(phc)
+	}
+
+	/// The PHC string to store in the database.
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+
+	/// Check the client-side hashed password against this hash, using the algorithm and
+	/// parameters recorded in the hash.
+	pub async fn verify(
+		&self,
+		client_hash: &[u8; HASHED_PASSWORD_LEN],
+	) -> Result<bool, ServiceError> {
+		let client_hash = *client_hash;
+		let phc = self.0.clone();
+
+		web::block(move || verify_phc(&phc, &client_hash))
+			.await
+			.map_err(|e| match e {
+				BlockingError::Error(e) => e,
+				BlockingError::Canceled => {
+					ServiceError::InternalServerError("Unexpectedly cancelled".to_string())
+				}
+			})
+	}
+
+	/// Whether this hash was computed with another algorithm or with other parameters than the
+	/// ones currently configured, and should thus be recomputed once the password is known.
+	pub fn needs_rehash(&self, argon2_settings: &Argon2Settings) -> bool {
+		let hash = match PasswordHash::new(&self.0) {
+			Ok(hash) => hash,
+			Err(_) => return true,
+		};
+
+		let is_current_algorithm = hash.algorithm == Algorithm::Argon2id.ident()
+			&& hash.version == Some(Version::V0x13.into());
+
+		match Params::try_from(&hash) {
+			Ok(params) if is_current_algorithm => {
+				params.m_cost() != argon2_settings.memory_cost
+					|| params.t_cost() != argon2_settings.time_cost
+					|| params.p_cost() != argon2_settings.parallelism
+			}
+			_ => true,
+		}
+	}
+}
+
+/// Build the `Argon2id` hasher with the configured parameters.
+pub fn argon2_hasher(argon2_settings: &Argon2Settings) -> Result<Argon2<'static>, ServiceError> {
+	let params = Params::new(
+		argon2_settings.memory_cost,
+		argon2_settings.time_cost,
+		argon2_settings.parallelism,
+		Some(HASHED_PASSWORD_LEN),
+	)
+	.map_err(|e| {
+		ServiceError::InternalServerError(format!("Invalid `Argon2` parameters: {}", e))
+	})?;
+
+	Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+fn verify_phc(phc: &str, client_hash: &[u8]) -> Result<bool, ServiceError> {
+	let hash = PasswordHash::new(phc).map_err(password_hash_error)?;
+
+	if hash.algorithm.as_str() == PBKDF2_IDENT {
+		return verify_pbkdf2(&hash, client_hash);
+	}
+
+	// `Argon2` takes the variant, version and parameters to verify with from `hash`.
+	match Argon2::default().verify_password(client_hash, &hash) {
+		Ok(()) => Ok(true),
+		Err(argon2::password_hash::Error::Password) => Ok(false),
+		Err(e) => Err(password_hash_error(e)),
+	}
+}
+
+/// Verify a legacy `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>` password hash.
+fn verify_pbkdf2(hash: &PasswordHash, client_hash: &[u8]) -> Result<bool, ServiceError> {
+	let invalid =
+		|| ServiceError::InternalServerError("Invalid `PBKDF2` password hash".to_string());
+
+	let iterations = hash
+		.params
+		.get_decimal("i")
+		.and_then(NonZeroU32::new)
+		.ok_or_else(invalid)?;
+
+	let mut salt_buf = [0u8; 64];
+	let salt = hash
+		.salt
+		.ok_or_else(invalid)?
+		.decode_b64(&mut salt_buf)
+		.map_err(password_hash_error)?;
+
+	let previously_derived = hash.hash.ok_or_else(invalid)?;
+
+	Ok(pbkdf2::verify(
+		PBKDF2_ALGORITHM,
+		iterations,
+		salt,
+		client_hash,
+		previously_derived.as_bytes(),
+	)
+	.is_ok())
+}
+
+fn password_hash_error(e: argon2::password_hash::Error) -> ServiceError {
+	ServiceError::InternalServerError(format!("Password hashing error: {}", e))
 }
 
 /// Length of the extracted hashed password in bytes. This is for the raw hashed password bytes that
@@ -62,22 +179,73 @@ pub const HASHED_PASSWORD_LEN: usize = 32;
 /// Length of the randomly generated salt in bytes.
 pub const SALT_LEN: usize = 16;
 
-/// We use the `PBKDF2` algorithm to compute the password hash in a secure fashion, with the core
-/// hash function being `HMAC-SHA-256`.
+/// PHC algorithm identifier of legacy password hashes.
+const PBKDF2_IDENT: &str = "pbkdf2-sha256";
+
+/// Legacy password hashes were computed with the `PBKDF2` algorithm, with the core hash function
+/// being `HMAC-SHA-256`.
 ///
 /// # References
 ///
 /// - [`ring::pbkdf2`](https://briansmith.org/rustdoc/ring/pbkdf2/index.html).
-pub static PBKDF2_ALGORITHM: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+static PBKDF2_ALGORITHM: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
 
-/// Number of `PBKDF2` iterations to perform. The more iterations, the more difficult to try to
-/// compute a rainbow table to try to reverse the hash. However, more iterations also take more CPU
-/// cycles to compute.
-///
-/// We default to use `100_000` iterations which gives a reasonably large number of iterations to
-/// hinder a possible attacker.
-///
-/// As computing power increases, the number of iterations should also be increased to ensure the
-/// difficulity (in computing time) for an potential adversay to try to compute a rainbow table for
-/// the `PBKDF2` + `HMAC-SHA-256` combination.
-pub const PBKDF2_ITERATIONS: usize = 100_000;
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_settings() -> Argon2Settings {
+		Argon2Settings {
+			memory_cost: 64,
+			time_cost: 1,
+			parallelism: 1,
+		}
+	}
+
+	#[test]
+	fn test_argon2_roundtrip() {
+		let settings = test_settings();
+		let salt = SaltString::encode_b64(&[1u8; SALT_LEN]).unwrap();
+		let hashed = HashedPassword::from_phc_string(
+			argon2_hasher(&settings)
+				.unwrap()
+				.hash_password(&[7u8; HASHED_PASSWORD_LEN], &salt)
+				.unwrap()
+				.to_string(),
+		);
+
+		assert!(hashed.as_str().starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+		assert!(verify_phc(hashed.as_str(), &[7u8; HASHED_PASSWORD_LEN]).unwrap());
+		assert!(!verify_phc(hashed.as_str(), &[8u8; HASHED_PASSWORD_LEN]).unwrap());
+		assert!(!hashed.needs_rehash(&settings));
+		assert!(hashed.needs_rehash(&Argon2Settings {
+			time_cost: 2,
+			..settings
+		}));
+	}
+
+	#[test]
+	fn test_verify_legacy_pbkdf2() {
+		let salt = [1u8; SALT_LEN];
+		let client_hash = [7u8; HASHED_PASSWORD_LEN];
+		let mut derived = [0u8; HASHED_PASSWORD_LEN];
+		pbkdf2::derive(
+			PBKDF2_ALGORITHM,
+			NonZeroU32::new(1_000).unwrap(),
+			&salt,
+			&client_hash,
+			&mut derived,
+		);
+
+		// Same encoding as produced by the migration from the legacy columns.
+		let hashed = HashedPassword::from_phc_string(format!(
+			"$pbkdf2-sha256$i=1000${}${}",
+			base64::encode(salt).trim_end_matches('='),
+			base64::encode(derived).trim_end_matches('=')
+		));
+
+		assert!(verify_phc(hashed.as_str(), &client_hash).unwrap());
+		assert!(!verify_phc(hashed.as_str(), &[8u8; HASHED_PASSWORD_LEN]).unwrap());
+		assert!(hashed.needs_rehash(&test_settings()));
+	}
+}